@@ -2,13 +2,560 @@
 
 extern crate proc_macro;
 
+mod stable;
+
 use quote::{quote, quote_spanned};
-use syn::{parse_macro_input, Fields, DeriveInput, Data};
+use syn::{parse_macro_input, Fields, DeriveInput, Data, Meta, NestedMeta, Lit};
 use syn::spanned::Spanned;
 use deriving::{has_attribute, normalized_fields, is_fields_variant_unnamed, normalized_variant_match_cause};
 
 use proc_macro::TokenStream;
+use stable::ContainerKind;
+
+/// Parsed `#[bm(...)]` attributes of a field.
+#[derive(Default)]
+struct FieldAttrs {
+    compact: bool,
+    skip: bool,
+    max_len: Option<u64>,
+    with: Option<syn::Path>,
+}
+
+/// Item type of a `Vec` field.
+fn vec_item(ty: &syn::Type) -> Option<&syn::Type> {
+    generic_item(ty, "Vec")
+}
+
+/// Single generic argument of a type named `wrapper`, like `Vec<T>`.
+fn generic_item<'a>(ty: &'a syn::Type, wrapper: &str) -> Option<&'a syn::Type> {
+    let segment = match ty {
+        syn::Type::Path(ref path) => path.path.segments.last()?.into_value(),
+        _ => return None,
+    };
+
+    if segment.ident != wrapper {
+        return None
+    }
+
+    match segment.arguments {
+        syn::PathArguments::AngleBracketed(ref args) if args.args.len() == 1 => {
+            match args.args.first()?.into_value() {
+                syn::GenericArgument::Type(ref ty) => Some(ty),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Parse `#[bm(...)]` attributes of a field, panicking on misuse.
+fn field_attrs(field: &syn::Field) -> FieldAttrs {
+    let mut attrs = FieldAttrs::default();
+
+    for attr in &field.attrs {
+        let list = match attr.parse_meta() {
+            Ok(Meta::List(ref list)) if list.ident == "bm" => list.clone(),
+            Ok(Meta::Word(ref ident)) | Ok(Meta::NameValue(syn::MetaNameValue { ref ident, .. }))
+                if ident == "bm" => panic!("bm attribute must be a list, like #[bm(compact)]"),
+            _ => continue,
+        };
+
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::Word(ref ident)) if ident == "compact" => attrs.compact = true,
+                NestedMeta::Meta(Meta::Word(ref ident)) if ident == "skip" => attrs.skip = true,
+                NestedMeta::Meta(Meta::NameValue(ref value)) if value.ident == "max_len" => {
+                    match value.lit {
+                        Lit::Int(ref lit) => attrs.max_len = Some(lit.value()),
+                        _ => panic!("max_len must be an integer, like #[bm(max_len = 16)]"),
+                    }
+                },
+                NestedMeta::Meta(Meta::NameValue(ref value)) if value.ident == "with" => {
+                    match value.lit {
+                        Lit::Str(ref lit) => attrs.with = Some(
+                            lit.parse().unwrap_or_else(|_| panic!("with must be a path, like #[bm(with = \"path\")]"))
+                        ),
+                        _ => panic!("with must be a string, like #[bm(with = \"path\")]"),
+                    }
+                },
+                _ => panic!("Unknown bm attribute, expected compact, skip, max_len or with"),
+            }
+        }
+    }
+
+    if attrs.skip && (attrs.compact || attrs.max_len.is_some() || attrs.with.is_some()) {
+        panic!("skip cannot be combined with other bm attributes")
+    }
+    if attrs.with.is_some() && (attrs.compact || attrs.max_len.is_some()) {
+        panic!("with cannot be combined with compact or max_len")
+    }
+    if attrs.max_len.is_some() && vec_item(&field.ty).is_none() {
+        panic!("max_len is only supported on Vec fields, use MaxVec for other types")
+    }
+
+    attrs
+}
+
+/// Normalized fields with their parsed attributes.
+fn attributed_fields(fields: &Fields) -> Vec<(proc_macro2::TokenStream, syn::Field, FieldAttrs)> {
+    normalized_fields(fields)
+        .into_iter()
+        .map(|(name, field)| {
+            let attrs = field_attrs(&field);
+            (name, field, attrs)
+        })
+        .collect()
+}
+
+/// Panic if any variant field is skipped, which is only supported for
+/// struct fields.
+fn check_variant_fields(fields: &Fields) {
+    if attributed_fields(fields).iter().any(|f| f.2.skip) {
+        panic!("skip is only supported on struct fields")
+    }
+}
+
+/// Expression converting a field reference into a tree root.
+fn into_tree_expr(
+    field: &syn::Field,
+    attrs: &FieldAttrs,
+    value: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let span = field.span();
+
+    if let Some(ref path) = attrs.with {
+        quote_spanned! { span => #path::into_tree(#value, db) }
+    } else if let Some(max_len) = attrs.max_len {
+        let max_len = max_len as usize;
+        if attrs.compact {
+            quote_spanned! { span => bm_le::IntoCompactListTree::into_compact_list_tree(
+                &bm_le::ElementalVariableVecRef(&(#value)[..]), db, Some(#max_len)
+            ) }
+        } else {
+            quote_spanned! { span => bm_le::IntoCompositeListTree::into_composite_list_tree(
+                &bm_le::ElementalVariableVecRef(&(#value)[..]), db, Some(#max_len)
+            ) }
+        }
+    } else if attrs.compact {
+        quote_spanned! { span => bm_le::IntoTree::into_tree(&bm_le::CompactRef(#value), db) }
+    } else {
+        quote_spanned! { span => bm_le::IntoTree::into_tree(#value, db) }
+    }
+}
+
+/// Where clause for converting a field into a tree root.
+fn into_tree_where(field: &syn::Field, attrs: &FieldAttrs) -> Option<proc_macro2::TokenStream> {
+    let ty = &field.ty;
+    let span = field.span();
+
+    if attrs.skip || attrs.with.is_some() {
+        None
+    } else if attrs.max_len.is_some() {
+        let item = vec_item(ty).expect("Checked in field_attrs; qed");
+        if attrs.compact {
+            Some(quote_spanned! { span => for<'a> bm_le::ElementalVariableVecRef<'a, #item>: bm_le::IntoCompactListTree })
+        } else {
+            Some(quote_spanned! { span => for<'a> bm_le::ElementalVariableVecRef<'a, #item>: bm_le::IntoCompositeListTree })
+        }
+    } else if attrs.compact {
+        Some(quote_spanned! { span => for<'a> bm_le::CompactRef<'a, #ty>: bm_le::IntoTree })
+    } else {
+        Some(quote_spanned! { span => #ty: bm_le::IntoTree })
+    }
+}
+
+/// Expression converting a field reference into a tree root, caching
+/// the result as child `i` of the cache.
+fn into_tree_cached_expr(
+    field: &syn::Field,
+    attrs: &FieldAttrs,
+    value: proc_macro2::TokenStream,
+    i: usize,
+) -> proc_macro2::TokenStream {
+    let span = field.span();
+
+    if attrs.with.is_some() || attrs.max_len.is_some() {
+        // Custom conversions are not cached.
+        into_tree_expr(field, attrs, value)
+    } else if attrs.compact {
+        quote_spanned! { span => bm_le::IntoTreeCached::into_tree_cached(
+            &bm_le::CompactRef(#value), db, cache.child(#i)
+        ) }
+    } else {
+        quote_spanned! { span => bm_le::IntoTreeCached::into_tree_cached(
+            #value, db, cache.child(#i)
+        ) }
+    }
+}
+
+/// Expression converting a tree root into a field value.
+fn from_tree_expr(
+    field: &syn::Field,
+    attrs: &FieldAttrs,
+    root: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let ty = &field.ty;
+    let span = field.span();
+
+    if attrs.skip {
+        quote_spanned! { span => Default::default() }
+    } else if let Some(ref path) = attrs.with {
+        quote_spanned! { span => #path::from_tree(#root, db)? }
+    } else if let Some(max_len) = attrs.max_len {
+        let max_len = max_len as usize;
+        let item = vec_item(ty).expect("Checked in field_attrs; qed");
+        if attrs.compact {
+            quote_spanned! { span => <bm_le::ElementalVariableVec<#item> as bm_le::FromCompactListTree>
+                             ::from_compact_list_tree(#root, db, Some(#max_len))?.0 }
+        } else {
+            quote_spanned! { span => <bm_le::ElementalVariableVec<#item> as bm_le::FromCompositeListTree>
+                             ::from_composite_list_tree(#root, db, Some(#max_len))?.0 }
+        }
+    } else if attrs.compact {
+        quote_spanned! { span => <bm_le::Compact<#ty> as bm_le::FromTree>::from_tree(#root, db)?.0 }
+    } else {
+        quote_spanned! { span => <#ty as bm_le::FromTree>::from_tree(#root, db)? }
+    }
+}
+
+/// Where clause for converting a tree root into a field value.
+fn from_tree_where(field: &syn::Field, attrs: &FieldAttrs) -> Option<proc_macro2::TokenStream> {
+    let ty = &field.ty;
+    let span = field.span();
+
+    if attrs.skip {
+        Some(quote_spanned! { span => #ty: Default })
+    } else if attrs.with.is_some() {
+        None
+    } else if attrs.max_len.is_some() {
+        let item = vec_item(ty).expect("Checked in field_attrs; qed");
+        if attrs.compact {
+            Some(quote_spanned! { span => bm_le::ElementalVariableVec<#item>: bm_le::FromCompactListTree })
+        } else {
+            Some(quote_spanned! { span => bm_le::ElementalVariableVec<#item>: bm_le::FromCompositeListTree })
+        }
+    } else if attrs.compact {
+        Some(quote_spanned! { span => bm_le::Compact<#ty>: bm_le::FromTree })
+    } else {
+        Some(quote_spanned! { span => #ty: bm_le::FromTree })
+    }
+}
+
+/// Inherent constants describing the tree layout of a struct.
+fn layout_impl(input: &DeriveInput, fields: &Fields) -> proc_macro2::TokenStream {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = attributed_fields(fields)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, f)| !f.2.skip)
+        .collect::<Vec<_>>();
+    let fields_count = fields.len();
+    let first_index = fields_count.next_power_of_two();
+    let depth = first_index.trailing_zeros() as usize;
+
+    let (consts, matches): (Vec<_>, Vec<_>) = fields
+        .iter()
+        .enumerate()
+        .map(|(i, (position, f))| {
+            let field_name = match f.1.ident {
+                Some(ref ident) => ident.to_string().trim_start_matches("r#").to_string(),
+                None => format!("field_{}", position),
+            };
+            let const_name = syn::Ident::new(
+                &format!("{}_INDEX", field_name.to_uppercase()), f.1.span()
+            );
+            let doc = format!("Generalized index of field `{}`.", field_name);
+            let zero_index = first_index + i - 1;
+
+            (quote_spanned! { f.1.span() =>
+                #[doc = #doc]
+                pub const #const_name: bm_le::Index = bm_le::Index::from_zero(#zero_index);
+            },
+             quote! {
+                 #field_name => Some(Self::#const_name),
+             })
+        }).unzip();
+
+    quote! {
+        #[allow(dead_code)]
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Number of fields in the tree.
+            pub const FIELDS_COUNT: usize = #fields_count;
+            /// Depth of the container tree, not including field subtrees.
+            pub const TREE_DEPTH: usize = #depth;
 
+            #(#consts)*
+
+            /// Generalized index of a field by its name.
+            pub fn field_index(name: &str) -> Option<bm_le::Index> {
+                match name {
+                    #(#matches)*
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Layout constants of a struct. They are emitted from `IntoTree`, or
+/// from `FromTree` instead if the struct is marked `#[bm(layout)]`, so
+/// that deriving both emits them once.
+fn container_layout(input: &DeriveInput, kind: &ContainerKind, from_tree: bool) -> proc_macro2::TokenStream {
+    let marked = has_attribute("bm", &input.attrs, "layout");
+    let data = match input.data {
+        Data::Struct(ref data) => data,
+        _ if marked => panic!("layout is only supported on structs"),
+        _ => return quote! { },
+    };
+
+    if marked != from_tree {
+        quote! { }
+    } else if matches!(kind, ContainerKind::Container) {
+        layout_impl(input, &data.fields)
+    } else {
+        stable::layout(input, kind)
+    }
+}
+
+/// Expression decoding the next container item into a field value.
+fn decode_expr(field: &syn::Field, attrs: &FieldAttrs) -> proc_macro2::TokenStream {
+    let ty = &field.ty;
+    let span = field.span();
+
+    let value = if attrs.skip {
+        quote_spanned! { span => Default::default() }
+    } else if attrs.compact {
+        quote_spanned! { span => items.decode_next::<bm_le::Compact<#ty>>()?.0 }
+    } else {
+        quote_spanned! { span => items.decode_next::<#ty>()? }
+    };
+
+    match attrs.max_len {
+        Some(max_len) => {
+            let max_len = max_len as usize;
+            let item = vec_item(ty).expect("Checked in field_attrs; qed");
+            if attrs.compact {
+                quote_spanned! { span => items.decode_next_compact_list::<#item>(#max_len)? }
+            } else {
+                quote_spanned! { span => items.decode_next_list::<#item>(#max_len)? }
+            }
+        },
+        None => value,
+    }
+}
+
+/// Maximum number of options of a ssz union.
+const MAX_UNION_OPTIONS: usize = 128;
+
+/// Whether the enum is marked as a ssz union, checking that variants
+/// satisfy union constraints.
+fn is_union(input: &DeriveInput) -> bool {
+    if !has_attribute("bm", &input.attrs, "union") {
+        return false
+    }
+
+    let data = match input.data {
+        Data::Enum(ref data) => data,
+        _ => panic!("Union must be an enum"),
+    };
+
+    if data.variants.is_empty() || data.variants.len() > MAX_UNION_OPTIONS {
+        panic!("Union must have between 1 and {} options", MAX_UNION_OPTIONS)
+    }
+
+    for (i, variant) in data.variants.iter().enumerate() {
+        match variant.fields {
+            Fields::Unit if i == 0 && data.variants.len() > 1 => (),
+            Fields::Unit => panic!("Only the first option of a union with multiple options can be None"),
+            Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => (),
+            _ => panic!("Union option must have exactly one unnamed field"),
+        }
+    }
+
+    true
+}
+
+/// Derive `IntoTree` for a struct or enum.
+///
+/// Fields accept `#[bm(compact)]`, `#[bm(max_len = N)]` on `Vec` fields,
+/// `#[bm(skip)]` on struct fields that aren't part of the tree, and
+/// `#[bm(with = "path")]` to convert through `path::into_tree` and
+/// `path::from_tree`.
+///
+/// Structs also get layout constants: `FIELDS_COUNT`, `TREE_DEPTH`, a
+/// `<FIELD>_INDEX` generalized index for each field, and `field_index`
+/// to look one up by name. Indices of nested containers compose with
+/// `Index::concat`. Structs marked `#[bm(layout)]` get them from
+/// `FromTree` instead, for types that don't derive `IntoTree`.
+///
+/// Structs marked `#[bm(stable_container = N)]` or `#[bm(profile =
+/// "Base")]` are merkleized as EIP-7495 stable containers and profiles,
+/// and also implement `StableContainer` or `Profile`.
+///
+/// ```
+/// use bm_le::{IntoTree, FromTree};
+///
+/// #[derive(IntoTree, FromTree)]
+/// struct Foo {
+///     #[bm(max_len = 4)]
+///     a: Vec<u64>,
+///     #[bm(skip)]
+///     b: u64,
+/// }
+/// ```
+///
+/// `max_len` is only supported on `Vec` fields:
+///
+/// ```compile_fail
+/// use bm_le::IntoTree;
+///
+/// #[derive(IntoTree)]
+/// struct Foo {
+///     #[bm(max_len = 4)]
+///     a: u64,
+/// }
+/// ```
+///
+/// `skip` cannot be combined with other attributes:
+///
+/// ```compile_fail
+/// use bm_le::IntoTree;
+///
+/// #[derive(IntoTree)]
+/// struct Foo {
+///     #[bm(skip, compact)]
+///     a: Vec<u64>,
+/// }
+/// ```
+///
+/// A `with` module provides both `into_tree` and `from_tree`:
+///
+/// ```
+/// use bm_le::{IntoTree, FromTree};
+///
+/// mod foo {
+///     use bm_le::{IntoTree, FromTree, WriteBackend, ReadBackend, ValueOf, Error,
+///                 CompatibleConstruct};
+///
+///     pub fn into_tree<DB: WriteBackend>(
+///         value: &Vec<u64>,
+///         db: &mut DB
+///     ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+///         DB::Construct: CompatibleConstruct,
+///     {
+///         value.into_tree(db)
+///     }
+///
+///     pub fn from_tree<DB: ReadBackend>(
+///         root: &ValueOf<DB::Construct>,
+///         db: &mut DB
+///     ) -> Result<Vec<u64>, Error<DB::Error>> where
+///         DB::Construct: CompatibleConstruct,
+///     {
+///         Vec::<u64>::from_tree(root, db)
+///     }
+/// }
+///
+/// #[derive(IntoTree, FromTree)]
+/// struct Foo {
+///     #[bm(with = "foo")]
+///     a: Vec<u64>,
+/// }
+/// ```
+///
+/// `with` cannot be combined with `compact` or `max_len`:
+///
+/// ```compile_fail
+/// use bm_le::{IntoTree, FromTree};
+///
+/// mod foo {
+///     use bm_le::{IntoTree, FromTree, WriteBackend, ReadBackend, ValueOf, Error,
+///                 CompatibleConstruct};
+///
+///     pub fn into_tree<DB: WriteBackend>(
+///         value: &Vec<u64>,
+///         db: &mut DB
+///     ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+///         DB::Construct: CompatibleConstruct,
+///     {
+///         value.into_tree(db)
+///     }
+///
+///     pub fn from_tree<DB: ReadBackend>(
+///         root: &ValueOf<DB::Construct>,
+///         db: &mut DB
+///     ) -> Result<Vec<u64>, Error<DB::Error>> where
+///         DB::Construct: CompatibleConstruct,
+///     {
+///         Vec::<u64>::from_tree(root, db)
+///     }
+/// }
+///
+/// #[derive(IntoTree, FromTree)]
+/// struct Foo {
+///     #[bm(with = "foo", max_len = 4)]
+///     a: Vec<u64>,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use bm_le::{IntoTree, FromTree};
+///
+/// mod foo {
+///     use bm_le::{IntoTree, FromTree, WriteBackend, ReadBackend, ValueOf, Error,
+///                 CompatibleConstruct};
+///
+///     pub fn into_tree<DB: WriteBackend>(
+///         value: &Vec<u64>,
+///         db: &mut DB
+///     ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+///         DB::Construct: CompatibleConstruct,
+///     {
+///         value.into_tree(db)
+///     }
+///
+///     pub fn from_tree<DB: ReadBackend>(
+///         root: &ValueOf<DB::Construct>,
+///         db: &mut DB
+///     ) -> Result<Vec<u64>, Error<DB::Error>> where
+///         DB::Construct: CompatibleConstruct,
+///     {
+///         Vec::<u64>::from_tree(root, db)
+///     }
+/// }
+///
+/// #[derive(IntoTree, FromTree)]
+/// struct Foo {
+///     #[bm(with = "foo", compact)]
+///     a: Vec<u64>,
+/// }
+/// ```
+///
+/// Unknown attributes are rejected:
+///
+/// ```compile_fail
+/// use bm_le::IntoTree;
+///
+/// #[derive(IntoTree)]
+/// struct Foo {
+///     #[bm(unknown)]
+///     a: u64,
+/// }
+/// ```
+///
+/// Including on the struct itself:
+///
+/// ```compile_fail
+/// use bm_le::IntoTree;
+///
+/// #[derive(IntoTree)]
+/// #[bm(unknown)]
+/// struct Foo {
+///     a: u64,
+/// }
+/// ```
 #[proc_macro_derive(IntoTree, attributes(bm))]
 pub fn into_tree_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
@@ -17,42 +564,165 @@ pub fn into_tree_derive(input: TokenStream) -> TokenStream {
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     let build_fields = |fs, prefix| {
-        let where_fields = normalized_fields(fs)
+        let fields = attributed_fields(fs)
+            .into_iter()
+            .filter(|f| !f.2.skip)
+            .collect::<Vec<_>>();
+
+        let where_fields = fields
+            .iter()
+            .filter_map(|f| into_tree_where(&f.1, &f.2))
+            .collect::<Vec<_>>();
+
+        let fields = fields
             .iter()
             .map(|f| {
+                let ident = &f.0;
+                let expr = into_tree_expr(&f.1, &f.2, quote! { #prefix #ident });
+
+                quote_spanned! { f.1.span() => {
+                    vector.push(#expr?);
+                } }
+            }).collect::<Vec<_>>();
+
+        let inner = quote! {
+            let mut vector = Vec::new();
+            #(#fields)*
+            bm_le::utils::vector_tree(&vector, db, None)
+        };
+
+        (where_fields, inner)
+    };
+
+    let kind = stable::container_kind(&input);
+    let layout = container_layout(&input, &kind, false);
+    let (where_fields, inner) = match input.data {
+        Data::Struct(_) if !matches!(kind, ContainerKind::Container) => {
+            stable::into_tree(&input, &kind)
+        },
+        Data::Struct(ref data) => {
+            build_fields(&data.fields, quote! { &self. })
+        },
+        Data::Enum(ref data) => {
+            let union = is_union(&input);
+            let mut where_fields = Vec::new();
+
+            let variants = data.variants
+                .iter()
+                .enumerate()
+                .map(|(i, variant)| {
+                    check_variant_fields(&variant.fields);
+                    let (mut variant_where_fields, variant_inner) = build_fields(
+                        &variant.fields,
+                        if is_fields_variant_unnamed(variant) { quote! { variant. } } else { quote! {} }
+                    );
+
+                    where_fields.append(&mut variant_where_fields);
+
+                    let inner = if union {
+                        let value_root = match attributed_fields(&variant.fields).first() {
+                            Some(f) => {
+                                let expr = into_tree_expr(&f.1, &f.2, quote! { variant.0 });
+                                quote! { #expr? }
+                            },
+                            None => quote! { bm_le::Value::End(Default::default()) },
+                        };
+
+                        quote! {
+                            let value_root: bm_le::ValueOf<DB::Construct> = #value_root;
+                            bm_le::utils::mix_in_type(&value_root, db, #i)
+                        }
+                    } else {
+                        quote! {
+                            let vector_root = { #variant_inner }?;
+                            bm_le::utils::mix_in_type(&vector_root, db, #i)
+                        }
+                    };
+
+                    normalized_variant_match_cause(&input.ident, variant, inner)
+                }).collect::<Vec<_>>();
+
+            (where_fields, quote! {
+                match self {
+                    #(#variants)*
+                }
+            })
+        },
+        Data::Union(_) => panic!("Unsupported"),
+    };
+
+    let expanded = quote! {
+        impl #impl_generics bm_le::IntoTree for #name #ty_generics where
+            #where_clause
+            #(#where_fields),*
+        {
+            fn into_tree<DB: bm_le::WriteBackend>(
+                &self,
+                db: &mut DB
+            ) -> Result<bm_le::ValueOf<DB::Construct>, bm_le::Error<DB::Error>> where
+                DB::Construct: bm_le::CompatibleConstruct
+            {
+                #inner
+            }
+        }
+
+        #layout
+    };
+
+    proc_macro::TokenStream::from(expanded)
+}
+
+#[proc_macro_derive(IntoTreeCached, attributes(bm))]
+pub fn into_tree_cached_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    if !matches!(stable::container_kind(&input), ContainerKind::Container) {
+        panic!("IntoTreeCached is not supported for stable containers and profiles")
+    }
+
+    let build_fields = |fs, prefix| {
+        let fields = attributed_fields(fs)
+            .into_iter()
+            .filter(|f| !f.2.skip)
+            .collect::<Vec<_>>();
+
+        let where_fields = fields
+            .iter()
+            .filter_map(|f| {
                 let ty = &f.1.ty;
 
-                if has_attribute("bm", &f.1.attrs, "compact") {
-                    quote_spanned! {
-		        f.1.span() => for<'a> bm_le::CompactRef<'a, #ty>: bm_le::IntoTree
-	            }
+                if f.2.with.is_some() || f.2.max_len.is_some() {
+                    into_tree_where(&f.1, &f.2)
+                } else if f.2.compact {
+                    Some(quote_spanned! {
+                        f.1.span() => for<'a> bm_le::CompactRef<'a, #ty>: bm_le::IntoTreeCached
+                    })
                 } else {
-	            quote_spanned! {
-		        f.1.span() => #ty: bm_le::IntoTree
-	            }
+                    Some(quote_spanned! {
+                        f.1.span() => #ty: bm_le::IntoTreeCached
+                    })
                 }
-	    }).collect::<Vec<_>>();
+            }).collect::<Vec<_>>();
 
-        let fields = normalized_fields(fs)
+        let fields = fields
             .iter()
-            .map(|f| {
+            .enumerate()
+            .map(|(i, f)| {
                 let ident = &f.0;
+                let expr = into_tree_cached_expr(&f.1, &f.2, quote! { #prefix #ident }, i);
 
-                if has_attribute("bm", &f.1.attrs, "compact") {
-                    quote_spanned! { f.1.span() => {
-                        vector.push(bm_le::IntoTree::into_tree(&bm_le::CompactRef(#prefix #ident), db)?);
-                    } }
-                } else {
-                    quote_spanned! { f.1.span() => {
-                        vector.push(bm_le::IntoTree::into_tree(#prefix #ident, db)?);
-                    } }
-                }
+                quote_spanned! { f.1.span() => {
+                    vector.push(#expr?);
+                } }
             }).collect::<Vec<_>>();
 
         let inner = quote! {
             let mut vector = Vec::new();
             #(#fields)*
-            bm_le::utils::vector_tree(&vector, db, None)
+            cache.vector_tree(&vector, db, None)
         };
 
         (where_fields, inner)
@@ -65,12 +735,14 @@ pub fn into_tree_derive(input: TokenStream) -> TokenStream {
             (where_fields, inner)
         },
         Data::Enum(ref data) => {
+            let union = is_union(&input);
             let mut where_fields = Vec::new();
 
             let variants = data.variants
                 .iter()
                 .enumerate()
                 .map(|(i, variant)| {
+                    check_variant_fields(&variant.fields);
                     let (mut variant_where_fields, variant_inner) = build_fields(
                         &variant.fields,
                         if is_fields_variant_unnamed(variant) { quote! { variant. } } else { quote! {} }
@@ -78,10 +750,27 @@ pub fn into_tree_derive(input: TokenStream) -> TokenStream {
 
                     where_fields.append(&mut variant_where_fields);
 
-                    normalized_variant_match_cause(&input.ident, &variant, quote! {
-                        let vector_root = { #variant_inner }?;
-                        bm_le::utils::mix_in_type(&vector_root, db, #i)
-                    })
+                    let inner = if union {
+                        let value_root = match attributed_fields(&variant.fields).first() {
+                            Some(f) => {
+                                let expr = into_tree_cached_expr(&f.1, &f.2, quote! { variant.0 }, 0);
+                                quote! { #expr? }
+                            },
+                            None => quote! { bm_le::Value::End(Default::default()) },
+                        };
+
+                        quote! {
+                            let value_root: bm_le::ValueOf<DB::Construct> = #value_root;
+                            bm_le::utils::mix_in_type(&value_root, db, #i)
+                        }
+                    } else {
+                        quote! {
+                            let vector_root = { #variant_inner }?;
+                            bm_le::utils::mix_in_type(&vector_root, db, #i)
+                        }
+                    };
+
+                    normalized_variant_match_cause(&input.ident, variant, inner)
                 }).collect::<Vec<_>>();
 
             (where_fields, quote! {
@@ -94,13 +783,14 @@ pub fn into_tree_derive(input: TokenStream) -> TokenStream {
     };
 
     let expanded = quote! {
-        impl #impl_generics bm_le::IntoTree for #name #ty_generics where
+        impl #impl_generics bm_le::IntoTreeCached for #name #ty_generics where
             #where_clause
             #(#where_fields),*
         {
-            fn into_tree<DB: bm_le::WriteBackend>(
+            fn into_tree_cached<DB: bm_le::WriteBackend>(
                 &self,
-                db: &mut DB
+                db: &mut DB,
+                cache: &mut bm_le::TreeHashCache,
             ) -> Result<bm_le::ValueOf<DB::Construct>, bm_le::Error<DB::Error>> where
                 DB::Construct: bm_le::CompatibleConstruct
             {
@@ -112,65 +802,91 @@ pub fn into_tree_derive(input: TokenStream) -> TokenStream {
     proc_macro::TokenStream::from(expanded)
 }
 
+/// Derive `FromTree` for a struct or enum. Skipped fields are set to
+/// their default value.
+///
+/// Structs marked `#[bm(layout)]` get the layout constants described in
+/// `IntoTree` from this derive:
+///
+/// ```
+/// use bm_le::FromTree;
+///
+/// #[derive(FromTree)]
+/// #[bm(layout)]
+/// struct Foo {
+///     a: u64,
+///     b: u64,
+/// }
+///
+/// assert_eq!(Foo::B_INDEX, bm_le::Index::from_one(3).unwrap());
+/// ```
+///
+/// Enum variant fields can't be skipped:
+///
+/// ```compile_fail
+/// use bm_le::FromTree;
+///
+/// #[derive(FromTree)]
+/// enum Foo {
+///     A(#[bm(skip)] u64),
+/// }
+/// ```
+///
+/// Skipped fields must implement `Default`:
+///
+/// ```compile_fail
+/// use bm_le::FromTree;
+///
+/// struct NoDefault;
+///
+/// #[derive(FromTree)]
+/// struct Foo {
+///     a: u64,
+///     #[bm(skip)]
+///     b: NoDefault,
+/// }
+/// ```
 #[proc_macro_derive(FromTree, attributes(bm))]
 pub fn from_tree_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    let name = input.ident;
+    let name = &input.ident;
 
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     let build_fields = |fs| {
-        let where_fields = normalized_fields(fs)
-            .iter()
-            .map(|f| {
-	        let ty = &f.1.ty;
+        let fields = attributed_fields(fs);
 
-                if has_attribute("bm", &f.1.attrs, "compact") {
-                    quote_spanned! {
-		        f.1.span() => bm_le::Compact<#ty>: bm_le::FromTree
-	            }
-                } else {
-	            quote_spanned! {
-		        f.1.span() => #ty: bm_le::FromTree
-	            }
-                }
-	    }).collect::<Vec<_>>();
+        let where_fields = fields
+            .iter()
+            .filter_map(|f| from_tree_where(&f.1, &f.2))
+            .collect::<Vec<_>>();
 
-        let fields = normalized_fields(fs)
+        let mut fields_count = 0usize;
+        let fields = fields
             .iter()
-            .enumerate()
-            .map(|(i, f)| {
+            .map(|f| {
                 let name = &f.0;
-                let ty = &f.1.ty;
+                let i = fields_count;
+                if !f.2.skip {
+                    fields_count += 1;
+                }
 
                 (quote_spanned! { f.1.span() => #name },
-                 if has_attribute("bm", &f.1.attrs, "compact") {
-                     quote_spanned! {
-                         f.1.span() =>
-                             <bm_le::Compact<#ty> as bm_le::FromTree>::from_tree(
-                                 &vector.get(db, #i)?,
-                                 db,
-                             )?.0
-                     }
-                 } else {
-                     quote_spanned! {
-                         f.1.span() =>
-                             bm_le::FromTree::from_tree(
-                                 &vector.get(db, #i)?,
-                                 db,
-                             )?
-                     }
-                 })
+                 from_tree_expr(&f.1, &f.2, quote! { &vector.get(db, #i)? }))
             }).collect::<Vec<_>>();
 
-        (where_fields, fields)
+        (where_fields, fields, fields_count)
     };
 
+    let kind = stable::container_kind(&input);
+    let layout = container_layout(&input, &kind, true);
     let (where_fields, inner) = match input.data {
+        Data::Struct(_) if !matches!(kind, ContainerKind::Container) => {
+            stable::from_tree(&input, &kind)
+        },
         Data::Struct(ref data) => {
-            let (where_fields, fields) = build_fields(&data.fields);
+            let (where_fields, fields, fields_count) = build_fields(&data.fields);
 
-            let fields_count = fields.iter().count();
             let fields = fields.into_iter().map(|f| {
                 let name = f.0;
                 let value = f.1;
@@ -184,32 +900,57 @@ pub fn from_tree_derive(input: TokenStream) -> TokenStream {
                 {
                     use bm_le::Leak;
 
+                    if db.strict_decoding() {
+                        bm_le::utils::check_empty_tail(root, db, #fields_count, None)?;
+                    }
+
                     let vector = bm_le::DanglingVector::<DB::Construct>::from_leaked(
                         (root.clone(), #fields_count, None)
                     );
 
-                    Ok(Self {
+                    bm_le::utils::decode_nested(db, |db| Ok(Self {
                         #(#fields)*
-                    })
+                    }))
                 }
             };
 
             (where_fields, inner)
         },
         Data::Enum(ref data) => {
+            let union = is_union(&input);
             let mut where_fields = Vec::new();
 
             let variants = data.variants
                 .iter()
                 .enumerate()
                 .map(|(i, variant)| {
-                    let (mut variant_where_fields, variant_fields) = build_fields(
+                    check_variant_fields(&variant.fields);
+                    let (mut variant_where_fields, variant_fields, fields_count) = build_fields(
                         &variant.fields,
                     );
                     let ident = &variant.ident;
 
                     where_fields.append(&mut variant_where_fields);
-                    let fields_count = variant_fields.iter().count();
+
+                    if union {
+                        return match attributed_fields(&variant.fields).first() {
+                            Some(f) => {
+                                let value = from_tree_expr(&f.1, &f.2, quote! { value_root });
+                                quote! {
+                                    #i => Ok(#name::#ident(#value)),
+                                }
+                            },
+                            None => quote! {
+                                #i => {
+                                    if value_root != &bm_le::Value::End(Default::default()) {
+                                        return Err(bm_le::Error::NonCanonical)
+                                    }
+
+                                    Ok(#name::#ident)
+                                },
+                            },
+                        }
+                    }
 
                     match variant.fields {
                         Fields::Named(_) => {
@@ -226,6 +967,10 @@ pub fn from_tree_derive(input: TokenStream) -> TokenStream {
                                 #i => {
                                     use bm_le::Leak;
 
+                                    if db.strict_decoding() {
+                                        bm_le::utils::check_empty_tail(vector_root, db, #fields_count, None)?;
+                                    }
+
                                     let vector = bm_le::DanglingVector::<DB::Construct>::from_leaked(
                                         (vector_root.clone(), #fields_count, None)
                                     );
@@ -249,6 +994,10 @@ pub fn from_tree_derive(input: TokenStream) -> TokenStream {
                                 #i => {
                                     use bm_le::Leak;
 
+                                    if db.strict_decoding() {
+                                        bm_le::utils::check_empty_tail(vector_root, db, #fields_count, None)?;
+                                    }
+
                                     let vector = bm_le::DanglingVector::<DB::Construct>::from_leaked(
                                         (vector_root.clone(), #fields_count, None)
                                     );
@@ -263,7 +1012,7 @@ pub fn from_tree_derive(input: TokenStream) -> TokenStream {
                             quote! {
                                 #i => {
                                     if vector_root != &bm_le::Value::End(Default::default()) {
-                                        return Err(bm_le::Error::CorruptedDatabase)
+                                        return Err(bm_le::Error::NonCanonical)
                                     }
 
                                     Ok(#name::#ident)
@@ -273,11 +1022,14 @@ pub fn from_tree_derive(input: TokenStream) -> TokenStream {
                     }
                 }).collect::<Vec<_>>();
 
+            let root_name = if union { quote! { value_root } } else { quote! { vector_root } };
+
             (where_fields, quote! {
-                bm_le::utils::decode_with_type(root, db, |vector_root, db, ty| {
+                bm_le::utils::decode_with_type(root, db, |#root_name, db, ty| {
                     match ty {
                         #(#variants)*
-                        _ => return Err(bm_le::Error::CorruptedDatabase)
+                        // Unknown selectors.
+                        _ => return Err(bm_le::Error::NonCanonical)
                     }
                 })
             })
@@ -300,7 +1052,460 @@ pub fn from_tree_derive(input: TokenStream) -> TokenStream {
                     #inner
                 }
             }
+
+            #layout
+        };
+
+    proc_macro::TokenStream::from(expanded)
+}
+
+#[proc_macro_derive(Encode, attributes(bm))]
+pub fn encode_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let build_fields = |fs, prefix| {
+        let fields = attributed_fields(fs)
+            .into_iter()
+            .filter(|f| !f.2.skip)
+            .collect::<Vec<_>>();
+
+        let types = fields
+            .iter()
+            .map(|f| {
+                let ty = &f.1.ty;
+
+                if f.2.compact {
+                    quote_spanned! { f.1.span() => bm_le::CompactRef<'_, #ty> }
+                } else {
+                    quote_spanned! { f.1.span() => #ty }
+                }
+            }).collect::<Vec<_>>();
+
+        let where_fields = fields
+            .iter()
+            .map(|f| {
+                let ty = &f.1.ty;
+
+                if f.2.compact {
+                    quote_spanned! {
+                        f.1.span() => for<'a> bm_le::CompactRef<'a, #ty>: bm_le::Encode
+                    }
+                } else {
+                    quote_spanned! {
+                        f.1.span() => #ty: bm_le::Encode
+                    }
+                }
+            }).collect::<Vec<_>>();
+
+        let fields = fields
+            .iter()
+            .map(|f| {
+                let ident = &f.0;
+
+                if f.2.compact {
+                    quote_spanned! { f.1.span() => {
+                        encoder.append(&bm_le::CompactRef(#prefix #ident));
+                    } }
+                } else {
+                    quote_spanned! { f.1.span() => {
+                        encoder.append(#prefix #ident);
+                    } }
+                }
+            }).collect::<Vec<_>>();
+
+        let types_ref = &types;
+        let is_fixed_len = quote! {
+            true #(&& <#types_ref as bm_le::Encode>::is_fixed_len())*
+        };
+        let fixed_part_len = quote! {
+            0 #(+ <#types_ref as bm_le::Encode>::fixed_len())*
+        };
+        let inner = quote! {
+            let mut encoder = bm_le::ContainerEncoder::new(out, #fixed_part_len);
+            #(#fields)*
+            encoder.finish();
+        };
+
+        (where_fields, is_fixed_len, fixed_part_len, inner)
+    };
+
+    let kind = stable::container_kind(&input);
+    let (where_fields, is_fixed_len, fixed_part_len, inner) = match input.data {
+        Data::Struct(_) if !matches!(kind, ContainerKind::Container) => {
+            stable::encode(&input, &kind)
+        },
+        Data::Struct(ref data) => {
+            build_fields(&data.fields, quote! { &self. })
+        },
+        Data::Enum(ref data) => {
+            let union = is_union(&input);
+            let mut where_fields = Vec::new();
+
+            let variants = data.variants
+                .iter()
+                .enumerate()
+                .map(|(i, variant)| {
+                    check_variant_fields(&variant.fields);
+                    let (mut variant_where_fields, _, _, mut variant_inner) = build_fields(
+                        &variant.fields,
+                        if is_fields_variant_unnamed(variant) { quote! { variant. } } else { quote! {} }
+                    );
+
+                    where_fields.append(&mut variant_where_fields);
+
+                    if union {
+                        variant_inner = match normalized_fields(&variant.fields).first() {
+                            Some(f) if has_attribute("bm", &f.1.attrs, "compact") => quote_spanned! {
+                                f.1.span() => bm_le::Encode::encode_to(&bm_le::CompactRef(variant.0), out);
+                            },
+                            Some(f) => quote_spanned! {
+                                f.1.span() => bm_le::Encode::encode_to(variant.0, out);
+                            },
+                            None => quote! { },
+                        };
+                    }
+
+                    normalized_variant_match_cause(&input.ident, variant, quote! {
+                        out.push(#i as u8);
+                        #variant_inner
+                    })
+                }).collect::<Vec<_>>();
+
+            (where_fields, quote! { false }, quote! { 0 }, quote! {
+                match self {
+                    #(#variants)*
+                }
+            })
+        },
+        Data::Union(_) => panic!("Unsupported"),
+    };
+
+    let expanded = quote! {
+        impl #impl_generics bm_le::Encode for #name #ty_generics where
+            #where_clause
+            #(#where_fields),*
+        {
+            fn is_fixed_len() -> bool {
+                #is_fixed_len
+            }
+
+            fn fixed_len() -> usize {
+                if <Self as bm_le::Encode>::is_fixed_len() {
+                    #fixed_part_len
+                } else {
+                    bm_le::BYTES_PER_LENGTH_OFFSET
+                }
+            }
+
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                #inner
+            }
+        }
+    };
+
+    proc_macro::TokenStream::from(expanded)
+}
+
+#[proc_macro_derive(Decode, attributes(bm))]
+pub fn decode_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let build_fields = |fs| {
+        let fields = attributed_fields(fs);
+
+        let types = fields
+            .iter()
+            .filter(|f| !f.2.skip)
+            .map(|f| {
+                let ty = &f.1.ty;
+
+                if f.2.compact {
+                    quote_spanned! { f.1.span() => bm_le::Compact<#ty> }
+                } else {
+                    quote_spanned! { f.1.span() => #ty }
+                }
+            }).collect::<Vec<_>>();
+
+        let where_fields = types
+            .iter()
+            .map(|ty| quote! { #ty: bm_le::Decode })
+            .chain(fields.iter().filter(|f| f.2.skip).map(|f| {
+                let ty = &f.1.ty;
+                quote_spanned! { f.1.span() => #ty: Default }
+            }))
+            .collect::<Vec<_>>();
+
+        let fields = fields
+            .iter()
+            .map(|f| {
+                let name = &f.0;
+
+                (quote_spanned! { f.1.span() => #name }, decode_expr(&f.1, &f.2))
+            }).collect::<Vec<_>>();
+
+        let types_ref = &types;
+        let is_fixed_len = quote! {
+            true #(&& <#types_ref as bm_le::Decode>::is_fixed_len())*
+        };
+        let fixed_part_len = quote! {
+            0 #(+ <#types_ref as bm_le::Decode>::fixed_len())*
         };
+        let register = quote! {
+            let mut decoder = bm_le::ContainerDecoder::new(bytes);
+            #(decoder.register::<#types_ref>()?;)*
+            let mut items = decoder.finish()?;
+        };
+
+        (where_fields, is_fixed_len, fixed_part_len, register, fields)
+    };
+
+    let kind = stable::container_kind(&input);
+    let (where_fields, is_fixed_len, fixed_part_len, inner) = match input.data {
+        Data::Struct(_) if !matches!(kind, ContainerKind::Container) => {
+            stable::decode(&input, &kind)
+        },
+        Data::Struct(ref data) => {
+            let (where_fields, is_fixed_len, fixed_part_len, register, fields) =
+                build_fields(&data.fields);
+
+            let construct = match data.fields {
+                Fields::Named(_) => {
+                    let fields = fields.into_iter().map(|(name, value)| quote! { #name: #value, });
+                    quote! { Self { #(#fields)* } }
+                },
+                Fields::Unnamed(_) => {
+                    let fields = fields.into_iter().map(|(_, value)| quote! { #value, });
+                    quote! { Self(#(#fields)*) }
+                },
+                Fields::Unit => quote! { Self },
+            };
+
+            (where_fields, is_fixed_len, fixed_part_len, quote! {
+                #register
+                Ok(#construct)
+            })
+        },
+        Data::Enum(ref data) => {
+            let union = is_union(&input);
+            let mut where_fields = Vec::new();
+
+            let variants = data.variants
+                .iter()
+                .enumerate()
+                .map(|(i, variant)| {
+                    check_variant_fields(&variant.fields);
+                    let (mut variant_where_fields, _, _, register, fields) =
+                        build_fields(&variant.fields);
+                    let ident = &variant.ident;
+                    let i = i as u8;
+
+                    where_fields.append(&mut variant_where_fields);
+
+                    if union {
+                        let value = match normalized_fields(&variant.fields).first() {
+                            Some(f) if has_attribute("bm", &f.1.attrs, "compact") => {
+                                let ty = &f.1.ty;
+                                quote_spanned! {
+                                    f.1.span() => #name::#ident(
+                                        <bm_le::Compact<#ty> as bm_le::Decode>::decode(bytes)?.0
+                                    )
+                                }
+                            },
+                            Some(f) => {
+                                let ty = &f.1.ty;
+                                quote_spanned! {
+                                    f.1.span() => #name::#ident(<#ty as bm_le::Decode>::decode(bytes)?)
+                                }
+                            },
+                            None => quote! {{
+                                if !bytes.is_empty() {
+                                    return Err(bm_le::DecodeError::InvalidLength)
+                                }
+                                #name::#ident
+                            }},
+                        };
+
+                        return quote! {
+                            #i => Ok(#value),
+                        }
+                    }
+
+                    let construct = match variant.fields {
+                        Fields::Named(_) => {
+                            let fields = fields.into_iter().map(|(name, value)| quote! { #name: #value, });
+                            quote! { #name::#ident { #(#fields)* } }
+                        },
+                        Fields::Unnamed(_) => {
+                            let fields = fields.into_iter().map(|(_, value)| quote! { #value, });
+                            quote! { #name::#ident(#(#fields)*) }
+                        },
+                        Fields::Unit => quote! { #name::#ident },
+                    };
+
+                    quote! {
+                        #i => {
+                            #register
+                            Ok(#construct)
+                        },
+                    }
+                }).collect::<Vec<_>>();
+
+            (where_fields, quote! { false }, quote! { 0 }, quote! {
+                let (selector, bytes) = bytes.split_first().ok_or(bm_le::DecodeError::InvalidLength)?;
+                match selector {
+                    #(#variants)*
+                    _ => Err(bm_le::DecodeError::InvalidSelector),
+                }
+            })
+        },
+        Data::Union(_) => panic!("Not supported"),
+    };
+
+    let expanded = quote! {
+        impl #impl_generics bm_le::Decode for #name #ty_generics where
+            #where_clause
+            #(#where_fields),*
+        {
+            fn is_fixed_len() -> bool {
+                #is_fixed_len
+            }
+
+            fn fixed_len() -> usize {
+                if <Self as bm_le::Decode>::is_fixed_len() {
+                    #fixed_part_len
+                } else {
+                    bm_le::BYTES_PER_LENGTH_OFFSET
+                }
+            }
+
+            fn decode(bytes: &[u8]) -> Result<Self, bm_le::DecodeError> {
+                #inner
+            }
+        }
+    };
+
+    proc_macro::TokenStream::from(expanded)
+}
+
+#[proc_macro_derive(View, attributes(bm))]
+pub fn view_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let vis = &input.vis;
+    let view_name = syn::Ident::new(&format!("{}View", name), name.span());
+
+    if !input.generics.params.is_empty() {
+        panic!("View is not supported for generic types")
+    }
+    if !matches!(stable::container_kind(&input), ContainerKind::Container) {
+        panic!("View is not supported for stable containers and profiles")
+    }
+
+    let data = match input.data {
+        Data::Struct(ref data) => data,
+        _ => panic!("View is only supported for structs"),
+    };
+
+    let fields = attributed_fields(&data.fields)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, f)| !f.2.skip)
+        .collect::<Vec<_>>();
+    let first_index = fields.len().next_power_of_two();
+
+    let (getters, setters): (Vec<_>, Vec<_>) = fields
+        .iter()
+        .enumerate()
+        .map(|(i, (position, f))| {
+            let ty = &f.1.ty;
+            let index = first_index + i;
+            let getter = match f.1.ident {
+                Some(ref ident) => ident.clone(),
+                None => syn::Ident::new(&format!("field_{}", position), f.1.span()),
+            };
+            let setter = syn::Ident::new(&format!("set_{}", getter), f.1.span());
+            let getter_doc = format!("Decode field `{}` on demand.", getter);
+            let setter_doc = format!("Update field `{}`, returning the new root.", getter);
+
+            let decode = from_tree_expr(&f.1, &f.2, quote! { &subtree.metadata() });
+            let encode = into_tree_expr(&f.1, &f.2, quote! { value });
+
+            (quote! {
+                #[doc = #getter_doc]
+                pub fn #getter(&mut self) -> Result<#ty, bm_le::Error<DB::Error>> {
+                    use bm_le::Leak;
+
+                    let db = &mut *self.db;
+                    let raw = bm_le::DanglingRaw::<DB::Construct>::from_leaked(self.root.clone());
+                    let subtree = raw.subtree(
+                        db,
+                        bm_le::Index::from_one(#index).expect("Index is greater than zero; qed"),
+                    )?;
+                    Ok(#decode)
+                }
+            },
+             quote! {
+                #[doc = #setter_doc]
+                pub fn #setter(
+                    &mut self,
+                    value: &#ty
+                ) -> Result<bm_le::ValueOf<DB::Construct>, bm_le::Error<DB::Error>> {
+                    use bm_le::Leak;
+
+                    let db = &mut *self.db;
+                    let value = #encode?;
+                    let mut raw = bm_le::DanglingRaw::<DB::Construct>::from_leaked(self.root.clone());
+                    raw.set(
+                        db,
+                        bm_le::Index::from_one(#index).expect("Index is greater than zero; qed"),
+                        value,
+                    )?;
+                    self.root = raw.metadata();
+                    Ok(self.root.clone())
+                }
+            })
+        }).unzip();
+
+    let view_doc = format!("Lazy view of `{}` over a stored tree.", name);
+
+    let expanded = quote! {
+        #[doc = #view_doc]
+        #vis struct #view_name<'db, DB: bm_le::Backend> {
+            root: bm_le::ValueOf<DB::Construct>,
+            db: &'db mut DB,
+        }
+
+        impl<'db, DB: bm_le::Backend> #view_name<'db, DB> {
+            /// Create a new view from the container root.
+            pub fn new(root: bm_le::ValueOf<DB::Construct>, db: &'db mut DB) -> Self {
+                Self { root, db }
+            }
+
+            /// Current root of the container.
+            pub fn root(&self) -> bm_le::ValueOf<DB::Construct> {
+                self.root.clone()
+            }
+        }
+
+        impl<'db, DB: bm_le::ReadBackend> #view_name<'db, DB> where
+            DB::Construct: bm_le::CompatibleConstruct,
+        {
+            #(#getters)*
+        }
+
+        #[allow(clippy::ptr_arg)]
+        impl<'db, DB: bm_le::WriteBackend> #view_name<'db, DB> where
+            DB::Construct: bm_le::CompatibleConstruct,
+        {
+            #(#setters)*
+        }
+    };
 
     proc_macro::TokenStream::from(expanded)
 }
@@ -0,0 +1,534 @@
+//! Derive of stable containers and profiles (EIP-7495).
+
+use quote::{quote, quote_spanned};
+use syn::{DeriveInput, Data, Fields, Meta, NestedMeta, Lit};
+use syn::spanned::Spanned;
+use crate::{FieldAttrs, field_attrs, generic_item, into_tree_expr, into_tree_where,
+            from_tree_expr, from_tree_where, decode_expr};
+
+/// Kind of a struct, from `#[bm(...)]` attributes on it.
+pub enum ContainerKind {
+    /// Plain container.
+    Container,
+    /// Stable container with given capacity.
+    Stable(usize),
+    /// Profile of the given base stable container.
+    Profile(syn::Path),
+}
+
+/// Parse the kind of a struct, panicking on misuse.
+pub fn container_kind(input: &DeriveInput) -> ContainerKind {
+    let mut kinds = Vec::new();
+
+    for attr in &input.attrs {
+        let list = match attr.parse_meta() {
+            Ok(Meta::List(ref list)) if list.ident == "bm" => list.clone(),
+            Ok(Meta::Word(ref ident)) | Ok(Meta::NameValue(syn::MetaNameValue { ref ident, .. }))
+                if ident == "bm" => panic!("bm attribute must be a list, like #[bm(union)]"),
+            _ => continue,
+        };
+
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(ref value)) if value.ident == "stable_container" => {
+                    match value.lit {
+                        Lit::Int(ref lit) if lit.value() > 0 =>
+                            kinds.push(ContainerKind::Stable(lit.value() as usize)),
+                        _ => panic!("stable_container must be a positive integer, like #[bm(stable_container = 16)]"),
+                    }
+                },
+                NestedMeta::Meta(Meta::NameValue(ref value)) if value.ident == "profile" => {
+                    match value.lit {
+                        Lit::Str(ref lit) => kinds.push(ContainerKind::Profile(
+                            lit.parse().unwrap_or_else(|_| panic!("profile must be a path, like #[bm(profile = \"Base\")]"))
+                        )),
+                        _ => panic!("profile must be a string, like #[bm(profile = \"Base\")]"),
+                    }
+                },
+                NestedMeta::Meta(Meta::Word(ref ident)) if ident == "union" => {
+                    if !matches!(input.data, Data::Enum(_)) {
+                        panic!("Union must be an enum")
+                    }
+                },
+                NestedMeta::Meta(Meta::Word(ref ident)) if ident == "layout" => (),
+                _ => panic!("Unknown bm attribute, expected stable_container, profile, union or layout"),
+            }
+        }
+    }
+
+    if kinds.len() > 1 {
+        panic!("stable_container and profile cannot be combined")
+    }
+
+    match kinds.pop() {
+        None => ContainerKind::Container,
+        Some(kind) => {
+            match input.data {
+                Data::Struct(ref data) => match data.fields {
+                    Fields::Named(_) => (),
+                    _ => panic!("Stable containers and profiles must have named fields"),
+                },
+                _ => panic!("Stable containers and profiles must be structs"),
+            }
+            if !input.generics.params.is_empty() {
+                panic!("Stable containers and profiles are not supported for generic types")
+            }
+
+            kind
+        },
+    }
+}
+
+/// Field of a stable container or profile.
+struct StableField {
+    name: syn::Ident,
+    /// Field with the type inside `Option` if it is optional.
+    inner: syn::Field,
+    attrs: FieldAttrs,
+    optional: bool,
+}
+
+fn stable_fields(input: &DeriveInput, kind: &ContainerKind) -> Vec<StableField> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => panic!("Checked in container_kind; qed"),
+        },
+        _ => panic!("Checked in container_kind; qed"),
+    };
+
+    let ret = fields.iter().map(|field| {
+        let mut inner = field.clone();
+        let optional = match generic_item(&field.ty, "Option") {
+            Some(ty) => {
+                inner.ty = ty.clone();
+                true
+            },
+            None => false,
+        };
+
+        if let ContainerKind::Stable(_) = kind {
+            if !optional {
+                panic!("Stable container fields must be Option")
+            }
+        }
+
+        let attrs = field_attrs(&inner);
+        if attrs.skip {
+            panic!("skip is not supported in stable containers and profiles")
+        }
+
+        StableField {
+            name: field.ident.clone().expect("Checked in container_kind; qed"),
+            inner,
+            attrs,
+            optional,
+        }
+    }).collect::<Vec<_>>();
+
+    if let ContainerKind::Stable(capacity) = kind {
+        if ret.len() > *capacity {
+            panic!("Stable container has more fields than its capacity")
+        }
+    }
+
+    ret
+}
+
+/// Name of a field, without the raw identifier prefix.
+fn field_name(ident: &syn::Ident) -> String {
+    ident.to_string().trim_start_matches("r#").to_string()
+}
+
+/// Capacity of the stable container tree.
+fn capacity(kind: &ContainerKind) -> proc_macro2::TokenStream {
+    match kind {
+        ContainerKind::Stable(capacity) => quote! { #capacity },
+        ContainerKind::Profile(base) => quote! { <#base as bm_le::StableContainer>::CAPACITY },
+        ContainerKind::Container => panic!("Not a stable container"),
+    }
+}
+
+/// Position of each field in the stable container tree.
+fn positions(kind: &ContainerKind, fields: &[StableField]) -> Vec<proc_macro2::TokenStream> {
+    fields.iter().enumerate().map(|(i, f)| {
+        let name = field_name(&f.name);
+
+        match kind {
+            ContainerKind::Stable(_) => quote! { #i },
+            ContainerKind::Profile(base) => quote! {
+                <#base as bm_le::StableContainer>::field_position(#name)
+                    .expect("Profile fields are checked against the base; qed")
+            },
+            ContainerKind::Container => panic!("Not a stable container"),
+        }
+    }).collect()
+}
+
+/// Layout constants, and the `StableContainer` or `Profile` impl.
+pub fn layout(input: &DeriveInput, kind: &ContainerKind) -> proc_macro2::TokenStream {
+    let name = &input.ident;
+    let fields = stable_fields(input, kind);
+    let fields_count = fields.len();
+    let field_names = fields.iter().map(|f| field_name(&f.name)).collect::<Vec<_>>();
+    let const_names = fields.iter().map(|f| {
+        syn::Ident::new(&format!("{}_INDEX", field_name(&f.name).to_uppercase()), f.name.span())
+    }).collect::<Vec<_>>();
+    let docs = field_names.iter().map(|f| format!("Generalized index of field `{}`.", f)).collect::<Vec<_>>();
+
+    let (depth, indices, kind_impl) = match kind {
+        ContainerKind::Stable(capacity) => {
+            let first_index = capacity.next_power_of_two();
+            let depth = first_index.trailing_zeros() as usize + 1;
+            let indices = (0..fields_count).map(|i| {
+                let zero_index = 2 * first_index + i - 1;
+                quote! { bm_le::Index::from_zero(#zero_index) }
+            }).collect::<Vec<_>>();
+            let positions = 0..fields_count;
+            let field_names = &field_names;
+
+            (quote! { #depth }, indices, quote! {
+                impl bm_le::StableContainer for #name {
+                    const CAPACITY: usize = #capacity;
+
+                    fn field_position(name: &str) -> Option<usize> {
+                        match name {
+                            #(#field_names => Some(#positions),)*
+                            _ => None,
+                        }
+                    }
+                }
+            })
+        },
+        ContainerKind::Profile(base) => {
+            let indices = const_names.iter().map(|c| quote! { #base::#c }).collect::<Vec<_>>();
+            let checks = fields.iter().map(|f| {
+                let field = &f.name;
+                let ty = &f.inner.ty;
+                quote_spanned! { f.inner.span() => let _: &Option<#ty> = &base.#field; }
+            });
+
+            (quote! { #base::TREE_DEPTH }, indices, quote! {
+                impl bm_le::Profile for #name {
+                    type Base = #base;
+                }
+
+                const _: () = {
+                    // Profile fields must match optional fields of the base.
+                    #[allow(dead_code)]
+                    fn check_profile(base: &#base) {
+                        #(#checks)*
+                    }
+                };
+            })
+        },
+        ContainerKind::Container => panic!("Not a stable container"),
+    };
+
+    let const_names = &const_names;
+    let field_names = &field_names;
+    quote! {
+        #[allow(dead_code)]
+        impl #name {
+            /// Number of fields in the tree.
+            pub const FIELDS_COUNT: usize = #fields_count;
+            /// Depth of the container tree, not including field subtrees.
+            pub const TREE_DEPTH: usize = #depth;
+
+            #(
+                #[doc = #docs]
+                pub const #const_names: bm_le::Index = #indices;
+            )*
+
+            /// Generalized index of a field by its name.
+            pub fn field_index(name: &str) -> Option<bm_le::Index> {
+                match name {
+                    #(#field_names => Some(Self::#const_names),)*
+                    _ => None,
+                }
+            }
+        }
+
+        #kind_impl
+    }
+}
+
+/// Where clauses and body of `IntoTree::into_tree`.
+pub fn into_tree(
+    input: &DeriveInput,
+    kind: &ContainerKind
+) -> (Vec<proc_macro2::TokenStream>, proc_macro2::TokenStream) {
+    let fields = stable_fields(input, kind);
+    let capacity = capacity(kind);
+    let positions = positions(kind, &fields);
+
+    let where_fields = fields.iter()
+        .filter_map(|f| into_tree_where(&f.inner, &f.attrs))
+        .collect::<Vec<_>>();
+
+    let sets = fields.iter().zip(positions.iter()).map(|(f, position)| {
+        let name = &f.name;
+        let expr = into_tree_expr(&f.inner, &f.attrs, quote! { value });
+
+        if f.optional {
+            quote_spanned! { f.inner.span() =>
+                if let Some(value) = &self.#name {
+                    fields[#position] = Some(#expr?);
+                }
+            }
+        } else {
+            quote_spanned! { f.inner.span() => {
+                let value = &self.#name;
+                fields[#position] = Some(#expr?);
+            } }
+        }
+    }).collect::<Vec<_>>();
+
+    (where_fields, quote! {
+        let capacity = #capacity;
+        let mut fields = Vec::new();
+        fields.resize(capacity, None);
+        #(#sets)*
+        bm_le::utils::stable_container_tree(&fields, db, capacity)
+    })
+}
+
+/// Where clauses and body of `FromTree::from_tree`.
+pub fn from_tree(
+    input: &DeriveInput,
+    kind: &ContainerKind
+) -> (Vec<proc_macro2::TokenStream>, proc_macro2::TokenStream) {
+    let fields = stable_fields(input, kind);
+    let capacity = capacity(kind);
+    let positions = positions(kind, &fields);
+
+    let where_fields = fields.iter()
+        .filter_map(|f| from_tree_where(&f.inner, &f.attrs))
+        .collect::<Vec<_>>();
+
+    let roots = fields.iter().map(|f| syn::Ident::new(&format!("{}_root", field_name(&f.name)), f.name.span()))
+        .collect::<Vec<_>>();
+
+    let values = fields.iter().zip(roots.iter()).map(|(f, root)| {
+        let name = &f.name;
+        let expr = from_tree_expr(&f.inner, &f.attrs, quote! { &root });
+
+        if f.optional {
+            quote_spanned! { f.inner.span() =>
+                #name: match #root {
+                    Some(root) => Some(#expr),
+                    None => None,
+                },
+            }
+        } else {
+            quote_spanned! { f.inner.span() =>
+                #name: {
+                    let root = #root.ok_or(bm_le::Error::NonCanonical)?;
+                    #expr
+                },
+            }
+        }
+    }).collect::<Vec<_>>();
+
+    (where_fields, quote! {
+        let mut fields = bm_le::utils::decode_stable_container(root, db, #capacity)?;
+        #(let #roots = fields[#positions].take();)*
+        if fields.iter().any(Option::is_some) {
+            return Err(bm_le::Error::NonCanonical)
+        }
+
+        bm_le::utils::decode_nested(db, |db| Ok(Self {
+            #(#values)*
+        }))
+    })
+}
+
+/// Number of bits of the active fields bitvector in the encoding.
+fn active_bits(kind: &ContainerKind, fields: &[StableField]) -> usize {
+    match kind {
+        ContainerKind::Stable(capacity) => *capacity,
+        ContainerKind::Profile(_) => fields.iter().filter(|f| f.optional).count(),
+        ContainerKind::Container => panic!("Not a stable container"),
+    }
+}
+
+/// Where clauses, `is_fixed_len`, fixed part length and body of
+/// `Encode::encode_to`.
+pub fn encode(
+    input: &DeriveInput,
+    kind: &ContainerKind
+) -> (Vec<proc_macro2::TokenStream>, proc_macro2::TokenStream, proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let fields = stable_fields(input, kind);
+    let active_bits = active_bits(kind, &fields);
+
+    let types = fields.iter().map(|f| {
+        let ty = &f.inner.ty;
+
+        if f.attrs.compact {
+            quote_spanned! { f.inner.span() => bm_le::CompactRef<'_, #ty> }
+        } else {
+            quote_spanned! { f.inner.span() => #ty }
+        }
+    }).collect::<Vec<_>>();
+
+    let where_fields = fields.iter().map(|f| {
+        let ty = &f.inner.ty;
+
+        if f.attrs.compact {
+            quote_spanned! { f.inner.span() => for<'a> bm_le::CompactRef<'a, #ty>: bm_le::Encode }
+        } else {
+            quote_spanned! { f.inner.span() => #ty: bm_le::Encode }
+        }
+    }).collect::<Vec<_>>();
+
+    let actives = fields.iter().filter(|f| f.optional).map(|f| {
+        let name = &f.name;
+        quote! { active.push(self.#name.is_some()); }
+    }).collect::<Vec<_>>();
+
+    let lens = fields.iter().zip(types.iter()).map(|(f, ty)| {
+        let name = &f.name;
+
+        if f.optional {
+            quote! {
+                + if self.#name.is_some() { <#ty as bm_le::Encode>::fixed_len() } else { 0 }
+            }
+        } else {
+            quote! { + <#ty as bm_le::Encode>::fixed_len() }
+        }
+    }).collect::<Vec<_>>();
+
+    let appends = fields.iter().map(|f| {
+        let name = &f.name;
+        let append = if f.attrs.compact {
+            quote_spanned! { f.inner.span() => encoder.append(&bm_le::CompactRef(value)); }
+        } else {
+            quote_spanned! { f.inner.span() => encoder.append(value); }
+        };
+
+        if f.optional {
+            quote! {
+                if let Some(value) = &self.#name {
+                    #append
+                }
+            }
+        } else {
+            quote! {
+                let value = &self.#name;
+                #append
+            }
+        }
+    }).collect::<Vec<_>>();
+
+    let active = if active_bits > 0 {
+        quote! {
+            let mut active = Vec::new();
+            #(#actives)*
+            active.resize(#active_bits, false);
+            <bool as bm_le::CompactElement>::encode_compact_vector(&active, out);
+        }
+    } else {
+        quote! { }
+    };
+
+    let (is_fixed_len, fixed_part_len) = if active_bits > 0 {
+        (quote! { false }, quote! { 0 })
+    } else {
+        let types = &types;
+        (quote! { true #(&& <#types as bm_le::Encode>::is_fixed_len())* },
+         quote! { 0 #(+ <#types as bm_le::Encode>::fixed_len())* })
+    };
+
+    (where_fields, is_fixed_len, fixed_part_len, quote! {
+        #active
+        let fixed_part_len = 0 #(#lens)*;
+        let mut encoder = bm_le::ContainerEncoder::new(out, fixed_part_len);
+        #(#appends)*
+        encoder.finish();
+    })
+}
+
+/// Where clauses, `is_fixed_len`, fixed part length and body of
+/// `Decode::decode`.
+pub fn decode(
+    input: &DeriveInput,
+    kind: &ContainerKind
+) -> (Vec<proc_macro2::TokenStream>, proc_macro2::TokenStream, proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let fields = stable_fields(input, kind);
+    let active_bits = active_bits(kind, &fields);
+    let active_len = (active_bits + 7) / 8;
+    let optional_count = fields.iter().filter(|f| f.optional).count();
+
+    let types = fields.iter().map(|f| {
+        let ty = &f.inner.ty;
+
+        if f.attrs.compact {
+            quote_spanned! { f.inner.span() => bm_le::Compact<#ty> }
+        } else {
+            quote_spanned! { f.inner.span() => #ty }
+        }
+    }).collect::<Vec<_>>();
+
+    let where_fields = types.iter()
+        .map(|ty| quote! { #ty: bm_le::Decode })
+        .collect::<Vec<_>>();
+
+    let mut optional_index = 0usize;
+    let (registers, values): (Vec<_>, Vec<_>) = fields.iter().zip(types.iter()).map(|(f, ty)| {
+        let name = &f.name;
+        let value = decode_expr(&f.inner, &f.attrs);
+
+        if f.optional {
+            let i = optional_index;
+            optional_index += 1;
+
+            (quote! {
+                if active[#i] {
+                    decoder.register::<#ty>()?;
+                }
+            }, quote! {
+                #name: if active[#i] { Some(#value) } else { None },
+            })
+        } else {
+            (quote! {
+                decoder.register::<#ty>()?;
+            }, quote! {
+                #name: #value,
+            })
+        }
+    }).unzip();
+
+    let active = if active_bits > 0 {
+        quote! {
+            if bytes.len() < #active_len {
+                return Err(bm_le::DecodeError::InvalidLength)
+            }
+            let (active, bytes) = bytes.split_at(#active_len);
+            let active = <bool as bm_le::CompactElement>::decode_compact_vector(active, #active_bits)?;
+            if active[#optional_count..].iter().any(|active| *active) {
+                return Err(bm_le::DecodeError::InvalidValue)
+            }
+        }
+    } else {
+        quote! { }
+    };
+
+    let (is_fixed_len, fixed_part_len) = if active_bits > 0 {
+        (quote! { false }, quote! { 0 })
+    } else {
+        let types = &types;
+        (quote! { true #(&& <#types as bm_le::Decode>::is_fixed_len())* },
+         quote! { 0 #(+ <#types as bm_le::Decode>::fixed_len())* })
+    };
+
+    (where_fields, is_fixed_len, fixed_part_len, quote! {
+        #active
+        let mut decoder = bm_le::ContainerDecoder::new(bytes);
+        #(#registers)*
+        let mut items = decoder.finish()?;
+
+        Ok(Self {
+            #(#values)*
+        })
+    })
+}
@@ -1,7 +1,12 @@
+use std::convert::TryFrom;
+use std::str::FromStr;
 use sha2::{Digest, Sha256};
 use primitive_types::H256;
 use bm::InMemoryBackend;
-use bm_le::{IntoTree, FromTree, MaxVec, DigestConstruct, tree_root};
+use bm_le::{IntoTree, IntoTreeCached, FromTree, Encode, Decode, View, DecodeError, MaxVec, Index,
+            DanglingRaw, Leak,
+            ByteVector, ByteList, DigestConstruct, TreeHashCache, tree_root, tree_root_cached,
+            try_tree_root, try_tree_root_cached};
 use generic_array::GenericArray;
 
 fn chunk(data: &[u8]) -> H256 {
@@ -25,7 +30,7 @@ struct BasicContainer {
     c: u128,
 }
 
-#[derive(IntoTree, FromTree, PartialEq, Eq, Debug)]
+#[derive(IntoTree, IntoTreeCached, FromTree, Encode, Decode, View, PartialEq, Eq, Debug)]
 struct ConfigContainer {
     a: u64,
     b: u64,
@@ -37,7 +42,7 @@ struct ConfigContainer {
     f: MaxVec<u64, typenum::U5>,
 }
 
-#[derive(IntoTree, FromTree, Debug, Eq, PartialEq)]
+#[derive(IntoTree, IntoTreeCached, FromTree, Encode, Decode, Debug, Eq, PartialEq)]
 pub enum EnumTest {
     A(u128),
     B {
@@ -47,6 +52,145 @@ pub enum EnumTest {
     E,
 }
 
+#[derive(IntoTree, IntoTreeCached, FromTree, Encode, Decode, Debug, Eq, PartialEq)]
+#[bm(union)]
+enum UnionNoneOrU16 {
+    None,
+    U16(u16),
+}
+
+#[derive(IntoTree, IntoTreeCached, FromTree, Encode, Decode, Debug, Eq, PartialEq)]
+#[bm(union)]
+enum UnionU16OrList {
+    U16(u16),
+    List(#[bm(compact)] MaxVec<u8, typenum::U16>),
+}
+
+#[derive(IntoTree, IntoTreeCached, FromTree, Encode, Decode, Debug, Eq, PartialEq)]
+struct BytesContainer {
+    a: ByteVector<typenum::U48>,
+    b: ByteList<typenum::U64>,
+}
+
+#[derive(IntoTree, IntoTreeCached)]
+struct CachedContainer {
+    a: u64,
+    #[bm(compact)]
+    b: MaxVec<u64, typenum::U1024>,
+    c: Vec<ConfigContainer>,
+    d: EnumTest,
+}
+
+mod u32_as_u64 {
+    use bm_le::{IntoTree, FromTree, WriteBackend, ReadBackend, ValueOf, Error, CompatibleConstruct};
+
+    pub fn into_tree<DB: WriteBackend>(
+        value: &u32,
+        db: &mut DB
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        u64::from(*value).into_tree(db)
+    }
+
+    pub fn from_tree<DB: ReadBackend>(
+        root: &ValueOf<DB::Construct>,
+        db: &mut DB
+    ) -> Result<u32, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        let value = u64::from_tree(root, db)?;
+        if value > u64::from(u32::MAX) {
+            return Err(Error::NonCanonical)
+        }
+        Ok(value as u32)
+    }
+}
+
+#[derive(FromTree)]
+#[bm(layout)]
+struct DecodedContainer {
+    a: u64,
+    b: u64,
+    c: u64,
+}
+
+#[derive(IntoTree, FromTree)]
+#[bm(layout)]
+struct MarkedContainer {
+    a: u64,
+    b: u64,
+}
+
+#[derive(IntoTree, IntoTreeCached, FromTree, Encode, Decode, View, PartialEq, Eq, Debug)]
+struct AttributeContainer {
+    #[bm(max_len = 4)]
+    a: Vec<u64>,
+    #[bm(skip)]
+    b: u64,
+    #[bm(compact, max_len = 16)]
+    c: Vec<u8>,
+    #[bm(with = "u32_as_u64")]
+    d: u32,
+}
+
+#[derive(IntoTree)]
+struct NestedContainer {
+    a: u64,
+    inner: ConfigContainer,
+}
+
+#[derive(IntoTree)]
+struct AttributeEquivalent {
+    a: MaxVec<u64, typenum::U4>,
+    #[bm(compact)]
+    c: MaxVec<u8, typenum::U16>,
+    d: u64,
+}
+
+#[derive(IntoTree, FromTree, Encode, Decode, Debug, Eq, PartialEq)]
+#[bm(stable_container = 4)]
+pub struct Shape {
+    pub side: Option<u16>,
+    pub color: Option<u8>,
+    pub radius: Option<u16>,
+}
+
+#[derive(IntoTree, FromTree, Encode, Decode, Debug, Eq, PartialEq)]
+#[bm(profile = "Shape")]
+struct Square {
+    side: u16,
+    color: u8,
+}
+
+#[derive(IntoTree, FromTree, Encode, Decode, Debug, Eq, PartialEq)]
+#[bm(profile = "Shape")]
+struct Circle {
+    color: u8,
+    radius: u16,
+}
+
+#[derive(IntoTree, FromTree, Encode, Decode, Debug, Eq, PartialEq)]
+#[bm(profile = "Shape")]
+struct ColoredShape {
+    side: Option<u16>,
+    color: u8,
+}
+
+#[derive(IntoTree)]
+struct ShapePayload {
+    side: u16,
+    color: u8,
+    radius: u16,
+}
+
+#[derive(IntoTree)]
+struct ShapeRepr {
+    value: ShapePayload,
+    #[bm(compact)]
+    active_fields: GenericArray<bool, typenum::U4>,
+}
+
 #[test]
 fn test_basic() {
     assert_eq!(tree_root::<Sha256, _>(&BasicContainer { a: 1, b: 2, c: 3 }),
@@ -63,7 +207,7 @@ fn test_config() {
         c: 3,
         d: GenericArray::from([4, 5, 6, 7]),
         e: 8,
-        f: MaxVec::from(vec![9, 10]),
+        f: MaxVec::try_from(vec![9, 10]).unwrap(),
     };
     let actual = container.into_tree(&mut db).unwrap();
     let decoded = ConfigContainer::from_tree(&actual, &mut db).unwrap();
@@ -87,3 +231,337 @@ fn test_enum() {
     assert_eq!(d2, e2);
     assert_eq!(d3, e3);
 }
+
+#[test]
+fn test_cached() {
+    let mut cache = TreeHashCache::new();
+    let config = |i| ConfigContainer {
+        a: i,
+        b: 2,
+        c: 3,
+        d: GenericArray::from([4, 5, 6, 7]),
+        e: 8,
+        f: MaxVec::try_from(vec![9, 10]).unwrap(),
+    };
+    let mut container = CachedContainer {
+        a: 1,
+        b: MaxVec::try_from((0..1000).collect::<Vec<_>>()).unwrap(),
+        c: (0..10).map(config).collect(),
+        d: EnumTest::A(123),
+    };
+    assert_eq!(tree_root_cached::<Sha256, _>(&container, &mut cache),
+               tree_root::<Sha256, _>(&container));
+
+    container.a = 2;
+    container.b.as_mut()[999] = 0;
+    container.c[4].f.try_push(11).unwrap();
+    container.c.push(config(10));
+    assert_eq!(tree_root_cached::<Sha256, _>(&container, &mut cache),
+               tree_root::<Sha256, _>(&container));
+
+    container.d = EnumTest::B { c: 1, d: 2 };
+    container.c.truncate(3);
+    assert_eq!(tree_root_cached::<Sha256, _>(&container, &mut cache),
+               tree_root::<Sha256, _>(&container));
+}
+
+#[test]
+fn test_codec() {
+    let container = ConfigContainer {
+        a: 1,
+        b: 2,
+        c: 3,
+        d: GenericArray::from([4, 5, 6, 7]),
+        e: 8,
+        f: MaxVec::try_from(vec![9, 10]).unwrap(),
+    };
+    let encoded = container.encode();
+    assert_eq!(encoded.len(), 8 * 3 + 8 * 4 + 8 + 4 + 8 * 2);
+    assert_eq!(&encoded[64..68], &[68, 0, 0, 0]);
+    assert_eq!(ConfigContainer::decode(&encoded).unwrap(), container);
+    assert!(!<ConfigContainer as Encode>::is_fixed_len());
+
+    let mut too_long = encoded.clone();
+    too_long.extend_from_slice(&[0; 8 * 4]);
+    assert_eq!(ConfigContainer::decode(&too_long), Err(DecodeError::ListTooLong));
+
+    let e1 = EnumTest::A(123);
+    let e2 = EnumTest::B { c: 1, d: 2 };
+    let e3 = EnumTest::E;
+    assert_eq!(e1.encode()[0], 0);
+    assert_eq!(e2.encode(), vec![1, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0]);
+    assert_eq!(e3.encode(), vec![2]);
+    assert_eq!(EnumTest::decode(&e1.encode()).unwrap(), e1);
+    assert_eq!(EnumTest::decode(&e2.encode()).unwrap(), e2);
+    assert_eq!(EnumTest::decode(&e3.encode()).unwrap(), e3);
+    assert_eq!(EnumTest::decode(&[3]), Err(DecodeError::InvalidSelector));
+    assert_eq!(EnumTest::decode(&[2, 0]), Err(DecodeError::InvalidLength));
+}
+
+#[test]
+fn test_union() {
+    let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+
+    let none = UnionNoneOrU16::None;
+    assert_eq!(none.encode(), vec![0x00]);
+    assert_eq!(tree_root::<Sha256, _>(&none), h(&chunk(&[])[..], &chunk(&[])[..]));
+    assert_eq!(tree_root::<Sha256, _>(&none), H256::from_str(
+        "f5a5fd42d16a20302798ef6ed309979b43003d2320d9f0e8ea9831a92759fb4b"
+    ).unwrap());
+
+    let value = UnionNoneOrU16::U16(0xaabb);
+    assert_eq!(value.encode(), vec![0x01, 0xbb, 0xaa]);
+    assert_eq!(tree_root::<Sha256, _>(&value), h(&chunk(&[0xbb, 0xaa])[..], &chunk(&[0x01])[..]));
+    assert_eq!(tree_root::<Sha256, _>(&value), H256::from_str(
+        "016550f636d58cac2344703d636a9205c8370c1220510a4c0053da00771e4c6c"
+    ).unwrap());
+
+    let value = UnionU16OrList::U16(0xaabb);
+    assert_eq!(value.encode(), vec![0x00, 0xbb, 0xaa]);
+    assert_eq!(tree_root::<Sha256, _>(&value), h(&chunk(&[0xbb, 0xaa])[..], &chunk(&[])[..]));
+    assert_eq!(tree_root::<Sha256, _>(&value), H256::from_str(
+        "1a3ae6022c070dce5686a48eae443224e871ee366f9688e390f8be648fd66cd1"
+    ).unwrap());
+
+    let list = UnionU16OrList::List(MaxVec::try_from(vec![1, 2, 3]).unwrap());
+    assert_eq!(list.encode(), vec![0x01, 0x01, 0x02, 0x03]);
+    assert_eq!(tree_root::<Sha256, _>(&list),
+               h(&h(&chunk(&[1, 2, 3])[..], &chunk(&[3])[..])[..], &chunk(&[0x01])[..]));
+    assert_eq!(tree_root::<Sha256, _>(&list), H256::from_str(
+        "90a0aada6406bba277498e6c76dafff9ad448e11e725d394172a9376b33863ec"
+    ).unwrap());
+
+    let mut cache = TreeHashCache::new();
+    for value in &[&UnionU16OrList::U16(0xaabb), &list] {
+        assert_eq!(tree_root_cached::<Sha256, _>(*value, &mut cache), tree_root::<Sha256, _>(*value));
+    }
+    let mut cache = TreeHashCache::new();
+    assert_eq!(tree_root_cached::<Sha256, _>(&none, &mut cache), tree_root::<Sha256, _>(&none));
+
+    for value in &[UnionNoneOrU16::None, UnionNoneOrU16::U16(0xaabb)] {
+        assert_eq!(&UnionNoneOrU16::decode(&value.encode()).unwrap(), value);
+        let root = value.into_tree(&mut db).unwrap();
+        assert_eq!(&UnionNoneOrU16::from_tree(&root, &mut db).unwrap(), value);
+    }
+    assert_eq!(UnionU16OrList::decode(&list.encode()).unwrap(), list);
+    let root = list.into_tree(&mut db).unwrap();
+    assert_eq!(UnionU16OrList::from_tree(&root, &mut db).unwrap(), list);
+
+    assert_eq!(UnionNoneOrU16::decode(&[]), Err(DecodeError::InvalidLength));
+    assert_eq!(UnionNoneOrU16::decode(&[0x00, 0x00]), Err(DecodeError::InvalidLength));
+    assert_eq!(UnionNoneOrU16::decode(&[0x01, 0xbb]), Err(DecodeError::InvalidLength));
+    assert_eq!(UnionNoneOrU16::decode(&[0x02, 0xbb, 0xaa]), Err(DecodeError::InvalidSelector));
+    assert_eq!(UnionNoneOrU16::decode(&[0x80, 0xbb, 0xaa]), Err(DecodeError::InvalidSelector));
+    assert_eq!(UnionU16OrList::decode(&[0x01, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08,
+                                        0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x11]),
+               Err(DecodeError::ListTooLong));
+
+    let invalid_selector = bm_le::utils::mix_in_type(&0xaabbu16, &mut db, 2).unwrap();
+    assert!(UnionNoneOrU16::from_tree(&invalid_selector, &mut db).is_err());
+    let invalid_none = bm_le::utils::mix_in_type(&0xaabbu16, &mut db, 0).unwrap();
+    assert!(UnionNoneOrU16::from_tree(&invalid_none, &mut db).is_err());
+    let invalid_selector = bm_le::utils::mix_in_type(&0xaabbu16, &mut db, 2).unwrap();
+    assert!(UnionU16OrList::from_tree(&invalid_selector, &mut db).is_err());
+}
+
+#[test]
+fn test_bytes() {
+    let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+    let container = BytesContainer {
+        a: ByteVector::try_from(vec![1; 48]).unwrap(),
+        b: ByteList::try_from(vec![2; 40]).unwrap(),
+    };
+
+    let a = h(&[1; 32], &chunk(&[1; 16])[..]);
+    let b = h(&h(&[2; 32], &chunk(&[2; 8])[..])[..], &chunk(&[40])[..]);
+    assert_eq!(tree_root::<Sha256, _>(&container), h(&a[..], &b[..]));
+    assert_eq!(tree_root_cached::<Sha256, _>(&container, &mut TreeHashCache::new()),
+               tree_root::<Sha256, _>(&container));
+
+    let root = container.into_tree(&mut db).unwrap();
+    assert_eq!(BytesContainer::from_tree(&root, &mut db).unwrap(), container);
+    assert_eq!(BytesContainer::decode(&container.encode()).unwrap(), container);
+}
+
+#[test]
+fn test_view() {
+    let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+    let mut container = ConfigContainer {
+        a: 1,
+        b: 2,
+        c: 3,
+        d: GenericArray::from([4, 5, 6, 7]),
+        e: 8,
+        f: MaxVec::try_from(vec![9, 10]).unwrap(),
+    };
+    let root = container.into_tree(&mut db).unwrap();
+
+    let mut view = ConfigContainerView::new(root.clone(), &mut db);
+    assert_eq!(view.a().unwrap(), 1);
+    assert_eq!(view.e().unwrap(), 8);
+    assert_eq!(view.d().unwrap(), container.d);
+    assert_eq!(view.f().unwrap(), container.f);
+
+    container.c = 33;
+    container.f.try_push(11).unwrap();
+    view.set_c(&33).unwrap();
+    let new_root = view.set_f(&container.f).unwrap();
+    assert_eq!(view.root(), new_root);
+    assert_eq!(view.c().unwrap(), 33);
+
+    assert_eq!(new_root, container.into_tree(&mut db).unwrap());
+    assert_eq!(ConfigContainer::from_tree(&new_root, &mut db).unwrap(), container);
+}
+
+#[test]
+fn test_attributes() {
+    let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+    let container = AttributeContainer {
+        a: vec![1, 2, 3],
+        b: 42,
+        c: vec![4, 5, 6, 7, 8],
+        d: 9,
+    };
+    let equivalent = AttributeEquivalent {
+        a: MaxVec::try_from(vec![1, 2, 3]).unwrap(),
+        c: MaxVec::try_from(vec![4, 5, 6, 7, 8]).unwrap(),
+        d: 9,
+    };
+
+    let root = container.into_tree(&mut db).unwrap();
+    assert_eq!(root, equivalent.into_tree(&mut db).unwrap());
+    assert_eq!(tree_root_cached::<Sha256, _>(&container, &mut TreeHashCache::new()),
+               tree_root::<Sha256, _>(&equivalent));
+
+    let decoded = AttributeContainer::from_tree(&root, &mut db).unwrap();
+    assert_eq!(decoded, AttributeContainer { b: 0, ..container });
+
+    let mut view = AttributeContainerView::new(root.clone(), &mut db);
+    assert_eq!(view.a().unwrap(), vec![1, 2, 3]);
+    assert_eq!(view.d().unwrap(), 9);
+
+    let encoded = decoded.encode();
+    assert_eq!(AttributeContainer::decode(&encoded).unwrap(), decoded);
+
+    let too_long = AttributeContainer { a: vec![1, 2, 3, 4, 5], ..decoded };
+    assert!(too_long.into_tree(&mut db).is_err());
+    assert_eq!(try_tree_root::<Sha256, _>(&too_long), Err(bm_le::Error::InvalidParameter));
+    assert_eq!(try_tree_root_cached::<Sha256, _>(&too_long, &mut TreeHashCache::new()),
+               Err(bm_le::Error::InvalidParameter));
+    assert_eq!(AttributeContainer::decode(&too_long.encode()), Err(DecodeError::ListTooLong));
+    assert_eq!(bm_le::Summary::Full(too_long).try_root::<Sha256>(), Err(bm_le::Error::InvalidParameter));
+
+    let too_long_compact = AttributeContainer { c: vec![0; 17], ..decoded };
+    assert_eq!(AttributeContainer::decode(&too_long_compact.encode()), Err(DecodeError::ListTooLong));
+}
+
+#[test]
+#[should_panic]
+fn test_over_limit_tree_root() {
+    tree_root::<Sha256, _>(&AttributeContainer { a: vec![1, 2, 3, 4, 5], b: 0, c: Vec::new(), d: 0 });
+}
+
+#[test]
+fn test_layout() {
+    assert_eq!(ConfigContainer::FIELDS_COUNT, 6);
+    assert_eq!(ConfigContainer::TREE_DEPTH, 3);
+    assert_eq!(ConfigContainer::A_INDEX, Index::from_one(8).unwrap());
+    assert_eq!(ConfigContainer::F_INDEX, Index::from_one(13).unwrap());
+    assert_eq!(ConfigContainer::field_index("e"), Some(ConfigContainer::E_INDEX));
+    assert_eq!(ConfigContainer::field_index("g"), None);
+    assert_eq!(AttributeContainer::FIELDS_COUNT, 3);
+    assert_eq!(AttributeContainer::D_INDEX, Index::from_one(6).unwrap());
+    assert_eq!(AttributeContainer::field_index("b"), None);
+    assert_eq!(DecodedContainer::TREE_DEPTH, 2);
+    assert_eq!(DecodedContainer::C_INDEX, Index::from_one(6).unwrap());
+    let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+    let root = (1u64, 2u64, 3u64).into_tree(&mut db).unwrap();
+    let decoded = DecodedContainer::from_tree(&root, &mut db).unwrap();
+    assert_eq!((decoded.a, decoded.b, decoded.c), (1, 2, 3));
+    assert_eq!(MarkedContainer::FIELDS_COUNT, 2);
+    assert_eq!(MarkedContainer::field_index("b"), Some(Index::from_one(3).unwrap()));
+
+    let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+    let container = NestedContainer {
+        a: 1,
+        inner: ConfigContainer {
+            a: 2,
+            b: 3,
+            c: 4,
+            d: GenericArray::from([5, 6, 7, 8]),
+            e: 9,
+            f: MaxVec::try_from(vec![10, 11]).unwrap(),
+        },
+    };
+    let root = container.into_tree(&mut db).unwrap();
+
+    let index = NestedContainer::INNER_INDEX.concat(&ConfigContainer::E_INDEX);
+    assert_eq!(NestedContainer::INNER_INDEX.checked_concat(&ConfigContainer::E_INDEX), Some(index));
+    let raw = DanglingRaw::from_leaked(root);
+    let subtree = raw.subtree(&mut db, index).unwrap();
+    assert_eq!(subtree.metadata(), 9u64.into_tree(&mut db).unwrap());
+}
+
+#[test]
+fn test_stable_container() {
+    let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+
+    let shape = Shape { side: Some(0x42), color: Some(1), radius: None };
+    let square = Square { side: 0x42, color: 1 };
+    let root = tree_root::<Sha256, _>(&ShapeRepr {
+        value: ShapePayload { side: 0x42, color: 1, radius: 0 },
+        active_fields: GenericArray::from([true, true, false, false]),
+    });
+    assert_eq!(tree_root::<Sha256, _>(&shape), root);
+    assert_eq!(tree_root::<Sha256, _>(&square), root);
+    assert_eq!(root, H256::from_str(
+        "bfdb6fda9d02805e640c0f5767b8d1bb9ff4211498a5e2d7c0f36e1b88ce57ff"
+    ).unwrap());
+    assert_eq!(shape.encode(), vec![0x03, 0x42, 0x00, 0x01]);
+    assert_eq!(square.encode(), vec![0x42, 0x00, 0x01]);
+    assert_eq!(Shape::decode(&shape.encode()).unwrap(), shape);
+    assert_eq!(Square::decode(&square.encode()).unwrap(), square);
+
+    let shape = Shape { side: None, color: Some(1), radius: Some(0x42) };
+    let circle = Circle { color: 1, radius: 0x42 };
+    let root = tree_root::<Sha256, _>(&ShapeRepr {
+        value: ShapePayload { side: 0, color: 1, radius: 0x42 },
+        active_fields: GenericArray::from([false, true, true, false]),
+    });
+    assert_eq!(tree_root::<Sha256, _>(&shape), root);
+    assert_eq!(tree_root::<Sha256, _>(&circle), root);
+    assert_eq!(root, H256::from_str(
+        "f66d2c38c8d2afbd409e86c529dff728e9a4208215ca20ee44e49c3d11e145d8"
+    ).unwrap());
+    assert_eq!(shape.encode(), vec![0x06, 0x01, 0x42, 0x00]);
+    assert_eq!(circle.encode(), vec![0x01, 0x42, 0x00]);
+    assert_eq!(Circle::decode(&circle.encode()).unwrap(), circle);
+
+    let shape = Shape { side: None, color: Some(1), radius: None };
+    let colored = ColoredShape { side: None, color: 1 };
+    assert_eq!(tree_root::<Sha256, _>(&shape), tree_root::<Sha256, _>(&colored));
+    assert_eq!(tree_root::<Sha256, _>(&colored), H256::from_str(
+        "522edd7309c0041b8eb6a218d756af558e9cf4c816441ec7e6eef42dfa47bb98"
+    ).unwrap());
+    assert_eq!(shape.encode(), vec![0x02, 0x01]);
+    assert_eq!(colored.encode(), vec![0x00, 0x01]);
+    let colored = ColoredShape { side: Some(0x42), color: 1 };
+    assert_eq!(colored.encode(), vec![0x01, 0x42, 0x00, 0x01]);
+    assert_eq!(ColoredShape::decode(&colored.encode()).unwrap(), colored);
+    assert_eq!(Shape::decode(&[0x08]), Err(DecodeError::InvalidValue));
+
+    let root = square.into_tree(&mut db).unwrap();
+    assert_eq!(Shape::from_tree(&root, &mut db).unwrap(),
+               Shape { side: Some(0x42), color: Some(1), radius: None });
+    assert_eq!(Square::from_tree(&root, &mut db).unwrap(), square);
+    assert_eq!(ColoredShape::from_tree(&root, &mut db).unwrap(), ColoredShape { side: Some(0x42), color: 1 });
+    assert!(matches!(Circle::from_tree(&root, &mut db), Err(bm_le::Error::NonCanonical)));
+
+    assert_eq!(Shape::TREE_DEPTH, 3);
+    assert_eq!(Shape::RADIUS_INDEX, Index::from_one(10).unwrap());
+    assert_eq!(Square::COLOR_INDEX, Shape::COLOR_INDEX);
+    assert_eq!(<Shape as bm_le::StableContainer>::field_position("radius"), Some(2));
+    let raw = DanglingRaw::from_leaked(root);
+    let subtree = raw.subtree(&mut db, Square::SIDE_INDEX).unwrap();
+    assert_eq!(subtree.metadata(), 0x42u16.into_tree(&mut db).unwrap());
+}
@@ -20,7 +20,12 @@ impl FromTree for bool {
     fn from_tree<DB: ReadBackend>(root: &ValueOf<DB::Construct>, db: &mut DB) -> Result<Self, Error<DB::Error>> where
         DB::Construct: CompatibleConstruct,
     {
-        Ok(u8::from_tree(root, db)? != 0)
+        match u8::from_tree(root, db)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ if db.strict_decoding() => Err(Error::NonCanonical),
+            _ => Ok(true),
+        }
     }
 }
 
@@ -49,6 +54,9 @@ macro_rules! impl_builtin_uint {
                     Value::End(value) => {
                         let mut bytes = Self::default().to_le_bytes();
                         let bytes_len = bytes.len();
+                        if db.strict_decoding() && value.0[bytes_len..].iter().any(|b| *b != 0) {
+                            return Err(Error::NonCanonical)
+                        }
                         bytes.copy_from_slice(&value.0[..bytes_len]);
 
                         Ok(Self::from_le_bytes(bytes))
@@ -138,7 +146,7 @@ impl<T> FromTree for Option<T> where
                     Ok(None)
                 },
                 1 => Ok(Some(T::from_tree(inner, db)?)),
-                _ => Err(Error::CorruptedDatabase),
+                _ => Err(Error::NonCanonical),
             }
         })
     }
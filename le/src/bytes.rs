@@ -0,0 +1,429 @@
+use typenum::Unsigned;
+use generic_array::{GenericArray, ArrayLength};
+use bm::{Error, ValueOf, Value, ReadBackend, WriteBackend, DanglingVector, Leak};
+use core::marker::PhantomData;
+use core::convert::TryFrom;
+use core::ops::{Deref, DerefMut};
+use alloc::vec::Vec;
+use crate::{IntoTree, FromTree, IntoCompactVectorChunks, CompatibleConstruct, End};
+use crate::elemental_variable::check_max_len;
+use crate::elemental_fixed::check_packed_padding;
+use crate::utils::{vector_tree, mix_in_length, decode_with_length};
+
+/// Number of bytes in a chunk.
+const BYTES_PER_CHUNK: usize = 32;
+
+/// Bytes reference, packed into chunks directly from the slice.
+pub(crate) struct PackedBytesRef<'a>(pub &'a [u8]);
+
+impl<'a> IntoCompactVectorChunks for PackedBytesRef<'a> {
+    fn into_compact_vector_chunks(
+        &self,
+        max_len: Option<usize>
+    ) -> (Vec<End>, Option<usize>) {
+        (self.0.chunks(BYTES_PER_CHUNK).map(|chunk| {
+            let mut ret = End::default();
+            ret.0.as_mut()[..chunk.len()].copy_from_slice(chunk);
+            ret
+        }).collect(), max_len.map(|max_len| (max_len + BYTES_PER_CHUNK - 1) / BYTES_PER_CHUNK))
+    }
+}
+
+pub(crate) fn bytes_tree<DB: WriteBackend>(
+    bytes: &[u8],
+    db: &mut DB,
+    max_len: usize
+) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+    DB::Construct: CompatibleConstruct,
+{
+    let (chunks, max_len) = PackedBytesRef(bytes).into_compact_vector_chunks(Some(max_len));
+    vector_tree(&chunks.into_iter().map(Value::End).collect::<Vec<_>>(), db, max_len)
+}
+
+pub(crate) fn bytes_from_tree<DB: ReadBackend>(
+    root: &ValueOf<DB::Construct>,
+    db: &mut DB,
+    len: usize,
+    max_len: usize
+) -> Result<Vec<u8>, Error<DB::Error>> where
+    DB::Construct: CompatibleConstruct,
+{
+    if db.strict_decoding() {
+        check_packed_padding(root, db, len, Some(max_len))?;
+    }
+
+    let vector = DanglingVector::<DB::Construct>::from_leaked(
+        (root.clone(), (len + BYTES_PER_CHUNK - 1) / BYTES_PER_CHUNK, Some((max_len + BYTES_PER_CHUNK - 1) / BYTES_PER_CHUNK))
+    );
+
+    let mut ret = Vec::with_capacity(len);
+    for i in 0..(len + BYTES_PER_CHUNK - 1) / BYTES_PER_CHUNK {
+        let chunk = vector.get(db, i)?;
+        let chunk_len = core::cmp::min(BYTES_PER_CHUNK, len - i * BYTES_PER_CHUNK);
+        ret.extend_from_slice(&chunk.as_ref()[..chunk_len]);
+    }
+
+    Ok(ret)
+}
+
+/// Byte vector with fixed length, merkleized as packed bytes.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct ByteVector<N: ArrayLength<u8>>(pub GenericArray<u8, N>);
+
+impl<N: ArrayLength<u8>> Deref for ByteVector<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<N: ArrayLength<u8>> DerefMut for ByteVector<N> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl<N: ArrayLength<u8>> AsRef<[u8]> for ByteVector<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<N: ArrayLength<u8>> From<GenericArray<u8, N>> for ByteVector<N> {
+    fn from(array: GenericArray<u8, N>) -> Self {
+        Self(array)
+    }
+}
+
+impl<N: ArrayLength<u8>> TryFrom<Vec<u8>> for ByteVector<N> {
+    type Error = Vec<u8>;
+
+    fn try_from(vec: Vec<u8>) -> Result<Self, Vec<u8>> {
+        if vec.len() != N::to_usize() {
+            return Err(vec)
+        }
+
+        Ok(Self(GenericArray::clone_from_slice(&vec)))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<N: ArrayLength<u8>> serde::Serialize for ByteVector<N> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&hex::encode(&self.0))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, N: ArrayLength<u8>> serde::Deserialize<'de> for ByteVector<N> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where
+        D: serde::Deserializer<'de>,
+    {
+        let vec = deserializer.deserialize_str(hex::Visitor)?;
+        Self::try_from(vec).map_err(|_| <D::Error as serde::de::Error>::custom("invalid length"))
+    }
+}
+
+#[cfg(feature = "parity-codec")]
+impl<N: ArrayLength<u8>> parity_codec::Encode for ByteVector<N> {
+    fn encode_to<W: parity_codec::Output>(&self, dest: &mut W) {
+        dest.write(&self.0)
+    }
+}
+
+#[cfg(feature = "parity-codec")]
+impl<N: ArrayLength<u8>> parity_codec::Decode for ByteVector<N> {
+    fn decode<I: parity_codec::Input>(input: &mut I) -> Option<Self> {
+        let mut ret = GenericArray::default();
+        if input.read(&mut ret) != N::to_usize() {
+            return None
+        }
+        Some(Self(ret))
+    }
+}
+
+impl<N: ArrayLength<u8>> IntoTree for ByteVector<N> {
+    fn into_tree<DB: WriteBackend>(&self, db: &mut DB) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        bytes_tree(&self.0, db, N::to_usize())
+    }
+}
+
+impl<N: ArrayLength<u8>> FromTree for ByteVector<N> {
+    fn from_tree<DB: ReadBackend>(root: &ValueOf<DB::Construct>, db: &mut DB) -> Result<Self, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        let value = bytes_from_tree(root, db, N::to_usize(), N::to_usize())?;
+        Ok(Self(GenericArray::clone_from_slice(&value)))
+    }
+}
+
+/// Byte list with maximum length, merkleized as packed bytes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ByteList<ML>(Vec<u8>, PhantomData<ML>);
+
+impl<ML> Deref for ByteList<ML> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<ML> AsRef<[u8]> for ByteList<ML> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<ML> Default for ByteList<ML> {
+    fn default() -> Self {
+        Self(Vec::new(), PhantomData)
+    }
+}
+
+impl<ML> From<ByteList<ML>> for Vec<u8> {
+    fn from(value: ByteList<ML>) -> Self {
+        value.0
+    }
+}
+
+impl<ML: Unsigned> ByteList<ML> {
+    /// Create an empty byte list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum length of the byte list.
+    pub fn max_len() -> usize {
+        ML::to_usize()
+    }
+
+    /// Push a byte, returning it back if the list is already full.
+    pub fn try_push(&mut self, value: u8) -> Result<(), u8> {
+        if self.0.len() >= ML::to_usize() {
+            return Err(value)
+        }
+
+        self.0.push(value);
+        Ok(())
+    }
+}
+
+impl<ML: Unsigned> TryFrom<Vec<u8>> for ByteList<ML> {
+    type Error = Vec<u8>;
+
+    fn try_from(vec: Vec<u8>) -> Result<Self, Vec<u8>> {
+        if vec.len() > ML::to_usize() {
+            return Err(vec)
+        }
+
+        Ok(Self(vec, PhantomData))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<ML: Unsigned> serde::Serialize for ByteList<ML> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&hex::encode(&self.0))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, ML: Unsigned> serde::Deserialize<'de> for ByteList<ML> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where
+        D: serde::Deserializer<'de>,
+    {
+        let vec = deserializer.deserialize_str(hex::Visitor)?;
+        Self::try_from(vec).map_err(|_| <D::Error as serde::de::Error>::custom("invalid length"))
+    }
+}
+
+#[cfg(feature = "parity-codec")]
+impl<N: Unsigned> parity_codec::Encode for ByteList<N> {
+    fn encode_to<W: parity_codec::Output>(&self, dest: &mut W) {
+        self.0.encode_to(dest)
+    }
+}
+
+#[cfg(feature = "parity-codec")]
+impl<N: Unsigned> parity_codec::Decode for ByteList<N> {
+    fn decode<I: parity_codec::Input>(input: &mut I) -> Option<Self> {
+        let decoded = Vec::<u8>::decode(input)?;
+        Self::try_from(decoded).ok()
+    }
+}
+
+impl<ML: Unsigned> IntoTree for ByteList<ML> {
+    fn into_tree<DB: WriteBackend>(&self, db: &mut DB) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        check_max_len(self.0.len(), Some(ML::to_usize()))?;
+        mix_in_length(&bytes_tree(&self.0, db, ML::to_usize())?, db, self.0.len())
+    }
+}
+
+impl<ML: Unsigned> FromTree for ByteList<ML> {
+    fn from_tree<DB: ReadBackend>(root: &ValueOf<DB::Construct>, db: &mut DB) -> Result<Self, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        let (vector_root, len) = decode_with_length::<ValueOf<DB::Construct>, _>(root, db)?;
+        if len > ML::to_usize() {
+            return Err(Error::NonCanonical)
+        }
+
+        Ok(Self(bytes_from_tree(&vector_root, db, len, ML::to_usize())?, PhantomData))
+    }
+}
+
+#[cfg(feature = "serde")]
+mod hex {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use core::fmt;
+
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    /// Encode bytes as a `0x` prefixed hex string.
+    pub fn encode(bytes: &[u8]) -> String {
+        let mut ret = String::with_capacity(2 + bytes.len() * 2);
+        ret.push_str("0x");
+        for byte in bytes {
+            ret.push(DIGITS[(byte >> 4) as usize] as char);
+            ret.push(DIGITS[(byte & 0x0f) as usize] as char);
+        }
+        ret
+    }
+
+    fn digit(c: u8) -> Option<u8> {
+        match c {
+            b'0'..=b'9' => Some(c - b'0'),
+            b'a'..=b'f' => Some(c - b'a' + 10),
+            b'A'..=b'F' => Some(c - b'A' + 10),
+            _ => None,
+        }
+    }
+
+    /// Decode a hex string, with optional `0x` prefix.
+    pub fn decode(s: &str) -> Option<Vec<u8>> {
+        let s = s.strip_prefix("0x").unwrap_or(s).as_bytes();
+        if s.len() % 2 != 0 {
+            return None
+        }
+
+        s.chunks(2).map(|pair| Some((digit(pair[0])? << 4) | digit(pair[1])?)).collect()
+    }
+
+    /// Visitor of hex strings.
+    pub struct Visitor;
+
+    impl<'de> serde::de::Visitor<'de> for Visitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "a hex string")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Vec<u8>, E> {
+            decode(value).ok_or_else(|| E::custom("invalid hex string"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DigestConstruct, Encode, Decode, DecodeError, tree_root};
+
+    use bm::InMemoryBackend;
+    use primitive_types::H256;
+    use sha2::{Digest, Sha256};
+    use typenum::{U4, U32, U33, U64};
+
+    fn h(a: &[u8], b: &[u8]) -> H256 {
+        let mut hash = Sha256::new();
+        hash.input(a);
+        hash.input(b);
+        H256::from_slice(hash.result().as_slice())
+    }
+
+    fn chunk(data: &[u8]) -> [u8; 32] {
+        let mut ret = [0u8; 32];
+        ret[..data.len()].copy_from_slice(data);
+        ret
+    }
+
+    #[test]
+    fn test_byte_vector() {
+        let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+        let value = ByteVector::<U33>::try_from((1..34).collect::<Vec<u8>>()).unwrap();
+        assert_eq!(tree_root::<Sha256, _>(&value),
+                   h(&(1..33).collect::<Vec<u8>>(), &chunk(&[33])));
+        assert_eq!(tree_root::<Sha256, _>(&value),
+                   tree_root::<Sha256, _>(&crate::Compact(value.0)));
+
+        let root = value.into_tree(&mut db).unwrap();
+        assert_eq!(ByteVector::<U33>::from_tree(&root, &mut db).unwrap(), value);
+
+        assert_eq!(value.encode(), (1..34).collect::<Vec<u8>>());
+        assert_eq!(ByteVector::<U33>::decode(&value.encode()), Ok(value));
+        assert_eq!(ByteVector::<U33>::decode(&[0; 32]), Err(DecodeError::InvalidLength));
+        assert!(ByteVector::<U33>::try_from(vec![0; 32]).is_err());
+    }
+
+    #[test]
+    fn test_byte_list() {
+        let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+        let value = ByteList::<U32>::try_from(vec![1, 2, 3]).unwrap();
+        assert_eq!(tree_root::<Sha256, _>(&value), h(&chunk(&[1, 2, 3]), &chunk(&[3])));
+
+        let value = ByteList::<U64>::try_from((0..40).collect::<Vec<u8>>()).unwrap();
+        assert_eq!(tree_root::<Sha256, _>(&value),
+                   tree_root::<Sha256, _>(&crate::Compact(crate::MaxVec::<u8, U64>::try_from(
+                       (0..40).collect::<Vec<u8>>()
+                   ).unwrap())));
+
+        let root = value.into_tree(&mut db).unwrap();
+        assert_eq!(ByteList::<U64>::from_tree(&root, &mut db).unwrap(), value);
+        assert!(matches!(ByteList::<U32>::from_tree(&root, &mut db), Err(Error::NonCanonical)));
+
+        assert_eq!(value.encode(), (0..40).collect::<Vec<u8>>());
+        assert_eq!(ByteList::<U64>::decode(&value.encode()), Ok(value));
+        assert_eq!(ByteList::<U4>::decode(&[0; 5]), Err(DecodeError::ListTooLong));
+    }
+
+    #[test]
+    fn test_checked() {
+        let mut bytes = ByteList::<U4>::try_from(vec![1, 2, 3]).unwrap();
+        assert_eq!(bytes.try_push(4), Ok(()));
+        assert_eq!(bytes.try_push(5), Err(5));
+        assert_eq!(&bytes[..], &[1, 2, 3, 4]);
+        assert!(ByteList::<U4>::try_from(vec![0; 5]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        use serde::Deserialize;
+        use serde::de::IntoDeserializer;
+        use serde::de::value::{Error as ValueError, StrDeserializer};
+
+        fn de(s: &str) -> StrDeserializer<'_, ValueError> {
+            s.into_deserializer()
+        }
+
+        assert_eq!(hex::encode(&[0x01, 0xab, 0xff]), "0x01abff");
+        assert_eq!(ByteList::<U4>::deserialize(de("0x01AbfF")).unwrap().as_ref(), &[0x01, 0xab, 0xff]);
+        assert_eq!(ByteVector::<U4>::deserialize(de("01020304")).unwrap().as_ref(), &[1, 2, 3, 4]);
+        assert!(ByteList::<U4>::deserialize(de("0x0102030405")).is_err());
+        assert!(ByteVector::<U4>::deserialize(de("0x010203")).is_err());
+        assert!(ByteList::<U4>::deserialize(de("0x010")).is_err());
+        assert!(ByteList::<U4>::deserialize(de("0xzz")).is_err());
+    }
+}
@@ -0,0 +1,543 @@
+use bm::{ValueOf, WriteBackend, Error, Value, Construct};
+use bm::utils::required_depth;
+use primitive_types::{H256, H512, U256};
+use generic_array::{GenericArray, ArrayLength};
+use vecarray::VecArray;
+use typenum::Unsigned;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+
+use crate::{IntoTree, IntoCompactVectorChunks, ElementalFixedVecRef, ElementalVariableVecRef,
+            IntoCompactListTree, MaxVec, ByteVector, ByteList, Compact, CompactRef,
+            CompatibleConstruct, Intermediate, End};
+use crate::elemental_variable::check_max_len;
+use crate::bytes::PackedBytesRef;
+
+/// Cache of a previous tree root computation. It remembers every layer of
+/// a vector tree, and the caches of its children, so that the next
+/// computation only hashes paths whose leaves changed. A cache must always
+/// be used with the same construct.
+#[derive(Debug, Clone, Default)]
+pub struct TreeHashCache {
+    depth: usize,
+    layers: Vec<Vec<Value<Intermediate, End>>>,
+    children: Vec<TreeHashCache>,
+}
+
+impl TreeHashCache {
+    /// Create a new empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the cache of the child at index, creating it if needed.
+    pub fn child(&mut self, index: usize) -> &mut TreeHashCache {
+        if self.children.len() <= index {
+            self.children.resize_with(index + 1, Default::default);
+        }
+        &mut self.children[index]
+    }
+
+    /// Serialize a vector, the same as `utils::vector_tree`, but only
+    /// hashing nodes whose children changed since the last computation.
+    /// Nodes reused from the cache are inserted again without hashing, so
+    /// that the tree is complete even if the backend dropped them.
+    pub fn vector_tree<DB: WriteBackend>(
+        &mut self,
+        values: &[ValueOf<DB::Construct>],
+        db: &mut DB,
+        max_len: Option<usize>
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        let total_depth = required_depth(max_len.unwrap_or(values.len()));
+        if total_depth != self.depth {
+            self.depth = total_depth;
+            self.layers.clear();
+        }
+
+        let mut layers = Vec::with_capacity(total_depth + 1);
+        let mut current = values.to_vec();
+        for depth_to_bottom in 0..total_depth {
+            let empty = if current.len() % 2 == 1 {
+                Some(<DB::Construct as Construct>::empty_at(db, depth_to_bottom)?)
+            } else {
+                None
+            };
+            let old = self.layers.get(depth_to_bottom);
+            let old_parents = self.layers.get(depth_to_bottom + 1);
+
+            let mut next = Vec::new();
+            let mut dirty = Vec::new();
+            let mut pairs = Vec::new();
+            for (i, chunk) in current.chunks(2).enumerate() {
+                let reused = match (old, old_parents) {
+                    (Some(old), Some(old_parents)) if
+                        old.get(2 * i) == chunk.first() && old.get(2 * i + 1) == chunk.get(1) =>
+                        old_parents.get(i).cloned(),
+                    _ => None,
+                };
+
+                let left = chunk[0].clone();
+                let right = chunk.get(1).cloned().or_else(|| empty.clone())
+                    .expect("Empty value is set when there is an odd number of values; qed");
+                match reused {
+                    Some(Value::Intermediate(key)) => {
+                        db.insert(key, (left, right))?;
+                        next.push(Value::Intermediate(key));
+                    },
+                    _ => {
+                        dirty.push(i);
+                        pairs.push((left, right));
+                        next.push(Value::End(Default::default()));
+                    },
+                }
+            }
+
+            let keys = <DB::Construct as Construct>::intermediate_of_batch(&pairs);
+            for ((i, key), pair) in dirty.into_iter().zip(keys).zip(pairs) {
+                db.insert(key, pair)?;
+                next[i] = Value::Intermediate(key);
+            }
+
+            layers.push(current);
+            current = next;
+        }
+
+        let root = if current.is_empty() {
+            <DB::Construct as Construct>::empty_at(db, total_depth)?
+        } else {
+            current[0].clone()
+        };
+        layers.push(current);
+        self.layers = layers;
+
+        Ok(root)
+    }
+
+    /// Mix in length, the same as `utils::mix_in_length`, but caching the
+    /// mixed in node.
+    pub fn mix_in_length<DB: WriteBackend>(
+        &mut self,
+        root: &ValueOf<DB::Construct>,
+        db: &mut DB,
+        len: usize
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        let len = U256::from(len).into_tree(db)?;
+        self.vector_tree(&[root.clone(), len], db, None)
+    }
+
+    /// Serialize a composite vector, using the cache of each item.
+    pub fn composite_vector_tree<T: IntoTreeCached, DB: WriteBackend>(
+        &mut self,
+        values: &[T],
+        db: &mut DB,
+        max_len: Option<usize>
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        self.children.truncate(values.len());
+        let mut roots = Vec::with_capacity(values.len());
+        for (i, value) in values.iter().enumerate() {
+            roots.push(value.into_tree_cached(db, self.child(i))?);
+        }
+        self.vector_tree(&roots, db, max_len)
+    }
+
+    /// Serialize a compact vector, caching its packed chunks.
+    pub fn compact_vector_tree<T: IntoCompactVectorChunks, DB: WriteBackend>(
+        &mut self,
+        value: &T,
+        db: &mut DB,
+        max_len: Option<usize>
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        let (chunks, max_len) = value.into_compact_vector_chunks(max_len);
+        self.vector_tree(&chunks.into_iter().map(Value::End).collect::<Vec<_>>(), db, max_len)
+    }
+}
+
+/// Traits for type converting into a tree structure, reusing the cache of
+/// the previous conversion.
+pub trait IntoTreeCached: IntoTree {
+    /// Convert this type into merkle tree, writing nodes into the given
+    /// database, and updating the cache. By default the cache is not
+    /// used, which is suitable for values fitting in a single chunk.
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        _cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        self.into_tree(db)
+    }
+}
+
+impl IntoTreeCached for bool { }
+impl IntoTreeCached for u8 { }
+impl IntoTreeCached for u16 { }
+impl IntoTreeCached for u32 { }
+impl IntoTreeCached for u64 { }
+impl IntoTreeCached for u128 { }
+impl IntoTreeCached for U256 { }
+impl IntoTreeCached for H256 { }
+impl IntoTreeCached for H512 { }
+impl IntoTreeCached for () { }
+impl IntoTreeCached for Value<Intermediate, End> { }
+impl<T: IntoTree> IntoTreeCached for Option<T> { }
+
+impl<T: IntoTreeCached> IntoTreeCached for Box<T> {
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        self.as_ref().into_tree_cached(db, cache)
+    }
+}
+
+impl<T: IntoTreeCached> IntoTreeCached for Vec<T> {
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        let root = cache.child(0).composite_vector_tree(self, db, None)?;
+        cache.mix_in_length(&root, db, self.len())
+    }
+}
+
+impl<T: IntoTreeCached, ML: Unsigned> IntoTreeCached for MaxVec<T, ML> {
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        check_max_len(self.len(), Some(ML::to_usize()))?;
+        let root = cache.child(0).composite_vector_tree(self, db, Some(ML::to_usize()))?;
+        cache.mix_in_length(&root, db, self.len())
+    }
+}
+
+impl<T: IntoTreeCached, L: ArrayLength<T>> IntoTreeCached for GenericArray<T, L> {
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        cache.composite_vector_tree(self, db, None)
+    }
+}
+
+impl<T: IntoTreeCached, L: Unsigned> IntoTreeCached for VecArray<T, L> {
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        cache.composite_vector_tree(self, db, None)
+    }
+}
+
+macro_rules! impl_fixed_array {
+    ( $( $n:expr ),* ) => { $(
+        impl<T: IntoTreeCached> IntoTreeCached for [T; $n] {
+            fn into_tree_cached<DB: WriteBackend>(
+                &self,
+                db: &mut DB,
+                cache: &mut TreeHashCache
+            ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+                DB::Construct: CompatibleConstruct,
+            {
+                cache.composite_vector_tree(&self[..], db, None)
+            }
+        }
+    )* }
+}
+
+impl_fixed_array!(1, 2, 3, 4, 5, 6, 7, 8,
+                  9, 10, 11, 12, 13, 14, 15, 16,
+                  17, 18, 19, 20, 21, 22, 23, 24,
+                  25, 26, 27, 28, 29, 30, 31, 32);
+
+impl<'a, T, L: ArrayLength<T>> IntoTreeCached for CompactRef<'a, GenericArray<T, L>> where
+    for<'b> ElementalFixedVecRef<'b, T>: IntoCompactVectorChunks,
+{
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        cache.compact_vector_tree(&ElementalFixedVecRef(self.0), db, None)
+    }
+}
+
+impl<T, L: ArrayLength<T>> IntoTreeCached for Compact<GenericArray<T, L>> where
+    for<'b> ElementalFixedVecRef<'b, T>: IntoCompactVectorChunks,
+{
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        cache.compact_vector_tree(&ElementalFixedVecRef(&self.0), db, None)
+    }
+}
+
+impl<'a, T, L: Unsigned> IntoTreeCached for CompactRef<'a, VecArray<T, L>> where
+    for<'b> ElementalFixedVecRef<'b, T>: IntoCompactVectorChunks,
+{
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        cache.compact_vector_tree(&ElementalFixedVecRef(self.0), db, None)
+    }
+}
+
+impl<T, L: Unsigned> IntoTreeCached for Compact<VecArray<T, L>> where
+    for<'b> ElementalFixedVecRef<'b, T>: IntoCompactVectorChunks,
+{
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        cache.compact_vector_tree(&ElementalFixedVecRef(&self.0), db, None)
+    }
+}
+
+impl<'a, T, ML: Unsigned> IntoTreeCached for CompactRef<'a, MaxVec<T, ML>> where
+    for<'b> ElementalFixedVecRef<'b, T>: IntoCompactVectorChunks,
+    for<'b> ElementalVariableVecRef<'b, T>: IntoCompactListTree,
+{
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        check_max_len(self.0.len(), Some(ML::to_usize()))?;
+        let root = cache.child(0).compact_vector_tree(&ElementalFixedVecRef(self.0), db, Some(ML::to_usize()))?;
+        cache.mix_in_length(&root, db, self.0.len())
+    }
+}
+
+impl<T, ML: Unsigned> IntoTreeCached for Compact<MaxVec<T, ML>> where
+    for<'b> ElementalFixedVecRef<'b, T>: IntoCompactVectorChunks,
+    for<'b> ElementalVariableVecRef<'b, T>: IntoCompactListTree,
+{
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        CompactRef(&self.0).into_tree_cached(db, cache)
+    }
+}
+
+impl<N: ArrayLength<u8>> IntoTreeCached for ByteVector<N> {
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        cache.compact_vector_tree(&PackedBytesRef(self), db, Some(N::to_usize()))
+    }
+}
+
+impl<ML: Unsigned> IntoTreeCached for ByteList<ML> {
+    fn into_tree_cached<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        cache: &mut TreeHashCache
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        check_max_len(self.len(), Some(ML::to_usize()))?;
+        let root = cache.child(0).compact_vector_tree(&PackedBytesRef(self), db, Some(ML::to_usize()))?;
+        cache.mix_in_length(&root, db, self.len())
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($i:ident => $t:ident),+) => {
+        impl<$($t: IntoTreeCached),+> IntoTreeCached for ($($t),+) {
+            fn into_tree_cached<DB: WriteBackend>(
+                &self,
+                db: &mut DB,
+                cache: &mut TreeHashCache
+            ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+                DB::Construct: CompatibleConstruct,
+            {
+                let ($($i),+) = self;
+                let mut vector = Vec::new();
+                $({
+                    let index = vector.len();
+                    vector.push($i.into_tree_cached(db, cache.child(index))?);
+                })+
+                cache.vector_tree(&vector, db, None)
+            }
+        }
+    }
+}
+
+impl_tuple!(a => A, b => B);
+impl_tuple!(a => A, b => B, c => C);
+impl_tuple!(a => A, b => B, c => C, d => D);
+impl_tuple!(a => A, b => B, c => C, d => D, e => E);
+impl_tuple!(a => A, b => B, c => C, d => D, e => E, f => F);
+impl_tuple!(a => A, b => B, c => C, d => D, e => E, f => F, g => G);
+impl_tuple!(a => A, b => B, c => C, d => D, e => E, f => F, g => G, h => H);
+impl_tuple!(a => A, b => B, c => C, d => D, e => E, f => F, g => G, h => H, i => I);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DigestConstruct, FromTree, tree_root, tree_root_cached};
+    use bm::{Backend, ReadBackend, NoopBackend, NoopBackendError, InMemoryBackend};
+    use std::collections::HashSet;
+    use sha2::Sha256;
+    use core::convert::TryFrom;
+
+    type Construct = DigestConstruct<Sha256>;
+
+    /// Backend counting inserts of new nodes.
+    #[derive(Default)]
+    struct CountingBackend(NoopBackend<Construct>, usize, HashSet<Intermediate>);
+
+    impl Backend for CountingBackend {
+        type Construct = Construct;
+        type Error = NoopBackendError;
+    }
+
+    impl ReadBackend for CountingBackend {
+        fn get(&mut self, key: &Intermediate) -> Result<(ValueOf<Construct>, ValueOf<Construct>), Self::Error> {
+            self.0.get(key)
+        }
+    }
+
+    impl WriteBackend for CountingBackend {
+        fn rootify(&mut self, key: &Intermediate) -> Result<(), Self::Error> {
+            self.0.rootify(key)
+        }
+
+        fn unrootify(&mut self, key: &Intermediate) -> Result<(), Self::Error> {
+            self.0.unrootify(key)
+        }
+
+        fn insert(
+            &mut self,
+            key: Intermediate,
+            value: (ValueOf<Construct>, ValueOf<Construct>)
+        ) -> Result<(), Self::Error> {
+            if self.2.insert(key) {
+                self.1 += 1;
+            }
+            self.0.insert(key, value)
+        }
+
+        fn virtual_empty_depth(&self) -> usize {
+            self.0.virtual_empty_depth()
+        }
+    }
+
+    #[test]
+    fn test_vector_tree_matches() {
+        let mut cache = TreeHashCache::new();
+        let mut values = Vec::new();
+        for i in 0..40u64 {
+            values.push(i * 3);
+            assert_eq!(tree_root_cached::<Sha256, _>(&values, &mut cache),
+                       tree_root::<Sha256, _>(&values));
+        }
+
+        for i in (0..40).step_by(7) {
+            values[i] = 0;
+            assert_eq!(tree_root_cached::<Sha256, _>(&values, &mut cache),
+                       tree_root::<Sha256, _>(&values));
+        }
+
+        while !values.is_empty() {
+            values.pop();
+            assert_eq!(tree_root_cached::<Sha256, _>(&values, &mut cache),
+                       tree_root::<Sha256, _>(&values));
+        }
+    }
+
+    #[test]
+    fn test_compact_chunks() {
+        let mut cache = TreeHashCache::new();
+        let mut values = Compact(MaxVec::<u64, typenum::U1024>::try_from((0..1000).collect::<Vec<_>>()).unwrap());
+        let mut db = CountingBackend::default();
+
+        let root = values.into_tree_cached(&mut db, &mut cache).unwrap();
+        assert_eq!(H256::from_slice(root.as_ref()), tree_root::<Sha256, _>(&values));
+
+        db.1 = 0;
+        values.0.as_mut()[500] = 1;
+        let root = values.into_tree_cached(&mut db, &mut cache).unwrap();
+        assert_eq!(H256::from_slice(root.as_ref()), tree_root::<Sha256, _>(&values));
+        assert_eq!(db.1, 8 + 1);
+    }
+
+    #[test]
+    fn test_composite_items() {
+        let mut cache = TreeHashCache::new();
+        let mut values = (0..16u64).map(|i| vec![i; 40]).collect::<Vec<_>>();
+        let mut db = CountingBackend::default();
+
+        values.into_tree_cached(&mut db, &mut cache).unwrap();
+        db.1 = 0;
+        values[3][39] = 0;
+        let root = values.into_tree_cached(&mut db, &mut cache).unwrap();
+        assert_eq!(H256::from_slice(root.as_ref()), tree_root::<Sha256, _>(&values));
+        assert_eq!(db.1, (6 + 1) + (4 + 1));
+    }
+
+    #[test]
+    fn test_dropped_root() {
+        let mut cache = TreeHashCache::new();
+        let mut values = (0..40u64).collect::<Vec<_>>();
+        let mut db = InMemoryBackend::<Construct>::default();
+
+        let root = values.into_tree_cached(&mut db, &mut cache).unwrap();
+        let key = root.intermediate().unwrap();
+        db.rootify(&key).unwrap();
+        db.unrootify(&key).unwrap();
+        assert!(db.as_ref().is_empty());
+
+        values[5] = 1;
+        let root = values.into_tree_cached(&mut db, &mut cache).unwrap();
+        assert_eq!(H256::from_slice(root.as_ref()), tree_root::<Sha256, _>(&values));
+        assert_eq!(Vec::<u64>::from_tree(&root, &mut db).unwrap(), values);
+    }
+}
@@ -0,0 +1,827 @@
+use primitive_types::{H256, H512, U256};
+use generic_array::{GenericArray, ArrayLength};
+use vecarray::VecArray;
+use typenum::Unsigned;
+use core::convert::TryFrom;
+use alloc::vec;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+
+use crate::{MaxVec, ByteVector, ByteList, Compact, CompactRef};
+
+/// Number of bytes of an offset of variable-size items.
+pub const BYTES_PER_LENGTH_OFFSET: usize = 4;
+
+/// Error of ssz decoding.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DecodeError {
+    /// Length of the bytes is invalid for the type.
+    InvalidLength,
+    /// Offset of a variable-size item is invalid.
+    InvalidOffset,
+    /// Bytes do not represent a valid value.
+    InvalidValue,
+    /// Selector of a union is invalid.
+    InvalidSelector,
+    /// List is longer than its maximum length.
+    ListTooLong,
+}
+
+/// Traits for type encoding into ssz bytes.
+pub trait Encode {
+    /// Whether the encoding of this type has fixed size.
+    fn is_fixed_len() -> bool;
+    /// Length taken in the fixed part of a containing value. This is the
+    /// encoding length for fixed-size types, and the offset length for
+    /// variable-size types.
+    fn fixed_len() -> usize;
+    /// Append the encoding of this value to the output.
+    fn encode_to(&self, out: &mut Vec<u8>);
+
+    /// Encode this value into bytes.
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_to(&mut out);
+        out
+    }
+}
+
+/// Traits for type decoding from ssz bytes.
+pub trait Decode: Sized {
+    /// Whether the encoding of this type has fixed size.
+    fn is_fixed_len() -> bool;
+    /// Length taken in the fixed part of a containing value. This is the
+    /// encoding length for fixed-size types, and the offset length for
+    /// variable-size types.
+    fn fixed_len() -> usize;
+    /// Decode this value from bytes, which must be consumed entirely.
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError>;
+}
+
+fn encode_offset(offset: usize, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(offset as u32).to_le_bytes());
+}
+
+fn decode_offset(bytes: &[u8], position: usize) -> Result<usize, DecodeError> {
+    let bytes = bytes.get(position..(position + BYTES_PER_LENGTH_OFFSET))
+        .ok_or(DecodeError::InvalidOffset)?;
+    let mut raw = [0u8; BYTES_PER_LENGTH_OFFSET];
+    raw.copy_from_slice(bytes);
+    Ok(u32::from_le_bytes(raw) as usize)
+}
+
+/// Builder of a container encoding, putting fixed-size items and offsets
+/// in the fixed part, and variable-size items after it.
+pub struct ContainerEncoder<'a> {
+    out: &'a mut Vec<u8>,
+    fixed_part_len: usize,
+    variable: Vec<u8>,
+}
+
+impl<'a> ContainerEncoder<'a> {
+    /// Create a new encoder, with the total length of the fixed part.
+    pub fn new(out: &'a mut Vec<u8>, fixed_part_len: usize) -> Self {
+        Self { out, fixed_part_len, variable: Vec::new() }
+    }
+
+    /// Append an item to the container.
+    pub fn append<T: Encode + ?Sized>(&mut self, value: &T) {
+        if T::is_fixed_len() {
+            value.encode_to(self.out);
+        } else {
+            encode_offset(self.fixed_part_len + self.variable.len(), self.out);
+            value.encode_to(&mut self.variable);
+        }
+    }
+
+    /// Finish the encoding, appending all variable-size items.
+    pub fn finish(self) {
+        self.out.extend_from_slice(&self.variable);
+    }
+}
+
+/// Builder of a container decoding. Items are registered in order, and
+/// offsets are validated before any item is decoded.
+pub struct ContainerDecoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    items: Vec<&'a [u8]>,
+    offsets: Vec<(usize, usize)>,
+}
+
+impl<'a> ContainerDecoder<'a> {
+    /// Create a new decoder over the container bytes.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0, items: Vec::new(), offsets: Vec::new() }
+    }
+
+    /// Register the type of the next item.
+    pub fn register<T: Decode>(&mut self) -> Result<(), DecodeError> {
+        if T::is_fixed_len() {
+            let len = T::fixed_len();
+            let item = self.bytes.get(self.position..(self.position + len))
+                .ok_or(DecodeError::InvalidLength)?;
+            self.items.push(item);
+            self.position += len;
+        } else {
+            let offset = decode_offset(self.bytes, self.position)?;
+            self.offsets.push((self.items.len(), offset));
+            self.items.push(&[]);
+            self.position += BYTES_PER_LENGTH_OFFSET;
+        }
+        Ok(())
+    }
+
+    /// Validate offsets, and return the items to be decoded.
+    pub fn finish(mut self) -> Result<ContainerItems<'a>, DecodeError> {
+        match self.offsets.first() {
+            None if self.position != self.bytes.len() => return Err(DecodeError::InvalidLength),
+            Some((_, first)) if *first != self.position => return Err(DecodeError::InvalidOffset),
+            _ => (),
+        }
+
+        for i in 0..self.offsets.len() {
+            let (index, start) = self.offsets[i];
+            let end = self.offsets.get(i + 1).map(|o| o.1).unwrap_or(self.bytes.len());
+            if start > end || end > self.bytes.len() {
+                return Err(DecodeError::InvalidOffset)
+            }
+            self.items[index] = &self.bytes[start..end];
+        }
+
+        Ok(ContainerItems { items: self.items.into_iter() })
+    }
+}
+
+/// Validated items of a container decoding.
+pub struct ContainerItems<'a> {
+    items: alloc::vec::IntoIter<&'a [u8]>,
+}
+
+impl<'a> ContainerItems<'a> {
+    /// Decode the next item.
+    pub fn decode_next<T: Decode>(&mut self) -> Result<T, DecodeError> {
+        T::decode(self.items.next().ok_or(DecodeError::InvalidLength)?)
+    }
+
+    /// Decode the next item as a list of at most `max_len` values. The
+    /// length is checked before any value is decoded.
+    pub fn decode_next_list<T: Decode>(&mut self, max_len: usize) -> Result<Vec<T>, DecodeError> {
+        decode_sequence(self.items.next().ok_or(DecodeError::InvalidLength)?, Some(max_len))
+    }
+
+    /// Decode the next item as a compact list of at most `max_len`
+    /// values. The length is checked before any value is decoded.
+    pub fn decode_next_compact_list<T: CompactElement>(
+        &mut self,
+        max_len: usize
+    ) -> Result<Vec<T>, DecodeError> {
+        T::decode_compact_list(self.items.next().ok_or(DecodeError::InvalidLength)?, Some(max_len))
+    }
+}
+
+fn encode_sequence<T: Encode>(values: &[T], out: &mut Vec<u8>) {
+    if T::is_fixed_len() {
+        for value in values {
+            value.encode_to(out);
+        }
+    } else {
+        let mut encoder = ContainerEncoder::new(out, values.len() * BYTES_PER_LENGTH_OFFSET);
+        for value in values {
+            encoder.append(value);
+        }
+        encoder.finish();
+    }
+}
+
+fn decode_sequence<T: Decode>(
+    bytes: &[u8],
+    max_len: Option<usize>
+) -> Result<Vec<T>, DecodeError> {
+    let check_len = |len: usize| match max_len {
+        Some(max_len) if len > max_len => Err(DecodeError::ListTooLong),
+        _ => Ok(len),
+    };
+
+    if T::is_fixed_len() {
+        let len = T::fixed_len();
+        if len == 0 || bytes.len() % len != 0 {
+            return Err(DecodeError::InvalidLength)
+        }
+        check_len(bytes.len() / len)?;
+        bytes.chunks(len).map(T::decode).collect()
+    } else {
+        if bytes.is_empty() {
+            return Ok(Vec::new())
+        }
+
+        let first = decode_offset(bytes, 0)?;
+        if first == 0 || first % BYTES_PER_LENGTH_OFFSET != 0 {
+            return Err(DecodeError::InvalidOffset)
+        }
+        let len = check_len(first / BYTES_PER_LENGTH_OFFSET)?;
+
+        let mut decoder = ContainerDecoder::new(bytes);
+        for _ in 0..len {
+            decoder.register::<T>()?;
+        }
+        let mut items = decoder.finish()?;
+        (0..len).map(|_| items.decode_next()).collect()
+    }
+}
+
+fn sequence_fixed_len(is_item_fixed_len: bool, item_fixed_len: usize, len: usize) -> usize {
+    if is_item_fixed_len {
+        item_fixed_len * len
+    } else {
+        BYTES_PER_LENGTH_OFFSET
+    }
+}
+
+macro_rules! impl_fixed {
+    ( $t:ty, $len:expr, $encode:expr, $decode:expr ) => {
+        impl Encode for $t {
+            fn is_fixed_len() -> bool { true }
+            fn fixed_len() -> usize { $len }
+
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                let encode: fn(&$t, &mut Vec<u8>) = $encode;
+                encode(self, out)
+            }
+        }
+
+        impl Decode for $t {
+            fn is_fixed_len() -> bool { true }
+            fn fixed_len() -> usize { $len }
+
+            fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+                if bytes.len() != $len {
+                    return Err(DecodeError::InvalidLength)
+                }
+                let decode: fn(&[u8]) -> Result<$t, DecodeError> = $decode;
+                decode(bytes)
+            }
+        }
+    }
+}
+
+macro_rules! impl_uint {
+    ( $t:ty ) => {
+        impl_fixed!($t, core::mem::size_of::<$t>(),
+                    |value, out| out.extend_from_slice(&value.to_le_bytes()),
+                    |bytes| {
+                        let mut raw = <$t>::default().to_le_bytes();
+                        raw.copy_from_slice(bytes);
+                        Ok(<$t>::from_le_bytes(raw))
+                    });
+    }
+}
+
+impl_uint!(u8);
+impl_uint!(u16);
+impl_uint!(u32);
+impl_uint!(u64);
+impl_uint!(u128);
+
+impl_fixed!(bool, 1, |value, out| out.push(*value as u8), |bytes| match bytes[0] {
+    0 => Ok(false),
+    1 => Ok(true),
+    _ => Err(DecodeError::InvalidValue),
+});
+
+impl_fixed!(U256, 32, |value, out| {
+    let mut raw = [0u8; 32];
+    value.to_little_endian(&mut raw);
+    out.extend_from_slice(&raw);
+}, |bytes| Ok(U256::from_little_endian(bytes)));
+
+impl_fixed!(H256, 32, |value, out| out.extend_from_slice(value.as_ref()),
+            |bytes| Ok(H256::from_slice(bytes)));
+
+impl_fixed!(H512, 64, |value, out| out.extend_from_slice(value.as_ref()),
+            |bytes| Ok(H512::from_slice(bytes)));
+
+impl_fixed!((), 0, |_, _| (), |_| Ok(()));
+
+impl<T: Encode, L: ArrayLength<T>> Encode for GenericArray<T, L> {
+    fn is_fixed_len() -> bool { T::is_fixed_len() }
+    fn fixed_len() -> usize { sequence_fixed_len(T::is_fixed_len(), T::fixed_len(), L::to_usize()) }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_sequence(&self[..], out)
+    }
+}
+
+impl<T: Decode, L: ArrayLength<T>> Decode for GenericArray<T, L> {
+    fn is_fixed_len() -> bool { T::is_fixed_len() }
+    fn fixed_len() -> usize { sequence_fixed_len(T::is_fixed_len(), T::fixed_len(), L::to_usize()) }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        GenericArray::from_exact_iter(decode_sequence::<T>(bytes, None)?)
+            .ok_or(DecodeError::InvalidLength)
+    }
+}
+
+impl<T: Encode, L: Unsigned> Encode for VecArray<T, L> {
+    fn is_fixed_len() -> bool { T::is_fixed_len() }
+    fn fixed_len() -> usize { sequence_fixed_len(T::is_fixed_len(), T::fixed_len(), L::to_usize()) }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_sequence(&self[..], out)
+    }
+}
+
+impl<T: Decode, L: Unsigned> Decode for VecArray<T, L> {
+    fn is_fixed_len() -> bool { T::is_fixed_len() }
+    fn fixed_len() -> usize { sequence_fixed_len(T::is_fixed_len(), T::fixed_len(), L::to_usize()) }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        VecArray::try_from(decode_sequence::<T>(bytes, None)?).map_err(|_| DecodeError::InvalidLength)
+    }
+}
+
+macro_rules! impl_fixed_array {
+    ( $( $n:expr ),* ) => { $(
+        impl<T: Encode> Encode for [T; $n] {
+            fn is_fixed_len() -> bool { T::is_fixed_len() }
+            fn fixed_len() -> usize { sequence_fixed_len(T::is_fixed_len(), T::fixed_len(), $n) }
+
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                encode_sequence(&self[..], out)
+            }
+        }
+
+        impl<T: Decode + Default + Copy> Decode for [T; $n] {
+            fn is_fixed_len() -> bool { T::is_fixed_len() }
+            fn fixed_len() -> usize { sequence_fixed_len(T::is_fixed_len(), T::fixed_len(), $n) }
+
+            fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+                let value = decode_sequence::<T>(bytes, None)?;
+                if value.len() != $n {
+                    return Err(DecodeError::InvalidLength)
+                }
+                let mut ret = [T::default(); $n];
+                ret.copy_from_slice(&value);
+                Ok(ret)
+            }
+        }
+    )* }
+}
+
+impl_fixed_array!(1, 2, 3, 4, 5, 6, 7, 8,
+                  9, 10, 11, 12, 13, 14, 15, 16,
+                  17, 18, 19, 20, 21, 22, 23, 24,
+                  25, 26, 27, 28, 29, 30, 31, 32);
+
+impl<T: Encode> Encode for Vec<T> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_sequence(&self[..], out)
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        decode_sequence(bytes, None)
+    }
+}
+
+impl<T: Encode, ML: Unsigned> Encode for MaxVec<T, ML> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_sequence(&self[..], out)
+    }
+}
+
+impl<T: Decode, ML: Unsigned> Decode for MaxVec<T, ML> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let values = decode_sequence::<T>(bytes, Some(ML::to_usize()))?;
+        MaxVec::try_from(values).map_err(|_| DecodeError::ListTooLong)
+    }
+}
+
+impl<N: ArrayLength<u8>> Encode for ByteVector<N> {
+    fn is_fixed_len() -> bool { true }
+    fn fixed_len() -> usize { N::to_usize() }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self)
+    }
+}
+
+impl<N: ArrayLength<u8>> Decode for ByteVector<N> {
+    fn is_fixed_len() -> bool { true }
+    fn fixed_len() -> usize { N::to_usize() }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() != N::to_usize() {
+            return Err(DecodeError::InvalidLength)
+        }
+        Ok(ByteVector(GenericArray::clone_from_slice(bytes)))
+    }
+}
+
+impl<ML: Unsigned> Encode for ByteList<ML> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self)
+    }
+}
+
+impl<ML: Unsigned> Decode for ByteList<ML> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        ByteList::try_from(bytes.to_vec()).map_err(|_| DecodeError::ListTooLong)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode_to(out);
+            },
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match bytes.split_first() {
+            Some((0, [])) => Ok(None),
+            Some((0, _)) => Err(DecodeError::InvalidLength),
+            Some((1, rest)) => Ok(Some(T::decode(rest)?)),
+            Some(_) => Err(DecodeError::InvalidSelector),
+            None => Err(DecodeError::InvalidLength),
+        }
+    }
+}
+
+impl<T: Encode> Encode for Box<T> {
+    fn is_fixed_len() -> bool { T::is_fixed_len() }
+    fn fixed_len() -> usize { T::fixed_len() }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.as_ref().encode_to(out)
+    }
+}
+
+impl<T: Decode> Decode for Box<T> {
+    fn is_fixed_len() -> bool { T::is_fixed_len() }
+    fn fixed_len() -> usize { T::fixed_len() }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(Box::new(T::decode(bytes)?))
+    }
+}
+
+/// Packed element of compact sequences. Bits are packed into bytes, and
+/// other basic values are laid out as they are.
+pub trait CompactElement: Encode + Decode {
+    /// Encode a compact vector of the elements.
+    fn encode_compact_vector(values: &[Self], out: &mut Vec<u8>) {
+        encode_sequence(values, out)
+    }
+    /// Decode a compact vector of the elements, with given length.
+    fn decode_compact_vector(bytes: &[u8], len: usize) -> Result<Vec<Self>, DecodeError> {
+        let value = decode_sequence(bytes, None)?;
+        if value.len() != len {
+            return Err(DecodeError::InvalidLength)
+        }
+        Ok(value)
+    }
+    /// Encoding length of a compact vector of the elements.
+    fn compact_vector_len(len: usize) -> usize {
+        <Self as Encode>::fixed_len() * len
+    }
+    /// Encode a compact list of the elements.
+    fn encode_compact_list(values: &[Self], out: &mut Vec<u8>) {
+        encode_sequence(values, out)
+    }
+    /// Decode a compact list of the elements. If `max_len` is given, the
+    /// length is checked before any element is decoded.
+    fn decode_compact_list(bytes: &[u8], max_len: Option<usize>) -> Result<Vec<Self>, DecodeError> {
+        decode_sequence(bytes, max_len)
+    }
+}
+
+impl CompactElement for u8 { }
+impl CompactElement for u16 { }
+impl CompactElement for u32 { }
+impl CompactElement for u64 { }
+impl CompactElement for u128 { }
+impl CompactElement for U256 { }
+
+impl CompactElement for bool {
+    fn encode_compact_vector(values: &[Self], out: &mut Vec<u8>) {
+        let mut bytes = vec![0u8; (values.len() + 7) / 8];
+        for (i, value) in values.iter().enumerate() {
+            bytes[i / 8] |= (*value as u8) << (i % 8);
+        }
+        out.extend_from_slice(&bytes);
+    }
+
+    fn decode_compact_vector(bytes: &[u8], len: usize) -> Result<Vec<Self>, DecodeError> {
+        if bytes.len() != (len + 7) / 8 {
+            return Err(DecodeError::InvalidLength)
+        }
+
+        let mut ret = Vec::with_capacity(len);
+        for i in 0..(bytes.len() * 8) {
+            let bit = bytes[i / 8] & (1 << (i % 8)) != 0;
+            if i < len {
+                ret.push(bit);
+            } else if bit {
+                return Err(DecodeError::InvalidValue)
+            }
+        }
+        Ok(ret)
+    }
+
+    fn compact_vector_len(len: usize) -> usize {
+        (len + 7) / 8
+    }
+
+    fn encode_compact_list(values: &[Self], out: &mut Vec<u8>) {
+        let mut bytes = vec![0u8; values.len() / 8 + 1];
+        for (i, value) in values.iter().enumerate() {
+            bytes[i / 8] |= (*value as u8) << (i % 8);
+        }
+        bytes[values.len() / 8] |= 1 << (values.len() % 8);
+        out.extend_from_slice(&bytes);
+    }
+
+    fn decode_compact_list(bytes: &[u8], max_len: Option<usize>) -> Result<Vec<Self>, DecodeError> {
+        let last = *bytes.last().ok_or(DecodeError::InvalidLength)?;
+        if last == 0 {
+            return Err(DecodeError::InvalidValue)
+        }
+
+        let len = (bytes.len() - 1) * 8 + (7 - last.leading_zeros() as usize);
+        match max_len {
+            Some(max_len) if len > max_len => return Err(DecodeError::ListTooLong),
+            _ => (),
+        }
+        let mut ret = Vec::with_capacity(len);
+        for i in 0..len {
+            ret.push(bytes[i / 8] & (1 << (i % 8)) != 0);
+        }
+        Ok(ret)
+    }
+}
+
+macro_rules! impl_compact_vector {
+    ( $t:ty, $( $g:tt )* ) => {
+        impl<$( $g )*> Encode for $t {
+            fn is_fixed_len() -> bool { true }
+            fn fixed_len() -> usize { T::compact_vector_len(L::to_usize()) }
+
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                T::encode_compact_vector(&self.0[..], out)
+            }
+        }
+    }
+}
+
+impl_compact_vector!(CompactRef<'a, GenericArray<T, L>>, 'a, T: CompactElement, L: ArrayLength<T>);
+impl_compact_vector!(Compact<GenericArray<T, L>>, T: CompactElement, L: ArrayLength<T>);
+impl_compact_vector!(CompactRef<'a, VecArray<T, L>>, 'a, T: CompactElement, L: Unsigned);
+impl_compact_vector!(Compact<VecArray<T, L>>, T: CompactElement, L: Unsigned);
+
+impl<T: CompactElement, L: ArrayLength<T>> Decode for Compact<GenericArray<T, L>> {
+    fn is_fixed_len() -> bool { true }
+    fn fixed_len() -> usize { T::compact_vector_len(L::to_usize()) }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let value = T::decode_compact_vector(bytes, L::to_usize())?;
+        Ok(Compact(GenericArray::from_exact_iter(value).ok_or(DecodeError::InvalidLength)?))
+    }
+}
+
+impl<T: CompactElement, L: Unsigned> Decode for Compact<VecArray<T, L>> {
+    fn is_fixed_len() -> bool { true }
+    fn fixed_len() -> usize { T::compact_vector_len(L::to_usize()) }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let value = T::decode_compact_vector(bytes, L::to_usize())?;
+        Ok(Compact(VecArray::try_from(value).map_err(|_| DecodeError::InvalidLength)?))
+    }
+}
+
+impl<'a, T: CompactElement, ML: Unsigned> Encode for CompactRef<'a, MaxVec<T, ML>> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        T::encode_compact_list(&self.0[..], out)
+    }
+}
+
+impl<T: CompactElement, ML: Unsigned> Encode for Compact<MaxVec<T, ML>> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        T::encode_compact_list(&self.0[..], out)
+    }
+}
+
+impl<T: CompactElement, ML: Unsigned> Decode for Compact<MaxVec<T, ML>> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let value = MaxVec::try_from(T::decode_compact_list(bytes, Some(ML::to_usize()))?)
+            .map_err(|_| DecodeError::ListTooLong)?;
+        Ok(Compact(value))
+    }
+}
+
+impl<'a, T: CompactElement> Encode for CompactRef<'a, Vec<T>> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        T::encode_compact_list(&self.0[..], out)
+    }
+}
+
+impl<T: CompactElement> Encode for Compact<Vec<T>> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        T::encode_compact_list(&self.0[..], out)
+    }
+}
+
+impl<T: CompactElement> Decode for Compact<Vec<T>> {
+    fn is_fixed_len() -> bool { false }
+    fn fixed_len() -> usize { BYTES_PER_LENGTH_OFFSET }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(Compact(T::decode_compact_list(bytes, None)?))
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($i:ident => $t:ident),+) => {
+        impl<$($t: Encode),+> Encode for ($($t),+) {
+            fn is_fixed_len() -> bool { $($t::is_fixed_len())&&+ }
+            fn fixed_len() -> usize {
+                if Self::is_fixed_len() {
+                    0 $(+ $t::fixed_len())+
+                } else {
+                    BYTES_PER_LENGTH_OFFSET
+                }
+            }
+
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                let ($($i),+) = self;
+                let mut encoder = ContainerEncoder::new(out, 0 $(+ $t::fixed_len())+);
+                $(encoder.append($i);)+
+                encoder.finish();
+            }
+        }
+
+        impl<$($t: Decode),+> Decode for ($($t),+) {
+            fn is_fixed_len() -> bool { $($t::is_fixed_len())&&+ }
+            fn fixed_len() -> usize {
+                if Self::is_fixed_len() {
+                    0 $(+ $t::fixed_len())+
+                } else {
+                    BYTES_PER_LENGTH_OFFSET
+                }
+            }
+
+            fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+                let mut decoder = ContainerDecoder::new(bytes);
+                $(decoder.register::<$t>()?;)+
+                let mut items = decoder.finish()?;
+                Ok(($(items.decode_next::<$t>()?),+))
+            }
+        }
+    }
+}
+
+impl_tuple!(a => A, b => B);
+impl_tuple!(a => A, b => B, c => C);
+impl_tuple!(a => A, b => B, c => C, d => D);
+impl_tuple!(a => A, b => B, c => C, d => D, e => E);
+impl_tuple!(a => A, b => B, c => C, d => D, e => E, f => F);
+impl_tuple!(a => A, b => B, c => C, d => D, e => E, f => F, g => G);
+impl_tuple!(a => A, b => B, c => C, d => D, e => E, f => F, g => G, h => H);
+impl_tuple!(a => A, b => B, c => C, d => D, e => E, f => F, g => G, h => H, i => I);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use typenum::{U4, U5, U8, U9};
+
+    #[test]
+    fn test_basic() {
+        assert_eq!(0x01020304u32.encode(), vec![0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(u16::decode(&[0x01, 0x02]), Ok(0x0201));
+        assert_eq!(u16::decode(&[0x01]), Err(DecodeError::InvalidLength));
+        assert_eq!(true.encode(), vec![1]);
+        assert_eq!(bool::decode(&[2]), Err(DecodeError::InvalidValue));
+        assert_eq!(U256::decode(&U256::from(12345).encode()), Ok(U256::from(12345)));
+    }
+
+    #[test]
+    fn test_variable() {
+        let value: Vec<Vec<u16>> = vec![vec![1, 2], vec![], vec![3]];
+        let encoded = value.encode();
+        assert_eq!(encoded, vec![12, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0, 1, 0, 2, 0, 3, 0]);
+        assert_eq!(Vec::<Vec<u16>>::decode(&encoded), Ok(value));
+
+        assert_eq!(Vec::<Vec<u16>>::decode(&[12, 0, 0, 0, 18, 0, 0, 0, 16, 0, 0, 0, 1, 0, 2, 0, 3, 0]),
+                   Err(DecodeError::InvalidOffset));
+        assert_eq!(Vec::<Vec<u16>>::decode(&[8, 0, 0, 0, 16, 0, 0, 0]),
+                   Err(DecodeError::InvalidOffset));
+        assert_eq!(Vec::<Vec<u16>>::decode(&[3, 0, 0]), Err(DecodeError::InvalidOffset));
+    }
+
+    #[test]
+    fn test_tuple_and_option() {
+        let value = (1u8, vec![2u8, 3], Some(4u16));
+        let encoded = value.encode();
+        assert_eq!(encoded, vec![1, 9, 0, 0, 0, 11, 0, 0, 0, 2, 3, 1, 4, 0]);
+        assert_eq!(<(u8, Vec<u8>, Option<u16>)>::decode(&encoded), Ok(value));
+
+        assert_eq!(None::<u16>.encode(), vec![0]);
+        assert_eq!(Option::<u16>::decode(&[0, 1]), Err(DecodeError::InvalidLength));
+        assert_eq!(Option::<u16>::decode(&[2, 1, 0]), Err(DecodeError::InvalidSelector));
+    }
+
+    #[test]
+    fn test_max_vec() {
+        let value = MaxVec::<u8, U4>::try_from(vec![1, 2, 3, 4]).unwrap();
+        assert_eq!(MaxVec::<u8, U4>::decode(&value.encode()), Ok(value));
+        assert_eq!(MaxVec::<u8, U4>::decode(&[1, 2, 3, 4, 5]), Err(DecodeError::ListTooLong));
+        assert_eq!(MaxVec::<u16, U4>::decode(&[1, 2, 3]), Err(DecodeError::InvalidLength));
+
+        // The length is read from the offsets, so the malformed last item is
+        // never decoded.
+        let nested = [12, 0, 0, 0, 12, 0, 0, 0, 200, 0, 0, 0];
+        assert_eq!(MaxVec::<Vec<u8>, typenum::U2>::decode(&nested), Err(DecodeError::ListTooLong));
+        assert_eq!(MaxVec::<Vec<u8>, typenum::U3>::decode(&nested), Err(DecodeError::InvalidOffset));
+    }
+
+    #[test]
+    fn test_bits() {
+        let bits = [true, false, true, true, false, false, false, false, true];
+
+        let vector = GenericArray::<bool, U9>::from_exact_iter(bits.iter().cloned()).unwrap();
+        assert_eq!(CompactRef(&vector).encode(), vec![0b0000_1101, 0b0000_0001]);
+        assert_eq!(Compact::<GenericArray<bool, U9>>::decode(&[0b0000_1101, 0b0000_0001]).unwrap().0,
+                   vector);
+        assert_eq!(Compact::<GenericArray<bool, U9>>::decode(&[0b0000_1101, 0b0000_0011]),
+                   Err(DecodeError::InvalidValue));
+        assert_eq!(<Compact<GenericArray<bool, U9>> as Decode>::fixed_len(), 2);
+
+        let list = MaxVec::<bool, U9>::try_from(bits.to_vec()).unwrap();
+        assert_eq!(CompactRef(&list).encode(), vec![0b0000_1101, 0b0000_0011]);
+        assert_eq!(Compact::<MaxVec<bool, U9>>::decode(&[0b0000_1101, 0b0000_0011]).unwrap().0,
+                   list);
+        assert_eq!(CompactRef(&MaxVec::<bool, U8>::default()).encode(), vec![0b0000_0001]);
+        assert_eq!(Compact::<MaxVec<bool, U8>>::decode(&[0b0000_1101, 0b0000_0011]),
+                   Err(DecodeError::ListTooLong));
+        assert_eq!(Compact::<MaxVec<bool, U8>>::decode(&[0b0000_1101, 0]),
+                   Err(DecodeError::InvalidValue));
+        assert_eq!(Compact::<MaxVec<bool, U8>>::decode(&[]), Err(DecodeError::InvalidLength));
+
+        let packed = MaxVec::<u16, U5>::try_from(vec![1, 2]).unwrap();
+        assert_eq!(CompactRef(&packed).encode(), vec![1, 0, 2, 0]);
+        assert_eq!(Compact::<MaxVec<u16, U5>>::decode(&[1, 0, 2, 0]).unwrap().0, packed);
+        assert_eq!(Compact::<MaxVec<u16, typenum::U1>>::decode(&[1, 0, 2, 0]),
+                   Err(DecodeError::ListTooLong));
+        assert_eq!(u16::decode_compact_list(&[1, 0, 2, 0], Some(1)), Err(DecodeError::ListTooLong));
+        assert_eq!(bool::decode_compact_list(&[0b0000_1101, 0b0000_0011], Some(8)),
+                   Err(DecodeError::ListTooLong));
+        assert_eq!(bool::decode_compact_list(&[0b0000_1101, 0b0000_0011], Some(9)),
+                   Ok(bits.to_vec()));
+    }
+}
@@ -0,0 +1,430 @@
+use bm::{Error, ValueOf, Value, ReadBackend, WriteBackend, DanglingVector, Leak};
+use primitive_types::U256;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use alloc::vec;
+use crate::{CompatibleConstruct, End};
+use crate::bytes::{bytes_tree, bytes_from_tree};
+use crate::utils::{vector_tree, mix_in_length, mix_in_type, decode_with_length, decode_with_type,
+                   check_empty_tail, decode_nested};
+
+/// Runtime description of a ssz type.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Schema {
+    /// Unsigned integer with the given number of bits, from 8 to 256.
+    Uint(usize),
+    /// Boolean.
+    Bool,
+    /// Vector of the given length.
+    Vector(Box<Schema>, usize),
+    /// List with the given maximum length.
+    List(Box<Schema>, usize),
+    /// Container with the given fields.
+    Container(Vec<Schema>),
+    /// Union with the given options. Only the first option may be
+    /// `None`.
+    Union(Vec<Option<Schema>>),
+    /// Bitvector of the given length.
+    Bitvector(usize),
+    /// Bitlist with the given maximum length.
+    Bitlist(usize),
+}
+
+impl Schema {
+    /// Whether the type is basic, so that its vectors and lists are packed.
+    pub fn is_basic(&self) -> bool {
+        matches!(self, Schema::Uint(_) | Schema::Bool)
+    }
+
+    fn basic_size<E>(&self) -> Result<usize, Error<E>> {
+        match self {
+            Schema::Uint(bits) if bits % 8 == 0 && (1..=32).contains(&(bits / 8)) &&
+                (bits / 8).is_power_of_two() => Ok(bits / 8),
+            Schema::Bool => Ok(1),
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+}
+
+/// Value whose shape is described by a runtime `Schema`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DynamicValue {
+    /// Unsigned integer.
+    Uint(U256),
+    /// Boolean.
+    Bool(bool),
+    /// Vector.
+    Vector(Vec<DynamicValue>),
+    /// List.
+    List(Vec<DynamicValue>),
+    /// Container.
+    Container(Vec<DynamicValue>),
+    /// Union with selector and value.
+    Union(usize, Option<Box<DynamicValue>>),
+    /// Bitvector.
+    Bitvector(Vec<bool>),
+    /// Bitlist.
+    Bitlist(Vec<bool>),
+}
+
+fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    let mut bytes = vec![0u8; (bits.len() + 7) / 8];
+    for (i, bit) in bits.iter().enumerate() {
+        bytes[i / 8] |= (*bit as u8) << (i % 8);
+    }
+    bytes
+}
+
+fn unpack_bits<E>(bytes: &[u8], len: usize, strict: bool) -> Result<Vec<bool>, Error<E>> {
+    if strict && len % 8 != 0 && bytes[len / 8] >> (len % 8) != 0 {
+        return Err(Error::NonCanonical)
+    }
+
+    Ok((0..len).map(|i| bytes[i / 8] & (1 << (i % 8)) != 0).collect())
+}
+
+impl DynamicValue {
+    fn basic_bytes<E>(&self, schema: &Schema) -> Result<Vec<u8>, Error<E>> {
+        let size = schema.basic_size()?;
+        match (self, schema) {
+            (DynamicValue::Uint(value), Schema::Uint(_)) => {
+                if value.bits() > size * 8 {
+                    return Err(Error::InvalidParameter)
+                }
+                let mut bytes = [0u8; 32];
+                value.to_little_endian(&mut bytes);
+                Ok(bytes[..size].to_vec())
+            },
+            (DynamicValue::Bool(value), Schema::Bool) => Ok([*value as u8].to_vec()),
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+
+    fn from_basic_bytes<E>(schema: &Schema, bytes: &[u8], strict: bool) -> Result<Self, Error<E>> {
+        match schema {
+            Schema::Uint(_) => Ok(DynamicValue::Uint(U256::from_little_endian(bytes))),
+            Schema::Bool => match bytes[0] {
+                0 => Ok(DynamicValue::Bool(false)),
+                1 => Ok(DynamicValue::Bool(true)),
+                _ if strict => Err(Error::NonCanonical),
+                _ => Ok(DynamicValue::Bool(true)),
+            },
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+
+    fn packed_tree<DB: WriteBackend>(
+        values: &[DynamicValue],
+        schema: &Schema,
+        db: &mut DB,
+        max_len: usize,
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        let size = schema.basic_size()?;
+        let mut bytes = Vec::new();
+        for value in values {
+            bytes.append(&mut value.basic_bytes(schema)?);
+        }
+        bytes_tree(&bytes, db, max_len * size)
+    }
+
+    fn composite_tree<DB: WriteBackend>(
+        values: &[DynamicValue],
+        schema: &Schema,
+        db: &mut DB,
+        max_len: Option<usize>,
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        let roots = values.iter()
+            .map(|value| value.into_tree(schema, db))
+            .collect::<Result<Vec<_>, _>>()?;
+        vector_tree(&roots, db, max_len)
+    }
+
+    /// Convert this value into merkle tree against the schema, writing
+    /// nodes into the given database.
+    pub fn into_tree<DB: WriteBackend>(
+        &self,
+        schema: &Schema,
+        db: &mut DB,
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        match (self, schema) {
+            (DynamicValue::Uint(_), Schema::Uint(_)) | (DynamicValue::Bool(_), Schema::Bool) => {
+                let mut chunk = End::default();
+                let bytes = self.basic_bytes(schema)?;
+                chunk.0.as_mut()[..bytes.len()].copy_from_slice(&bytes);
+                Ok(Value::End(chunk))
+            },
+            (DynamicValue::Vector(values), Schema::Vector(inner, len)) => {
+                if values.len() != *len {
+                    return Err(Error::InvalidParameter)
+                }
+
+                if inner.is_basic() {
+                    Self::packed_tree(values, inner, db, *len)
+                } else {
+                    Self::composite_tree(values, inner, db, None)
+                }
+            },
+            (DynamicValue::List(values), Schema::List(inner, max_len)) => {
+                if values.len() > *max_len {
+                    return Err(Error::InvalidParameter)
+                }
+
+                let vector_root = if inner.is_basic() {
+                    Self::packed_tree(values, inner, db, *max_len)?
+                } else {
+                    Self::composite_tree(values, inner, db, Some(*max_len))?
+                };
+                mix_in_length(&vector_root, db, values.len())
+            },
+            (DynamicValue::Container(values), Schema::Container(fields)) => {
+                if values.len() != fields.len() {
+                    return Err(Error::InvalidParameter)
+                }
+
+                let roots = values.iter().zip(fields.iter())
+                    .map(|(value, field)| value.into_tree(field, db))
+                    .collect::<Result<Vec<_>, _>>()?;
+                vector_tree(&roots, db, None)
+            },
+            (DynamicValue::Union(selector, value), Schema::Union(options)) => {
+                let value_root = match (value, options.get(*selector)) {
+                    (Some(value), Some(Some(option))) => value.into_tree(option, db)?,
+                    (None, Some(None)) => Value::End(Default::default()),
+                    _ => return Err(Error::InvalidParameter),
+                };
+                mix_in_type(&value_root, db, *selector)
+            },
+            (DynamicValue::Bitvector(bits), Schema::Bitvector(len)) => {
+                if bits.len() != *len {
+                    return Err(Error::InvalidParameter)
+                }
+
+                bytes_tree(&pack_bits(bits), db, (len + 7) / 8)
+            },
+            (DynamicValue::Bitlist(bits), Schema::Bitlist(max_len)) => {
+                if bits.len() > *max_len {
+                    return Err(Error::InvalidParameter)
+                }
+
+                let vector_root = bytes_tree(&pack_bits(bits), db, (max_len + 7) / 8)?;
+                mix_in_length(&vector_root, db, bits.len())
+            },
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+
+    fn from_packed_tree<DB: ReadBackend>(
+        schema: &Schema,
+        root: &ValueOf<DB::Construct>,
+        db: &mut DB,
+        len: usize,
+        max_len: usize,
+    ) -> Result<Vec<Self>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        let size = schema.basic_size()?;
+        let strict = db.strict_decoding();
+        let bytes = bytes_from_tree(root, db, len * size, max_len * size)?;
+        bytes.chunks(size)
+            .map(|chunk| Self::from_basic_bytes(schema, chunk, strict))
+            .collect()
+    }
+
+    fn from_composite_tree<'a, DB: ReadBackend, F>(
+        schema: F,
+        root: &ValueOf<DB::Construct>,
+        db: &mut DB,
+        len: usize,
+        max_len: Option<usize>,
+    ) -> Result<Vec<Self>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+        F: Fn(usize) -> &'a Schema,
+    {
+        if db.strict_decoding() {
+            check_empty_tail(root, db, len, max_len)?;
+        }
+
+        let vector = DanglingVector::<DB::Construct>::from_leaked(
+            (root.clone(), len, max_len)
+        );
+        let mut ret = Vec::new();
+        for i in 0..len {
+            let value = vector.get(db, i)?;
+            ret.push(decode_nested(db, |db| Self::from_tree(schema(i), &value, db))?);
+        }
+
+        Ok(ret)
+    }
+
+    /// Convert a merkle tree into a value against the schema.
+    pub fn from_tree<DB: ReadBackend>(
+        schema: &Schema,
+        root: &ValueOf<DB::Construct>,
+        db: &mut DB,
+    ) -> Result<Self, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        match schema {
+            Schema::Uint(_) | Schema::Bool => {
+                let size = schema.basic_size()?;
+                let chunk = match root {
+                    Value::End(chunk) => chunk,
+                    Value::Intermediate(_) => return Err(Error::CorruptedDatabase),
+                };
+                if db.strict_decoding() && chunk.0[size..].iter().any(|b| *b != 0) {
+                    return Err(Error::NonCanonical)
+                }
+
+                Self::from_basic_bytes(schema, &chunk.0[..size], db.strict_decoding())
+            },
+            Schema::Vector(inner, len) => {
+                let values = if inner.is_basic() {
+                    Self::from_packed_tree(inner, root, db, *len, *len)?
+                } else {
+                    Self::from_composite_tree(|_| inner.as_ref(), root, db, *len, None)?
+                };
+                Ok(DynamicValue::Vector(values))
+            },
+            Schema::List(inner, max_len) => {
+                let (vector_root, len) = decode_with_length::<ValueOf<DB::Construct>, _>(root, db)?;
+                if len > *max_len {
+                    return Err(Error::NonCanonical)
+                }
+
+                let values = if inner.is_basic() {
+                    Self::from_packed_tree(inner, &vector_root, db, len, *max_len)?
+                } else {
+                    Self::from_composite_tree(|_| inner.as_ref(), &vector_root, db, len, Some(*max_len))?
+                };
+                Ok(DynamicValue::List(values))
+            },
+            Schema::Container(fields) => {
+                let values = Self::from_composite_tree(|i| &fields[i], root, db, fields.len(), None)?;
+                Ok(DynamicValue::Container(values))
+            },
+            Schema::Union(options) => {
+                decode_with_type(root, db, |value_root, db, selector| {
+                    match options.get(selector) {
+                        Some(Some(option)) => Ok(DynamicValue::Union(
+                            selector,
+                            Some(Box::new(Self::from_tree(option, value_root, db)?)),
+                        )),
+                        Some(None) if value_root == &Value::End(Default::default()) =>
+                            Ok(DynamicValue::Union(selector, None)),
+                        _ => Err(Error::NonCanonical),
+                    }
+                })
+            },
+            Schema::Bitvector(len) => {
+                let strict = db.strict_decoding();
+                let bytes = bytes_from_tree(root, db, (len + 7) / 8, (len + 7) / 8)?;
+                Ok(DynamicValue::Bitvector(unpack_bits(&bytes, *len, strict)?))
+            },
+            Schema::Bitlist(max_len) => {
+                let (vector_root, len) = decode_with_length::<ValueOf<DB::Construct>, _>(root, db)?;
+                if len > *max_len {
+                    return Err(Error::NonCanonical)
+                }
+
+                let strict = db.strict_decoding();
+                let bytes = bytes_from_tree(&vector_root, db, (len + 7) / 8, (max_len + 7) / 8)?;
+                Ok(DynamicValue::Bitlist(unpack_bits(&bytes, len, strict)?))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IntoTree, FromTree, Compact, MaxVec, DigestConstruct, InMemoryBackend};
+    use generic_array::GenericArray;
+    use core::convert::TryFrom;
+    use core::fmt::Debug;
+    use sha2::Sha256;
+    use typenum::{U3, U4, U5, U10, U20};
+
+    fn assert_same<T: IntoTree + FromTree + Debug + PartialEq>(value: T, schema: Schema, dynamic: DynamicValue) {
+        let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+        let root = value.into_tree(&mut db).unwrap();
+        assert_eq!(dynamic.into_tree(&schema, &mut db).unwrap(), root);
+        assert_eq!(DynamicValue::from_tree(&schema, &root, &mut db).unwrap(), dynamic);
+        assert_eq!(T::from_tree(&root, &mut db).unwrap(), value);
+    }
+
+    fn uint(value: u64) -> DynamicValue {
+        DynamicValue::Uint(U256::from(value))
+    }
+
+    #[test]
+    fn test_basic() {
+        assert_same(0xabcdu16, Schema::Uint(16), uint(0xabcd));
+        assert_same(U256::from(12345), Schema::Uint(256), uint(12345));
+        assert_same(true, Schema::Bool, DynamicValue::Bool(true));
+    }
+
+    #[test]
+    fn test_composite() {
+        assert_same(
+            Compact(GenericArray::<u16, U3>::from([1, 2, 3])),
+            Schema::Vector(Box::new(Schema::Uint(16)), 3),
+            DynamicValue::Vector(vec![uint(1), uint(2), uint(3)]),
+        );
+        assert_same(
+            Compact(MaxVec::<u64, U5>::try_from(vec![1, 2, 3]).unwrap()),
+            Schema::List(Box::new(Schema::Uint(64)), 5),
+            DynamicValue::List(vec![uint(1), uint(2), uint(3)]),
+        );
+        assert_same(
+            MaxVec::<(u16, bool), U4>::try_from(vec![(1, true), (2, false)]).unwrap(),
+            Schema::List(Box::new(Schema::Container(vec![Schema::Uint(16), Schema::Bool])), 4),
+            DynamicValue::List(vec![
+                DynamicValue::Container(vec![uint(1), DynamicValue::Bool(true)]),
+                DynamicValue::Container(vec![uint(2), DynamicValue::Bool(false)]),
+            ]),
+        );
+        assert_same(
+            (7u32, Some(8u64)),
+            Schema::Container(vec![Schema::Uint(32), Schema::Union(vec![None, Some(Schema::Uint(64))])]),
+            DynamicValue::Container(vec![uint(7), DynamicValue::Union(1, Some(Box::new(uint(8))))]),
+        );
+        assert_same(
+            Option::<u64>::None,
+            Schema::Union(vec![None, Some(Schema::Uint(64))]),
+            DynamicValue::Union(0, None),
+        );
+    }
+
+    #[test]
+    fn test_bits() {
+        let bits = vec![true, false, true, true, false, false, true, false, true, true];
+        assert_same(
+            Compact(GenericArray::<bool, U10>::from_exact_iter(bits.clone()).unwrap()),
+            Schema::Bitvector(10),
+            DynamicValue::Bitvector(bits.clone()),
+        );
+        assert_same(
+            Compact(MaxVec::<bool, U20>::try_from(bits.clone()).unwrap()),
+            Schema::Bitlist(20),
+            DynamicValue::Bitlist(bits),
+        );
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+        assert!(matches!(uint(256).into_tree(&Schema::Uint(8), &mut db), Err(Error::InvalidParameter)));
+        assert!(matches!(uint(1).into_tree(&Schema::Uint(12), &mut db), Err(Error::InvalidParameter)));
+        assert!(matches!(DynamicValue::Bool(true).into_tree(&Schema::Uint(8), &mut db),
+                         Err(Error::InvalidParameter)));
+        assert!(matches!(DynamicValue::List(vec![uint(1); 3])
+                         .into_tree(&Schema::List(Box::new(Schema::Uint(8)), 2), &mut db),
+                         Err(Error::InvalidParameter)));
+    }
+}
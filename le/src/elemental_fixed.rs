@@ -1,5 +1,5 @@
 use bm::{ValueOf, ReadBackend, WriteBackend, Error, Value, DanglingPackedVector, DanglingVector, Leak, Sequence};
-use bm::utils::{vector_tree, host_len};
+use crate::utils::{vector_tree, host_len, check_empty_tail, decode_nested};
 use primitive_types::{H256, U256};
 use generic_array::GenericArray;
 use alloc::vec::Vec;
@@ -30,6 +30,17 @@ pub trait IntoCompactVectorTree {
         DB::Construct: CompatibleConstruct;
 }
 
+/// Traits for vector converting into packed chunks of a compact tree
+/// structure.
+pub trait IntoCompactVectorChunks {
+    /// Pack this vector into chunks, returning them together with the
+    /// maximum number of chunks for the maximum length specified.
+    fn into_compact_vector_chunks(
+        &self,
+        max_len: Option<usize>
+    ) -> (Vec<End>, Option<usize>);
+}
+
 /// Traits for vector converting from a composite tree structure.
 pub trait FromCompositeVectorTree: Sized {
     /// Convert this type from merkle tree, reading nodes from the
@@ -63,16 +74,28 @@ pub struct ElementalFixedVecRef<'a, T>(pub &'a [T]);
 /// Elemental `Vec` value. In ssz's definition, this is a basic "vector".
 pub struct ElementalFixedVec<T>(pub Vec<T>);
 
+impl<'a, T> IntoCompactVectorTree for ElementalFixedVecRef<'a, T> where
+    Self: IntoCompactVectorChunks,
+{
+    fn into_compact_vector_tree<DB: WriteBackend>(
+        &self,
+        db: &mut DB,
+        max_len: Option<usize>
+    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        let (chunks, max_len) = self.into_compact_vector_chunks(max_len);
+        vector_tree(&chunks.into_iter().map(Value::End).collect::<Vec<_>>(), db, max_len)
+    }
+}
+
 macro_rules! impl_builtin_fixed_uint_vector {
     ( $t:ty, $lt:ty ) => {
-        impl<'a> IntoCompactVectorTree for ElementalFixedVecRef<'a, $t> {
-            fn into_compact_vector_tree<DB: WriteBackend>(
+        impl<'a> IntoCompactVectorChunks for ElementalFixedVecRef<'a, $t> {
+            fn into_compact_vector_chunks(
                 &self,
-                db: &mut DB,
                 max_len: Option<usize>
-            ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
-                DB::Construct: CompatibleConstruct,
-            {
+            ) -> (Vec<End>, Option<usize>) {
                 let mut chunks: Vec<Vec<u8>> = Vec::new();
 
                 for value in self.0 {
@@ -90,9 +113,8 @@ macro_rules! impl_builtin_fixed_uint_vector {
                     }
                 }
 
-                vector_tree(&chunks.into_iter().map(|c| {
-                    Value::End(End(H256::from_slice(&c)))
-                }).collect::<Vec<_>>(), db, max_len.map(|max| host_len::<typenum::U32, $lt>(max)))
+                (chunks.into_iter().map(|c| End(H256::from_slice(&c))).collect(),
+                 max_len.map(|max| host_len::<typenum::U32, $lt>(max)))
             }
         }
 
@@ -105,6 +127,11 @@ macro_rules! impl_builtin_fixed_uint_vector {
             ) -> Result<Self, Error<DB::Error>> where
                 DB::Construct: CompatibleConstruct,
             {
+                if db.strict_decoding() {
+                    let size = <$lt as typenum::Unsigned>::to_usize();
+                    check_packed_padding(root, db, len * size, max_len.map(|max| max * size))?;
+                }
+
                 let packed = DanglingPackedVector::<DB::Construct, GenericArray<u8, $lt>, typenum::U32, $lt>::from_leaked(
                     (root.clone(), len, max_len)
                 );
@@ -129,19 +156,16 @@ impl_builtin_fixed_uint_vector!(u32, typenum::U4);
 impl_builtin_fixed_uint_vector!(u64, typenum::U8);
 impl_builtin_fixed_uint_vector!(u128, typenum::U16);
 
-impl<'a> IntoCompactVectorTree for ElementalFixedVecRef<'a, U256> {
-    fn into_compact_vector_tree<DB: WriteBackend>(
+impl<'a> IntoCompactVectorChunks for ElementalFixedVecRef<'a, U256> {
+    fn into_compact_vector_chunks(
         &self,
-        db: &mut DB,
         max_len: Option<usize>
-    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
-        DB::Construct: CompatibleConstruct,
-    {
-        vector_tree(&self.0.iter().map(|uint| {
+    ) -> (Vec<End>, Option<usize>) {
+        (self.0.iter().map(|uint| {
             let mut ret = End::default();
             uint.to_little_endian(&mut ret.0.as_mut());
-            Value::End(ret)
-        }).collect::<Vec<_>>(), db, max_len)
+            ret
+        }).collect(), max_len)
     }
 }
 
@@ -154,6 +178,10 @@ impl FromCompactVectorTree for ElementalFixedVec<U256> {
     ) -> Result<Self, Error<DB::Error>> where
         DB::Construct: CompatibleConstruct,
     {
+        if db.strict_decoding() {
+            check_empty_tail(root, db, len, max_len)?;
+        }
+
         let vector = DanglingVector::<DB::Construct>::from_leaked(
             (root.clone(), len, max_len)
         );
@@ -168,14 +196,11 @@ impl FromCompactVectorTree for ElementalFixedVec<U256> {
     }
 }
 
-impl<'a> IntoCompactVectorTree for ElementalFixedVecRef<'a, bool> {
-    fn into_compact_vector_tree<DB: WriteBackend>(
+impl<'a> IntoCompactVectorChunks for ElementalFixedVecRef<'a, bool> {
+    fn into_compact_vector_chunks(
         &self,
-        db: &mut DB,
         max_len: Option<usize>
-    ) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
-        DB::Construct: CompatibleConstruct,
-    {
+    ) -> (Vec<End>, Option<usize>) {
         let mut bytes = Vec::new();
         bytes.resize((self.0.len() + 7) / 8, 0u8);
 
@@ -183,7 +208,7 @@ impl<'a> IntoCompactVectorTree for ElementalFixedVecRef<'a, bool> {
             bytes[i / 8] |= (self.0[i] as u8) << (i % 8);
         }
 
-        ElementalFixedVecRef(&bytes).into_compact_vector_tree(db, max_len.map(|l| {
+        ElementalFixedVecRef(&bytes).into_compact_vector_chunks(max_len.map(|l| {
             (l + 7) / 8
         }))
     }
@@ -198,6 +223,10 @@ impl FromCompactVectorTree for ElementalFixedVec<bool> {
     ) -> Result<Self, Error<DB::Error>> where
         DB::Construct: CompatibleConstruct,
     {
+        if db.strict_decoding() {
+            check_packed_padding(root, db, (len + 7) / 8, max_len.map(|l| (l + 7) / 8))?;
+        }
+
         let packed = DanglingPackedVector::<DB::Construct, GenericArray<u8, typenum::U1>, typenum::U32, typenum::U1>::from_leaked(
             (root.clone(), (len + 7) / 8, max_len.map(|l| (l + 7) / 8))
         );
@@ -210,12 +239,41 @@ impl FromCompactVectorTree for ElementalFixedVec<bool> {
         for i in 0..len {
             ret.push(bytes[i / 8] & (1 << (i % 8)) != 0);
         }
-        // TODO: check to make sure rest of the bits are unset.
+        if db.strict_decoding() && len % 8 != 0 && bytes[len / 8] >> (len % 8) != 0 {
+            return Err(Error::NonCanonical)
+        }
 
         Ok(Self(ret))
     }
 }
 
+/// Check that a packed vector tree of `len` bytes has empty chunks after
+/// the last used one, and zero padding in the last used chunk.
+pub(crate) fn check_packed_padding<DB: ReadBackend>(
+    root: &ValueOf<DB::Construct>,
+    db: &mut DB,
+    len: usize,
+    max_len: Option<usize>
+) -> Result<(), Error<DB::Error>> where
+    DB::Construct: CompatibleConstruct,
+{
+    let chunks = host_len::<typenum::U32, typenum::U1>(len);
+    let max_chunks = max_len.map(host_len::<typenum::U32, typenum::U1>);
+    check_empty_tail(root, db, chunks, max_chunks)?;
+
+    if len % 32 != 0 {
+        let vector = DanglingVector::<DB::Construct>::from_leaked(
+            (root.clone(), chunks, max_chunks)
+        );
+        let last = vector.get(db, chunks - 1)?;
+        if last.as_ref()[(len % 32)..].iter().any(|b| *b != 0) {
+            return Err(Error::NonCanonical)
+        }
+    }
+
+    Ok(())
+}
+
 impl<'a, T> IntoCompositeVectorTree for ElementalFixedVecRef<'a, T> where
     T: IntoTree,
 {
@@ -242,6 +300,10 @@ fn from_composite_vector_tree<T, F, DB: ReadBackend>(
     DB::Construct: CompatibleConstruct,
     F: Fn(&ValueOf<DB::Construct>, &mut DB) -> Result<T, Error<DB::Error>>
 {
+    if db.strict_decoding() {
+        check_empty_tail(root, db, len, max_len)?;
+    }
+
     let vector = DanglingVector::<DB::Construct>::from_leaked(
         (root.clone(), len, max_len)
     );
@@ -249,7 +311,7 @@ fn from_composite_vector_tree<T, F, DB: ReadBackend>(
 
     for i in 0..len {
         let value = vector.get(db, i)?;
-        ret.push(f(&value, db)?);
+        ret.push(decode_nested(db, |db| f(&value, db))?);
     }
 
     Ok(ElementalFixedVec(ret))
@@ -62,6 +62,14 @@ pub struct ElementalVariableVecRef<'a, T>(pub &'a [T]);
 /// Variable `Vec` value. In `ssz`'s definition, this is a "list".
 pub struct ElementalVariableVec<T>(pub Vec<T>);
 
+/// Check that the list length does not exceed its maximum length.
+pub(crate) fn check_max_len<E>(len: usize, max_len: Option<usize>) -> Result<(), Error<E>> {
+    match max_len {
+        Some(max_len) if len > max_len => Err(Error::InvalidParameter),
+        _ => Ok(()),
+    }
+}
+
 macro_rules! impl_packed {
     ( $t:ty ) => {
         impl<'a> IntoCompactListTree for ElementalVariableVecRef<'a, $t> {
@@ -73,6 +81,7 @@ macro_rules! impl_packed {
                 DB::Construct: CompatibleConstruct,
             {
                 let len = self.0.len();
+                check_max_len(len, max_len)?;
 
                 mix_in_length(&ElementalFixedVecRef(&self.0).into_compact_vector_tree(db, max_len)?,
                               db, len)
@@ -100,6 +109,7 @@ impl<'a, T> IntoCompositeListTree for ElementalVariableVecRef<'a, T> where
         DB::Construct: CompatibleConstruct,
     {
         let len = self.0.len();
+        check_max_len(len, max_len)?;
 
         mix_in_length(&ElementalFixedVecRef(&self.0).into_composite_vector_tree(db, max_len)?,
                       db, len)
@@ -116,6 +126,9 @@ fn from_list_tree<T, F, DB: ReadBackend>(
     F: FnOnce(&ValueOf<DB::Construct>, &mut DB, usize, Option<usize>) -> Result<ElementalFixedVec<T>, Error<DB::Error>>
 {
     let (vector_root, len) = decode_with_length::<ValueOf<DB::Construct>, _>(root, db)?;
+    if max_len.map(|max_len| len > max_len).unwrap_or(false) {
+        return Err(Error::NonCanonical)
+    }
 
     let vector = f(
         &vector_root, db, len, max_len
@@ -1,5 +1,5 @@
 use bm::{ReadBackend, WriteBackend, ValueOf, Error, Value, DanglingVector, Leak};
-use bm::utils::vector_tree;
+use crate::utils::{vector_tree, check_empty_tail, decode_nested};
 use primitive_types::{H256, H512};
 use generic_array::{GenericArray, ArrayLength};
 use vecarray::VecArray;
@@ -107,7 +107,7 @@ impl FromTree for H512 {
     fn from_tree<DB: ReadBackend>(root: &ValueOf<DB::Construct>, db: &mut DB) -> Result<Self, Error<DB::Error>> where
         DB::Construct: CompatibleConstruct,
     {
-        let value = ElementalFixedVec::<u8>::from_compact_vector_tree(root, db, 32, None)?;
+        let value = ElementalFixedVec::<u8>::from_compact_vector_tree(root, db, 64, None)?;
         Ok(Self::from_slice(value.0.as_ref()))
     }
 }
@@ -197,7 +197,7 @@ impl FromTree for () {
         if root == &Value::End(Default::default()) {
             Ok(())
         } else {
-            Err(Error::CorruptedDatabase)
+            Err(Error::NonCanonical)
         }
     }
 }
@@ -216,17 +216,23 @@ macro_rules! impl_tuple {
             fn from_tree<DB: ReadBackend>(root: &ValueOf<DB::Construct>, db: &mut DB) -> Result<Self, Error<DB::Error>> where
                 DB::Construct: CompatibleConstruct,
             {
+                if db.strict_decoding() {
+                    check_empty_tail(root, db, $len, None)?;
+                }
+
                 let vector = DanglingVector::<DB::Construct>::from_leaked(
                     (root.clone(), $len, None)
                 );
-                let mut i = 0;
-                Ok(($({
-                    let value = <$t>::from_tree(&vector.get(db, i)?, db)?;
-                    #[allow(unused_assignments)] {
-                        i += 1;
-                    }
-                    value
-                }),+))
+                decode_nested(db, |db| {
+                    let mut i = 0;
+                    Ok(($({
+                        let value = <$t>::from_tree(&vector.get(db, i)?, db)?;
+                        #[allow(unused_assignments)] {
+                            i += 1;
+                        }
+                        value
+                    }),+))
+                })
             }
         }
 
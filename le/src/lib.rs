@@ -13,32 +13,51 @@ use generic_array::GenericArray;
 use primitive_types::H256;
 use digest::Digest;
 use core::marker::PhantomData;
+use alloc::vec::Vec;
 
 pub use bm::{Backend, ReadBackend, WriteBackend, InheritedDigestConstruct,
              UnitDigestConstruct, Construct, InheritedEmpty, Error, ValueOf, Value, Vector,
-             DanglingVector, List, Leak, NoopBackend, InMemoryBackend};
+             DanglingVector, DanglingRaw, Index, List, Leak, NoopBackend, NoopBackendError,
+             InMemoryBackend};
 
 mod basic;
 mod elemental_fixed;
 mod elemental_variable;
 mod fixed;
 mod variable;
+mod bytes;
+mod cache;
+mod codec;
+mod limits;
+mod summary;
+mod dynamic;
+mod stable;
 pub mod utils;
+#[cfg(feature = "serde")]
+pub mod ser;
 
-pub use elemental_fixed::{ElementalFixedVec, ElementalFixedVecRef,
+pub use elemental_fixed::{ElementalFixedVec, ElementalFixedVecRef, IntoCompactVectorChunks,
                           IntoCompactVectorTree, FromCompactVectorTree,
                           IntoCompositeVectorTree, FromCompositeVectorTree};
 pub use elemental_variable::{ElementalVariableVec, ElementalVariableVecRef,
                              IntoCompactListTree, FromCompactListTree,
                              IntoCompositeListTree, FromCompositeListTree};
 pub use variable::MaxVec;
+pub use bytes::{ByteVector, ByteList};
+pub use cache::{TreeHashCache, IntoTreeCached};
+pub use limits::{DecodeLimits, LimitedBackend};
+pub use summary::Summary;
+pub use dynamic::{Schema, DynamicValue};
+pub use stable::{StableContainer, Profile};
+pub use codec::{Encode, Decode, DecodeError, CompactElement, ContainerEncoder, ContainerDecoder,
+                ContainerItems, BYTES_PER_LENGTH_OFFSET};
 #[cfg(feature = "derive")]
-pub use bm_le_derive::{FromTree, IntoTree};
+pub use bm_le_derive::{FromTree, IntoTree, IntoTreeCached, Encode, Decode, View};
 
 /// Digest construct for bm-le.
 pub struct DigestConstruct<D: Digest<OutputSize=U32>>(PhantomData<D>);
 
-impl<D: Digest<OutputSize=U32>> Construct for DigestConstruct<D> {
+impl<D: Digest<OutputSize=U32> + 'static> Construct for DigestConstruct<D> {
     type Intermediate = Intermediate;
     type End = End;
 
@@ -49,18 +68,30 @@ impl<D: Digest<OutputSize=U32>> Construct for DigestConstruct<D> {
         H256::from_slice(digest.result().as_slice())
     }
 
+    fn intermediate_of_batch(pairs: &[(ValueOf<Self>, ValueOf<Self>)]) -> Vec<Self::Intermediate> {
+        let mut digest = D::new();
+        pairs.iter().map(|(left, right)| {
+            digest.input(left.as_ref());
+            digest.input(right.as_ref());
+            H256::from_slice(digest.result_reset().as_slice())
+        }).collect()
+    }
+
     fn empty_at<DB: WriteBackend<Construct=Self>>(
         db: &mut DB,
         depth_to_bottom: usize
     ) -> Result<ValueOf<Self>, DB::Error> {
-        let mut current = Value::End(Default::default());
-        for _ in 0..depth_to_bottom {
-            let value = (current.clone(), current);
-            let key = Self::intermediate_of(&value.0, &value.1);
-            db.insert(key.clone(), value)?;
-            current = Value::Intermediate(key);
-        }
-        Ok(current)
+        bm::inherited_empty_at(db, depth_to_bottom)
+    }
+
+    #[cfg(feature = "std")]
+    fn empty_value(depth_to_bottom: usize) -> Option<ValueOf<Self>> {
+        bm::EmptyTable::<Self>::with_shared(|table| table.at(depth_to_bottom))
+    }
+
+    #[cfg(feature = "std")]
+    fn empty_depth(key: &Self::Intermediate) -> Option<usize> {
+        bm::EmptyTable::<Self>::with_shared(|table| table.depth_of(key))
     }
 }
 
@@ -102,6 +133,48 @@ pub trait CompatibleConstruct: Construct<Intermediate=Intermediate, End=End> { }
 
 impl<C: Construct<Intermediate=Intermediate, End=End>> CompatibleConstruct for C { }
 
+/// Backend wrapper that decodes leniently. Non-canonical chunks and
+/// nonzero padding are accepted as long as a value can be read, while
+/// out-of-range selectors and lengths are still rejected.
+pub struct LenientBackend<'a, DB>(pub &'a mut DB);
+
+impl<'a, DB: Backend> Backend for LenientBackend<'a, DB> {
+    type Construct = DB::Construct;
+    type Error = DB::Error;
+}
+
+impl<'a, DB: ReadBackend> ReadBackend for LenientBackend<'a, DB> {
+    fn get(
+        &mut self,
+        key: &<DB::Construct as Construct>::Intermediate
+    ) -> Result<(ValueOf<DB::Construct>, ValueOf<DB::Construct>), DB::Error> {
+        self.0.get(key)
+    }
+
+    fn contains(
+        &mut self,
+        key: &<DB::Construct as Construct>::Intermediate
+    ) -> Result<bool, DB::Error> {
+        self.0.contains(key)
+    }
+
+    fn strict_decoding(&self) -> bool {
+        false
+    }
+
+    fn check_decode_len(&mut self, len: usize) -> Result<(), Error<DB::Error>> {
+        self.0.check_decode_len(len)
+    }
+
+    fn enter_decode(&mut self) -> Result<(), Error<DB::Error>> {
+        self.0.enter_decode()
+    }
+
+    fn leave_decode(&mut self) {
+        self.0.leave_decode()
+    }
+}
+
 /// Traits for type converting into a tree structure.
 pub trait IntoTree {
     /// Convert this type into merkle tree, writing nodes into the
@@ -143,11 +216,44 @@ impl<T> From<T> for Compact<T> {
 }
 
 /// Calculate a ssz merkle tree root, dismissing the tree.
+///
+/// Panics if the value cannot be merkleized, such as a list over its
+/// maximum length. Use `try_tree_root` to handle the error instead.
 pub fn tree_root<D, T>(value: &T) -> H256 where
     T: IntoTree,
-    D: Digest<OutputSize=U32>,
+    D: Digest<OutputSize=U32> + 'static,
+{
+    try_tree_root::<D, T>(value).expect("Value cannot be merkleized")
+}
+
+/// Calculate a ssz merkle tree root, dismissing the tree, or return the
+/// error if the value cannot be merkleized.
+pub fn try_tree_root<D, T>(value: &T) -> Result<H256, Error<NoopBackendError>> where
+    T: IntoTree,
+    D: Digest<OutputSize=U32> + 'static,
 {
     value.into_tree(&mut NoopBackend::<DigestConstruct<D>>::default())
         .map(|ret| H256::from_slice(ret.as_ref()))
-        .expect("Noop backend never fails in set; qed")
+}
+
+/// Calculate a ssz merkle tree root, dismissing the tree, but reusing and
+/// updating the cache of the previous calculation.
+///
+/// Panics if the value cannot be merkleized. Use `try_tree_root_cached`
+/// to handle the error instead.
+pub fn tree_root_cached<D, T>(value: &T, cache: &mut TreeHashCache) -> H256 where
+    T: IntoTreeCached,
+    D: Digest<OutputSize=U32> + 'static,
+{
+    try_tree_root_cached::<D, T>(value, cache).expect("Value cannot be merkleized")
+}
+
+/// Calculate a ssz merkle tree root with the cache of the previous
+/// calculation, or return the error if the value cannot be merkleized.
+pub fn try_tree_root_cached<D, T>(value: &T, cache: &mut TreeHashCache) -> Result<H256, Error<NoopBackendError>> where
+    T: IntoTreeCached,
+    D: Digest<OutputSize=U32> + 'static,
+{
+    value.into_tree_cached(&mut NoopBackend::<DigestConstruct<D>>::default(), cache)
+        .map(|ret| H256::from_slice(ret.as_ref()))
 }
@@ -0,0 +1,140 @@
+use bm::{Backend, ReadBackend, Construct, Error, ValueOf};
+
+/// Resource limits for decoding untrusted trees.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct DecodeLimits {
+    /// Maximum length of any list read from the tree.
+    pub max_len: usize,
+    /// Maximum number of nodes fetched from the backend.
+    pub max_nodes: usize,
+    /// Maximum nesting depth of decoded values.
+    pub max_depth: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_len: 1 << 20,
+            max_nodes: 1 << 24,
+            max_depth: 64,
+        }
+    }
+}
+
+/// Backend wrapper that limits resources used by decoding. Decoding a
+/// tree over the limits fails with `Error::LimitExceeded`.
+pub struct LimitedBackend<'a, DB> {
+    db: &'a mut DB,
+    limits: DecodeLimits,
+    nodes: usize,
+    depth: usize,
+}
+
+impl<'a, DB> LimitedBackend<'a, DB> {
+    /// Create a new limited backend.
+    pub fn new(db: &'a mut DB, limits: DecodeLimits) -> Self {
+        Self { db, limits, nodes: 0, depth: 0 }
+    }
+
+    /// Limits of this backend.
+    pub fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+
+    /// Number of nodes fetched so far.
+    pub fn nodes(&self) -> usize {
+        self.nodes
+    }
+
+    fn check_nodes<E>(&self) -> Result<(), Error<E>> {
+        if self.nodes > self.limits.max_nodes {
+            return Err(Error::LimitExceeded)
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, DB: Backend> Backend for LimitedBackend<'a, DB> {
+    type Construct = DB::Construct;
+    type Error = DB::Error;
+}
+
+impl<'a, DB: ReadBackend> ReadBackend for LimitedBackend<'a, DB> {
+    fn get(
+        &mut self,
+        key: &<DB::Construct as Construct>::Intermediate
+    ) -> Result<(ValueOf<DB::Construct>, ValueOf<DB::Construct>), DB::Error> {
+        self.nodes = self.nodes.saturating_add(1);
+        self.db.get(key)
+    }
+
+    fn contains(
+        &mut self,
+        key: &<DB::Construct as Construct>::Intermediate
+    ) -> Result<bool, DB::Error> {
+        self.nodes = self.nodes.saturating_add(1);
+        self.db.contains(key)
+    }
+
+    fn strict_decoding(&self) -> bool {
+        self.db.strict_decoding()
+    }
+
+    fn check_decode_len(&mut self, len: usize) -> Result<(), Error<DB::Error>> {
+        self.check_nodes()?;
+        if len > self.limits.max_len {
+            return Err(Error::LimitExceeded)
+        }
+
+        self.db.check_decode_len(len)
+    }
+
+    fn enter_decode(&mut self) -> Result<(), Error<DB::Error>> {
+        self.check_nodes()?;
+        if self.depth >= self.limits.max_depth {
+            return Err(Error::LimitExceeded)
+        }
+
+        self.db.enter_decode()?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave_decode(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        self.db.leave_decode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IntoTree, FromTree, DigestConstruct, InMemoryBackend};
+    use sha2::Sha256;
+    use alloc::vec::Vec;
+    use alloc::vec;
+
+    #[test]
+    fn test_limits() {
+        let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+        let value: Vec<Vec<u64>> = vec![vec![1, 2, 3], vec![4, 5, 6, 7, 8]];
+        let root = value.into_tree(&mut db).unwrap();
+
+        let mut limited = LimitedBackend::new(&mut db, DecodeLimits::default());
+        assert_eq!(Vec::<Vec<u64>>::from_tree(&root, &mut limited).unwrap(), value);
+        assert!(limited.nodes() > 0);
+
+        let limits = DecodeLimits { max_len: 4, ..Default::default() };
+        assert!(matches!(Vec::<Vec<u64>>::from_tree(&root, &mut LimitedBackend::new(&mut db, limits)),
+                         Err(Error::LimitExceeded)));
+
+        let limits = DecodeLimits { max_nodes: 4, ..Default::default() };
+        assert!(matches!(Vec::<Vec<u64>>::from_tree(&root, &mut LimitedBackend::new(&mut db, limits)),
+                         Err(Error::LimitExceeded)));
+
+        let limits = DecodeLimits { max_depth: 1, ..Default::default() };
+        assert!(matches!(Vec::<Vec<u64>>::from_tree(&root, &mut LimitedBackend::new(&mut db, limits)),
+                         Err(Error::LimitExceeded)));
+    }
+}
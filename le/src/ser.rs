@@ -0,0 +1,777 @@
+//! Merkleization through serde `Serializer`.
+//!
+//! Structs, tuples and fixed-size arrays are merkleized as containers,
+//! sequences as lists without a maximum length, `Option` as a union and
+//! enum variants with their index mixed in, the same as the derived
+//! `IntoTree`. Lists with maximum length and packed vectors and lists
+//! are supplied by the `list`, `packed_list` and `packed_vector` helpers,
+//! and bitlists and bitvectors by `bitlist` and `bitvector`, used with
+//! `#[serde(serialize_with = "...")]`. Those helpers serialize the field
+//! as a tuple struct carrying its layout, which other serializers will
+//! also see.
+//!
+//! `H256` and `U256` serialize as hex strings, which are not supported.
+//! Fields of those types use the `h256` and `u256` helpers instead.
+
+use bm::{Error, ValueOf, WriteBackend};
+use serde::ser::{self, Serialize, Serializer, Impossible};
+use typenum::Unsigned;
+use generic_array::{GenericArray, ArrayLength};
+use primitive_types::{H256, U256};
+use core::fmt::{self, Debug, Display};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::{IntoTree, CompatibleConstruct, MaxVec};
+use crate::bytes::bytes_tree;
+use crate::utils::{vector_tree, mix_in_length, mix_in_type};
+
+/// Name of the tuple struct carrying layout of a sequence.
+const SEQUENCE_TOKEN: &str = "$bm_le::Sequence";
+/// Name of the newtype struct carrying a 32-byte basic value.
+const BYTES32_TOKEN: &str = "$bm_le::Bytes32";
+
+/// Error of merkleizing through `Serializer`.
+#[derive(Debug)]
+pub enum SerializeError<E> {
+    /// Error of the tree.
+    Tree(Error<E>),
+    /// The serde data type is not supported.
+    Unsupported(&'static str),
+    /// Custom error of the serialized type.
+    Custom(String),
+}
+
+impl<E> From<Error<E>> for SerializeError<E> {
+    fn from(err: Error<E>) -> Self {
+        SerializeError::Tree(err)
+    }
+}
+
+impl<E: Debug> Display for SerializeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerializeError::Tree(err) => write!(f, "tree error: {:?}", err),
+            SerializeError::Unsupported(ty) => write!(f, "unsupported type: {}", ty),
+            SerializeError::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl<E: Debug> ser::StdError for SerializeError<E> { }
+
+impl<E: Debug> ser::Error for SerializeError<E> {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerializeError::Custom(msg.to_string())
+    }
+}
+
+/// Merkleize a serializable value, writing nodes into the given database.
+pub fn to_tree<T, DB>(value: &T, db: &mut DB) -> Result<ValueOf<DB::Construct>, SerializeError<DB::Error>> where
+    T: Serialize + ?Sized,
+    DB: WriteBackend,
+    DB::Construct: CompatibleConstruct,
+    DB::Error: Debug,
+{
+    value.serialize(TreeSerializer::new(db))
+}
+
+/// Serialize a sequence with its layout. `bits` is the size in bits of
+/// packed items, `1` for bitfields, or `0` if items are not packed.
+fn serialize_sequence<T, S>(
+    items: &[T],
+    is_list: bool,
+    bits: usize,
+    max_len: usize,
+    serializer: S
+) -> Result<S::Ok, S::Error> where
+    T: Serialize,
+    S: Serializer,
+{
+    use serde::ser::SerializeTupleStruct;
+
+    let mut state = serializer.serialize_tuple_struct(SEQUENCE_TOKEN, 4)?;
+    state.serialize_field(&is_list)?;
+    state.serialize_field(&(bits as u64))?;
+    state.serialize_field(&(max_len as u64))?;
+    state.serialize_field(items)?;
+    state.end()
+}
+
+/// Serialize `MaxVec` as a list with its maximum length.
+pub fn list<T, ML, S>(value: &MaxVec<T, ML>, serializer: S) -> Result<S::Ok, S::Error> where
+    T: Serialize,
+    ML: Unsigned,
+    S: Serializer,
+{
+    serialize_sequence(&value[..], true, 0, ML::to_usize(), serializer)
+}
+
+/// Serialize `MaxVec` of basic values as a packed list with its maximum
+/// length, like `#[bm(compact)]`. Booleans are rejected, and should use
+/// `bitlist` instead.
+pub fn packed_list<T, ML, S>(value: &MaxVec<T, ML>, serializer: S) -> Result<S::Ok, S::Error> where
+    T: Serialize,
+    ML: Unsigned,
+    S: Serializer,
+{
+    serialize_sequence(&value[..], true, core::mem::size_of::<T>() * 8, ML::to_usize(), serializer)
+}
+
+/// Serialize `GenericArray` of basic values as a packed vector, like
+/// `#[bm(compact)]`. Booleans are rejected, and should use `bitvector`
+/// instead.
+pub fn packed_vector<T, N, S>(value: &GenericArray<T, N>, serializer: S) -> Result<S::Ok, S::Error> where
+    T: Serialize,
+    N: ArrayLength<T>,
+    S: Serializer,
+{
+    serialize_sequence(&value[..], false, core::mem::size_of::<T>() * 8, N::to_usize(), serializer)
+}
+
+/// Serialize `MaxVec` of booleans as a bitlist with its maximum length,
+/// like `#[bm(compact)]`.
+pub fn bitlist<ML, S>(value: &MaxVec<bool, ML>, serializer: S) -> Result<S::Ok, S::Error> where
+    ML: Unsigned,
+    S: Serializer,
+{
+    serialize_sequence(&value[..], true, 1, ML::to_usize(), serializer)
+}
+
+/// Serialize `GenericArray` of booleans as a bitvector, like
+/// `#[bm(compact)]`.
+pub fn bitvector<N, S>(value: &GenericArray<bool, N>, serializer: S) -> Result<S::Ok, S::Error> where
+    N: ArrayLength<bool>,
+    S: Serializer,
+{
+    serialize_sequence(&value[..], false, 1, N::to_usize(), serializer)
+}
+
+/// 32 bytes serialized as a byte array.
+struct Bytes32([u8; 32]);
+
+impl Serialize for Bytes32 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+/// Serialize `H256` as a 32-byte basic value instead of its hex string.
+pub fn h256<S: Serializer>(value: &H256, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(value.as_ref());
+    serializer.serialize_newtype_struct(BYTES32_TOKEN, &Bytes32(bytes))
+}
+
+/// Serialize `U256` as a 32-byte basic value instead of its hex string.
+pub fn u256<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut bytes = [0u8; 32];
+    value.to_little_endian(&mut bytes);
+    serializer.serialize_newtype_struct(BYTES32_TOKEN, &Bytes32(bytes))
+}
+
+/// Serializer producing merkle tree of the value.
+pub struct TreeSerializer<'a, DB> {
+    db: &'a mut DB,
+}
+
+impl<'a, DB> TreeSerializer<'a, DB> {
+    /// Create a new serializer writing into the given database.
+    pub fn new(db: &'a mut DB) -> Self {
+        Self { db }
+    }
+}
+
+enum CompoundKind {
+    List,
+    Container,
+    Variant(usize),
+}
+
+/// Compound value of `TreeSerializer`.
+pub struct Compound<'a, DB: WriteBackend> {
+    db: &'a mut DB,
+    kind: CompoundKind,
+    roots: Vec<ValueOf<DB::Construct>>,
+}
+
+impl<'a, DB: WriteBackend> Compound<'a, DB> where
+    DB::Construct: CompatibleConstruct,
+    DB::Error: Debug,
+{
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError<DB::Error>> {
+        let root = value.serialize(TreeSerializer::new(&mut *self.db))?;
+        self.roots.push(root);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<ValueOf<DB::Construct>, SerializeError<DB::Error>> {
+        let vector_root = vector_tree(&self.roots, self.db, None)?;
+        Ok(match self.kind {
+            CompoundKind::List => mix_in_length(&vector_root, self.db, self.roots.len())?,
+            CompoundKind::Container => vector_root,
+            CompoundKind::Variant(index) => mix_in_type(&vector_root, self.db, index)?,
+        })
+    }
+}
+
+/// Tuple struct of `TreeSerializer`.
+pub enum TupleStruct<'a, DB: WriteBackend> {
+    /// Tuple struct merkleized as a container.
+    Container(Compound<'a, DB>),
+    /// Tuple struct carrying layout of a sequence.
+    Sequence(Sequence<'a, DB>),
+}
+
+/// Tuple struct carrying layout of a sequence.
+pub struct Sequence<'a, DB: WriteBackend> {
+    db: &'a mut DB,
+    header: Vec<u64>,
+    root: Option<ValueOf<DB::Construct>>,
+}
+
+impl<'a, DB: WriteBackend> TreeSerializer<'a, DB> where
+    DB::Construct: CompatibleConstruct,
+    DB::Error: Debug,
+{
+    fn basic<T: IntoTree>(self, value: T) -> Result<ValueOf<DB::Construct>, SerializeError<DB::Error>> {
+        Ok(value.into_tree(self.db)?)
+    }
+
+    fn compound(self, kind: CompoundKind) -> Compound<'a, DB> {
+        Compound { db: self.db, kind, roots: Vec::new() }
+    }
+}
+
+impl<'a, DB: WriteBackend> Serializer for TreeSerializer<'a, DB> where
+    DB::Construct: CompatibleConstruct,
+    DB::Error: Debug,
+{
+    type Ok = ValueOf<DB::Construct>;
+    type Error = SerializeError<DB::Error>;
+
+    type SerializeSeq = Compound<'a, DB>;
+    type SerializeTuple = Compound<'a, DB>;
+    type SerializeTupleStruct = TupleStruct<'a, DB>;
+    type SerializeTupleVariant = Compound<'a, DB>;
+    type SerializeMap = Impossible<Self::Ok, Self::Error>;
+    type SerializeStruct = Compound<'a, DB>;
+    type SerializeStructVariant = Compound<'a, DB>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> { self.basic(v) }
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> { self.basic(v) }
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> { self.basic(v) }
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> { self.basic(v) }
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> { self.basic(v) }
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> { self.basic(v) }
+
+    fn serialize_i8(self, _: i8) -> Result<Self::Ok, Self::Error> { Err(SerializeError::Unsupported("i8")) }
+    fn serialize_i16(self, _: i16) -> Result<Self::Ok, Self::Error> { Err(SerializeError::Unsupported("i16")) }
+    fn serialize_i32(self, _: i32) -> Result<Self::Ok, Self::Error> { Err(SerializeError::Unsupported("i32")) }
+    fn serialize_i64(self, _: i64) -> Result<Self::Ok, Self::Error> { Err(SerializeError::Unsupported("i64")) }
+    fn serialize_f32(self, _: f32) -> Result<Self::Ok, Self::Error> { Err(SerializeError::Unsupported("f32")) }
+    fn serialize_f64(self, _: f64) -> Result<Self::Ok, Self::Error> { Err(SerializeError::Unsupported("f64")) }
+    fn serialize_char(self, _: char) -> Result<Self::Ok, Self::Error> { Err(SerializeError::Unsupported("char")) }
+    fn serialize_str(self, _: &str) -> Result<Self::Ok, Self::Error> { Err(SerializeError::Unsupported("str")) }
+    fn serialize_bytes(self, _: &[u8]) -> Result<Self::Ok, Self::Error> { Err(SerializeError::Unsupported("bytes")) }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(mix_in_type(&(), self.db, 0)?)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        let root = value.serialize(TreeSerializer::new(&mut *self.db))?;
+        Ok(mix_in_type(&root, self.db, 1)?)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        self.basic(())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Self::Ok, Self::Error> {
+        self.compound(CompoundKind::Container).finish()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str
+    ) -> Result<Self::Ok, Self::Error> {
+        self.compound(CompoundKind::Variant(variant_index as usize)).finish()
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T
+    ) -> Result<Self::Ok, Self::Error> {
+        if name == BYTES32_TOKEN {
+            let bytes = value.serialize(BasicSerializer::new(false))?;
+            if bytes.len() != 32 {
+                return Err(SerializeError::Unsupported("bytes"))
+            }
+
+            self.basic(H256::from_slice(&bytes))
+        } else {
+            value.serialize(self)
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        value: &T
+    ) -> Result<Self::Ok, Self::Error> {
+        let mut compound = self.compound(CompoundKind::Variant(variant_index as usize));
+        compound.push(value)?;
+        compound.finish()
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(self.compound(CompoundKind::List))
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Ok(self.compound(CompoundKind::Container))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        _: usize
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        if name == SEQUENCE_TOKEN {
+            Ok(TupleStruct::Sequence(Sequence { db: self.db, header: Vec::new(), root: None }))
+        } else {
+            Ok(TupleStruct::Container(self.compound(CompoundKind::Container)))
+        }
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        _: usize
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(self.compound(CompoundKind::Variant(variant_index as usize)))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(SerializeError::Unsupported("map"))
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(self.compound(CompoundKind::Container))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        _: usize
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(self.compound(CompoundKind::Variant(variant_index as usize)))
+    }
+}
+
+macro_rules! impl_compound {
+    ($trait:ident, $method:ident $(, $key:ty)*) => {
+        impl<'a, DB: WriteBackend> ser::$trait for Compound<'a, DB> where
+            DB::Construct: CompatibleConstruct,
+            DB::Error: Debug,
+        {
+            type Ok = ValueOf<DB::Construct>;
+            type Error = SerializeError<DB::Error>;
+
+            fn $method<T: Serialize + ?Sized>(&mut self, $(_: $key,)* value: &T) -> Result<(), Self::Error> {
+                self.push(value)
+            }
+
+            fn end(self) -> Result<Self::Ok, Self::Error> {
+                self.finish()
+            }
+        }
+    }
+}
+
+impl_compound!(SerializeSeq, serialize_element);
+impl_compound!(SerializeTuple, serialize_element);
+impl_compound!(SerializeTupleVariant, serialize_field);
+impl_compound!(SerializeStruct, serialize_field, &'static str);
+impl_compound!(SerializeStructVariant, serialize_field, &'static str);
+
+impl<'a, DB: WriteBackend> ser::SerializeTupleStruct for TupleStruct<'a, DB> where
+    DB::Construct: CompatibleConstruct,
+    DB::Error: Debug,
+{
+    type Ok = ValueOf<DB::Construct>;
+    type Error = SerializeError<DB::Error>;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        match self {
+            TupleStruct::Container(compound) => compound.push(value),
+            TupleStruct::Sequence(sequence) => sequence.push(value),
+        }
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        match self {
+            TupleStruct::Container(compound) => compound.finish(),
+            TupleStruct::Sequence(sequence) => sequence.finish(),
+        }
+    }
+}
+
+impl<'a, DB: WriteBackend> Sequence<'a, DB> where
+    DB::Construct: CompatibleConstruct,
+    DB::Error: Debug,
+{
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError<DB::Error>> {
+        if self.header.len() < 3 {
+            let bytes = value.serialize(BasicSerializer::new(true))?;
+            let mut header = [0u8; 8];
+            header[..bytes.len()].copy_from_slice(&bytes);
+            self.header.push(u64::from_le_bytes(header));
+            return Ok(())
+        }
+
+        let (is_list, bits, max_len) = (self.header[0] != 0, self.header[1] as usize, self.header[2] as usize);
+        let root = value.serialize(ItemsSerializer { db: &mut *self.db, is_list, bits, max_len })?;
+        self.root = Some(root);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<ValueOf<DB::Construct>, SerializeError<DB::Error>> {
+        self.root.ok_or_else(|| SerializeError::Custom("sequence has no items".to_string()))
+    }
+}
+
+/// Serializer of the items of a sequence.
+struct ItemsSerializer<'a, DB> {
+    db: &'a mut DB,
+    is_list: bool,
+    bits: usize,
+    max_len: usize,
+}
+
+/// Items of a sequence.
+pub struct Items<'a, DB: WriteBackend> {
+    db: &'a mut DB,
+    is_list: bool,
+    bits: usize,
+    max_len: usize,
+    len: usize,
+    roots: Vec<ValueOf<DB::Construct>>,
+    bytes: Vec<u8>,
+}
+
+impl<'a, DB: WriteBackend> ser::SerializeSeq for Items<'a, DB> where
+    DB::Construct: CompatibleConstruct,
+    DB::Error: Debug,
+{
+    type Ok = ValueOf<DB::Construct>;
+    type Error = SerializeError<DB::Error>;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        if self.bits == 0 {
+            self.roots.push(value.serialize(TreeSerializer::new(&mut *self.db))?);
+        } else if self.bits == 1 {
+            let bytes = value.serialize(BasicSerializer::new(true))?;
+            if bytes.len() != 1 || bytes[0] > 1 {
+                return Err(SerializeError::Unsupported("non-boolean bitfield item"))
+            }
+            if self.len % 8 == 0 {
+                self.bytes.push(0);
+            }
+            let last = self.bytes.len() - 1;
+            self.bytes[last] |= bytes[0] << (self.len % 8);
+        } else {
+            let mut bytes = value.serialize(BasicSerializer::new(false))?;
+            if bytes.len() * 8 != self.bits {
+                return Err(SerializeError::Unsupported("non-basic packed item"))
+            }
+            self.bytes.append(&mut bytes);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        if self.len > self.max_len {
+            return Err(Error::InvalidParameter.into())
+        }
+
+        let vector_root = if self.bits == 0 {
+            vector_tree(&self.roots, self.db, Some(self.max_len))?
+        } else {
+            bytes_tree(&self.bytes, self.db, (self.max_len * self.bits + 7) / 8)?
+        };
+
+        if self.is_list {
+            Ok(mix_in_length(&vector_root, self.db, self.len)?)
+        } else {
+            Ok(vector_root)
+        }
+    }
+}
+
+macro_rules! unsupported {
+    ($( $method:ident($ty:ty) ),*) => { $(
+        fn $method(self, _: $ty) -> Result<Self::Ok, Self::Error> {
+            Err(SerializeError::Unsupported(stringify!($ty)))
+        }
+    )* }
+}
+
+macro_rules! unsupported_compound {
+    () => {
+        fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+            Err(SerializeError::Unsupported("option"))
+        }
+
+        fn serialize_some<T: Serialize + ?Sized>(self, _: &T) -> Result<Self::Ok, Self::Error> {
+            Err(SerializeError::Unsupported("option"))
+        }
+
+        fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+            Err(SerializeError::Unsupported("unit"))
+        }
+
+        fn serialize_unit_struct(self, _: &'static str) -> Result<Self::Ok, Self::Error> {
+            Err(SerializeError::Unsupported("unit struct"))
+        }
+
+        fn serialize_unit_variant(
+            self, _: &'static str, _: u32, _: &'static str
+        ) -> Result<Self::Ok, Self::Error> {
+            Err(SerializeError::Unsupported("unit variant"))
+        }
+
+        fn serialize_newtype_struct<T: Serialize + ?Sized>(
+            self, _: &'static str, value: &T
+        ) -> Result<Self::Ok, Self::Error> {
+            value.serialize(self)
+        }
+
+        fn serialize_newtype_variant<T: Serialize + ?Sized>(
+            self, _: &'static str, _: u32, _: &'static str, _: &T
+        ) -> Result<Self::Ok, Self::Error> {
+            Err(SerializeError::Unsupported("newtype variant"))
+        }
+
+        fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Self::Error> {
+            Err(SerializeError::Unsupported("tuple"))
+        }
+
+        fn serialize_tuple_struct(
+            self, _: &'static str, _: usize
+        ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+            Err(SerializeError::Unsupported("tuple struct"))
+        }
+
+        fn serialize_tuple_variant(
+            self, _: &'static str, _: u32, _: &'static str, _: usize
+        ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+            Err(SerializeError::Unsupported("tuple variant"))
+        }
+
+        fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+            Err(SerializeError::Unsupported("map"))
+        }
+
+        fn serialize_struct(
+            self, _: &'static str, _: usize
+        ) -> Result<Self::SerializeStruct, Self::Error> {
+            Err(SerializeError::Unsupported("struct"))
+        }
+
+        fn serialize_struct_variant(
+            self, _: &'static str, _: u32, _: &'static str, _: usize
+        ) -> Result<Self::SerializeStructVariant, Self::Error> {
+            Err(SerializeError::Unsupported("struct variant"))
+        }
+    }
+}
+
+impl<'a, DB: WriteBackend> Serializer for ItemsSerializer<'a, DB> where
+    DB::Construct: CompatibleConstruct,
+    DB::Error: Debug,
+{
+    type Ok = ValueOf<DB::Construct>;
+    type Error = SerializeError<DB::Error>;
+
+    type SerializeSeq = Items<'a, DB>;
+    type SerializeTuple = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeMap = Impossible<Self::Ok, Self::Error>;
+    type SerializeStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    unsupported!(serialize_bool(bool), serialize_u8(u8), serialize_u16(u16), serialize_u32(u32),
+                 serialize_u64(u64), serialize_i8(i8), serialize_i16(i16), serialize_i32(i32),
+                 serialize_i64(i64), serialize_f32(f32), serialize_f64(f64), serialize_char(char),
+                 serialize_str(&str), serialize_bytes(&[u8]));
+    unsupported_compound!();
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(Items {
+            db: self.db,
+            is_list: self.is_list,
+            bits: self.bits,
+            max_len: self.max_len,
+            len: 0,
+            roots: Vec::new(),
+            bytes: Vec::new(),
+        })
+    }
+}
+
+/// Serializer of basic values into little endian bytes. Booleans are
+/// only accepted if `bools` is set, so that they are not packed as bytes.
+struct BasicSerializer<E> {
+    bools: bool,
+    _marker: core::marker::PhantomData<E>,
+}
+
+impl<E> BasicSerializer<E> {
+    fn new(bools: bool) -> Self {
+        Self { bools, _marker: core::marker::PhantomData }
+    }
+}
+
+impl<E: Debug> Serializer for BasicSerializer<E> {
+    type Ok = Vec<u8>;
+    type Error = SerializeError<E>;
+
+    type SerializeSeq = Impossible<Self::Ok, Self::Error>;
+    type SerializeTuple = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeMap = Impossible<Self::Ok, Self::Error>;
+    type SerializeStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        if self.bools {
+            Ok([v as u8].to_vec())
+        } else {
+            Err(SerializeError::Unsupported("bool"))
+        }
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> { Ok(v.to_le_bytes().to_vec()) }
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> { Ok(v.to_le_bytes().to_vec()) }
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> { Ok(v.to_le_bytes().to_vec()) }
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> { Ok(v.to_le_bytes().to_vec()) }
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> { Ok(v.to_le_bytes().to_vec()) }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> { Ok(v.to_vec()) }
+
+    unsupported!(serialize_i8(i8), serialize_i16(i16), serialize_i32(i32), serialize_i64(i64),
+                 serialize_f32(f32), serialize_f64(f64), serialize_char(char),
+                 serialize_str(&str));
+    unsupported_compound!();
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(SerializeError::Unsupported("seq"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DigestConstruct, InMemoryBackend};
+    use serde::Serialize;
+    use core::convert::TryFrom;
+    use sha2::Sha256;
+    use typenum::{U4, U8};
+    use alloc::vec;
+
+    #[derive(Serialize)]
+    struct Inner {
+        a: u16,
+        b: bool,
+    }
+
+    #[derive(Serialize)]
+    struct Pair(u8, u8);
+
+    #[derive(Serialize)]
+    enum Kind {
+        A,
+        B(u64),
+        C { c: u32, d: u8 },
+    }
+
+    #[derive(Serialize)]
+    struct Outer {
+        a: u64,
+        b: Option<u32>,
+        c: Vec<u16>,
+        d: [u8; 3],
+        e: Inner,
+        #[serde(serialize_with = "list")]
+        f: MaxVec<u64, U8>,
+        #[serde(serialize_with = "packed_list")]
+        g: MaxVec<u16, U8>,
+        #[serde(serialize_with = "packed_vector")]
+        h: GenericArray<u32, U4>,
+        i: Kind,
+    }
+
+    #[test]
+    fn test_serializer() {
+        let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+        let value = Outer {
+            a: 1,
+            b: Some(2),
+            c: vec![3, 4, 5],
+            d: [6, 7, 8],
+            e: Inner { a: 9, b: true },
+            f: MaxVec::try_from(vec![10, 11]).unwrap(),
+            g: MaxVec::try_from(vec![12, 13, 14]).unwrap(),
+            h: GenericArray::from([15, 16, 17, 18]),
+            i: Kind::C { c: 19, d: 20 },
+        };
+
+        let expected = (
+            value.a,
+            value.b,
+            value.c.clone(),
+            value.d,
+            (value.e.a, value.e.b),
+            value.f.clone(),
+            crate::CompactRef(&value.g),
+            crate::CompactRef(&value.h),
+            crate::utils::mix_in_type(&(19u32, 20u8), &mut db, 2).unwrap(),
+        ).into_tree(&mut db).unwrap();
+        assert_eq!(to_tree(&value, &mut db).unwrap(), expected);
+
+        let a = to_tree(&Kind::A, &mut db).unwrap();
+        assert_eq!(a, crate::utils::mix_in_type(&crate::utils::vector_tree(&[], &mut db, None).unwrap(),
+                                                &mut db, 0).unwrap());
+        let b = to_tree(&Kind::B(21), &mut db).unwrap();
+        assert_eq!(b, crate::utils::mix_in_type(&21u64, &mut db, 1).unwrap());
+
+        assert_eq!(to_tree(&Pair(1, 2), &mut db).unwrap(), (1u8, 2u8).into_tree(&mut db).unwrap());
+        assert!(matches!(to_tree(&-1i32, &mut db), Err(SerializeError::Unsupported(_))));
+        assert!(matches!(to_tree(&"str", &mut db), Err(SerializeError::Unsupported(_))));
+    }
+}
@@ -0,0 +1,112 @@
+/// Forward-compatible container with a fixed capacity, as in EIP-7495.
+/// Fields are all optional, and the tree is always of `CAPACITY` values,
+/// with a bitvector of active fields mixed into the root. Generalized
+/// indices of fields therefore stay the same when fields are added.
+pub trait StableContainer {
+    /// Maximum number of fields.
+    const CAPACITY: usize;
+
+    /// Position of a field in the container, by its name.
+    fn field_position(name: &str) -> Option<usize>;
+}
+
+/// Restriction of a stable container to a subset of its fields, some of
+/// which can be made required. A profile merkleizes to the same root as
+/// the equivalent base value.
+pub trait Profile {
+    /// Base stable container.
+    type Base: StableContainer;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bm::{Error, ValueOf, ReadBackend, WriteBackend};
+    use crate::{IntoTree, FromTree, CompatibleConstruct, utils};
+    use crate::{DigestConstruct, InMemoryBackend};
+    use generic_array::GenericArray;
+    use primitive_types::H256;
+    use core::str::FromStr;
+    use sha2::Sha256;
+    use typenum::U4;
+    use alloc::vec::Vec;
+
+    #[derive(Debug, Eq, PartialEq)]
+    struct Shape {
+        side: Option<u16>,
+        color: Option<u8>,
+    }
+
+    impl StableContainer for Shape {
+        const CAPACITY: usize = 4;
+
+        fn field_position(name: &str) -> Option<usize> {
+            match name {
+                "side" => Some(0),
+                "color" => Some(1),
+                _ => None,
+            }
+        }
+    }
+
+    impl IntoTree for Shape {
+        fn into_tree<DB: WriteBackend>(&self, db: &mut DB) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+            DB::Construct: CompatibleConstruct,
+        {
+            let fields = [
+                self.side.map(|side| side.into_tree(db)).transpose()?,
+                self.color.map(|color| color.into_tree(db)).transpose()?,
+            ];
+            utils::stable_container_tree(&fields, db, Self::CAPACITY)
+        }
+    }
+
+    impl FromTree for Shape {
+        fn from_tree<DB: ReadBackend>(root: &ValueOf<DB::Construct>, db: &mut DB) -> Result<Self, Error<DB::Error>> where
+            DB::Construct: CompatibleConstruct,
+        {
+            let fields = utils::decode_stable_container(root, db, Self::CAPACITY)?;
+            if fields[2..].iter().any(Option::is_some) {
+                return Err(Error::NonCanonical)
+            }
+
+            Ok(Self {
+                side: fields[0].as_ref().map(|side| u16::from_tree(side, db)).transpose()?,
+                color: fields[1].as_ref().map(|color| u8::from_tree(color, db)).transpose()?,
+            })
+        }
+    }
+
+    #[test]
+    fn test_stable_container() {
+        let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+        let shape = Shape { side: None, color: Some(1) };
+        let root = shape.into_tree(&mut db).unwrap();
+
+        // Payload of all fields, with inactive ones as zero, next to the
+        // active fields bitvector.
+        let repr = (
+            (0u16, 1u8, 0u16, 0u8),
+            crate::CompactRef(&GenericArray::<bool, U4>::from([false, true, false, false])),
+        );
+        assert_eq!(root, repr.into_tree(&mut db).unwrap());
+        assert_eq!(H256::from_slice(root.as_ref()), H256::from_str(
+            "522edd7309c0041b8eb6a218d756af558e9cf4c816441ec7e6eef42dfa47bb98"
+        ).unwrap());
+        assert_eq!(Shape::from_tree(&root, &mut db).unwrap(), shape);
+
+        let square = Shape { side: Some(0x42), color: Some(1) };
+        assert_eq!(H256::from_slice(square.into_tree(&mut db).unwrap().as_ref()), H256::from_str(
+            "bfdb6fda9d02805e640c0f5767b8d1bb9ff4211498a5e2d7c0f36e1b88ce57ff"
+        ).unwrap());
+
+        let fields: Vec<Option<ValueOf<DigestConstruct<Sha256>>>> = [Some(1u64), None, None, Some(2)]
+            .iter()
+            .map(|value| value.map(|value| value.into_tree(&mut db).unwrap()))
+            .collect();
+        let root = utils::stable_container_tree(&fields, &mut db, 4).unwrap();
+        assert!(matches!(Shape::from_tree(&root, &mut db), Err(Error::NonCanonical)));
+        assert_eq!(utils::decode_stable_container(&root, &mut db, 4).unwrap(), fields);
+        assert!(matches!(utils::stable_container_tree(&fields, &mut db, 3), Err(Error::InvalidParameter)));
+    }
+}
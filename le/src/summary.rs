@@ -0,0 +1,253 @@
+use bm::{Error, ValueOf, Value, Backend, ReadBackend, WriteBackend, Construct, NoopBackendError};
+use primitive_types::H256;
+use digest::Digest;
+use typenum::U32;
+use crate::{IntoTree, FromTree, CompatibleConstruct, End, tree_root, try_tree_root};
+
+/// Value that is either stored in full, or only summarized by its tree
+/// root. Both forms merkleize to the same root.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Summary<T> {
+    /// Full value.
+    Full(T),
+    /// Tree root of the value.
+    Root(H256),
+}
+
+impl<T> Summary<T> {
+    /// Whether the full value is available.
+    pub fn is_full(&self) -> bool {
+        matches!(self, Summary::Full(_))
+    }
+
+    /// Get a reference to the full value, if available.
+    pub fn full(&self) -> Option<&T> {
+        match self {
+            Summary::Full(value) => Some(value),
+            Summary::Root(_) => None,
+        }
+    }
+
+    /// Convert into the full value, if available.
+    pub fn into_full(self) -> Option<T> {
+        match self {
+            Summary::Full(value) => Some(value),
+            Summary::Root(_) => None,
+        }
+    }
+}
+
+impl<T: IntoTree> Summary<T> {
+    /// Tree root of the value. Panics if the full value cannot be
+    /// merkleized, see `try_root`.
+    pub fn root<D: Digest<OutputSize=U32> + 'static>(&self) -> H256 {
+        match self {
+            Summary::Full(value) => tree_root::<D, T>(value),
+            Summary::Root(root) => *root,
+        }
+    }
+
+    /// Tree root of the value, or the error if the full value cannot be
+    /// merkleized.
+    pub fn try_root<D: Digest<OutputSize=U32> + 'static>(&self) -> Result<H256, Error<NoopBackendError>> {
+        match self {
+            Summary::Full(value) => try_tree_root::<D, T>(value),
+            Summary::Root(root) => Ok(*root),
+        }
+    }
+
+    /// Summarize the value, dismissing the full value. Panics if the full
+    /// value cannot be merkleized, like `root`.
+    pub fn summarize<D: Digest<OutputSize=U32> + 'static>(self) -> Self {
+        Summary::Root(self.root::<D>())
+    }
+}
+
+impl<T> From<T> for Summary<T> {
+    fn from(value: T) -> Self {
+        Summary::Full(value)
+    }
+}
+
+impl<T: IntoTree> IntoTree for Summary<T> {
+    fn into_tree<DB: WriteBackend>(&self, db: &mut DB) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        match self {
+            Summary::Full(value) => value.into_tree(db),
+            Summary::Root(root) => {
+                // Only refer to the subtree as an intermediate node if the
+                // backend has it, so that parent nodes can still be stored.
+                if db.contains(root)? {
+                    Ok(Value::Intermediate(*root))
+                } else {
+                    Ok(Value::End(End(*root)))
+                }
+            },
+        }
+    }
+}
+
+/// Backend recording whether fetching failed because a node is missing,
+/// as opposed to other backend errors.
+struct MissingBackend<'a, DB> {
+    db: &'a mut DB,
+    missing: bool,
+}
+
+impl<'a, DB: Backend> Backend for MissingBackend<'a, DB> {
+    type Construct = DB::Construct;
+    type Error = DB::Error;
+}
+
+impl<'a, DB: ReadBackend> ReadBackend for MissingBackend<'a, DB> {
+    fn get(
+        &mut self,
+        key: &<DB::Construct as Construct>::Intermediate
+    ) -> Result<(ValueOf<DB::Construct>, ValueOf<DB::Construct>), DB::Error> {
+        match self.db.get(key) {
+            Ok(value) => Ok(value),
+            Err(err) => {
+                if let Ok(false) = self.db.contains(key) {
+                    self.missing = true;
+                }
+                Err(err)
+            },
+        }
+    }
+
+    fn contains(
+        &mut self,
+        key: &<DB::Construct as Construct>::Intermediate
+    ) -> Result<bool, DB::Error> {
+        self.db.contains(key)
+    }
+
+    fn strict_decoding(&self) -> bool {
+        self.db.strict_decoding()
+    }
+
+    fn check_decode_len(&mut self, len: usize) -> Result<(), Error<DB::Error>> {
+        self.db.check_decode_len(len)
+    }
+
+    fn enter_decode(&mut self) -> Result<(), Error<DB::Error>> {
+        self.db.enter_decode()
+    }
+
+    fn leave_decode(&mut self) {
+        self.db.leave_decode()
+    }
+}
+
+impl<T: FromTree> FromTree for Summary<T> {
+    fn from_tree<DB: ReadBackend>(root: &ValueOf<DB::Construct>, db: &mut DB) -> Result<Self, Error<DB::Error>> where
+        DB::Construct: CompatibleConstruct,
+    {
+        let mut db = MissingBackend { db, missing: false };
+
+        match T::from_tree(root, &mut db) {
+            Ok(value) => Ok(Summary::Full(value)),
+            Err(Error::Backend(_)) if db.missing =>
+                Ok(Summary::Root(H256::from_slice(root.as_ref()))),
+            // An end value in place of the subtree is a root stored by
+            // `IntoTree` when the backend lacked the subtree nodes.
+            Err(Error::CorruptedDatabase) if matches!(root, Value::End(_)) =>
+                Ok(Summary::Root(H256::from_slice(root.as_ref()))),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DigestConstruct, InMemoryBackend};
+    use sha2::Sha256;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_summary() {
+        let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+        let body: Vec<u64> = vec![1, 2, 3, 4, 5];
+        let body_root = tree_root::<Sha256, _>(&body);
+
+        let full = (0u64, Summary::Full(body.clone()));
+        let summarized = (0u64, Summary::<Vec<u64>>::Root(body_root));
+        assert_eq!(summarized.1.root::<Sha256>(), full.1.root::<Sha256>());
+        assert_eq!(tree_root::<Sha256, _>(&full), tree_root::<Sha256, _>(&summarized));
+
+        let mut summarized_db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+        let root = summarized.into_tree(&mut summarized_db).unwrap();
+        assert_eq!(<(u64, Summary<Vec<u64>>)>::from_tree(&root, &mut summarized_db).unwrap(), summarized);
+
+        let root = full.into_tree(&mut db).unwrap();
+        assert_eq!(<(u64, Summary<Vec<u64>>)>::from_tree(&root, &mut db).unwrap(), full);
+
+        assert_eq!(Summary::<Vec<u64>>::Root(body_root).into_tree(&mut db).unwrap(), Value::Intermediate(body_root));
+        assert_eq!(Summary::Full(body.clone()).summarize::<Sha256>(), Summary::<Vec<u64>>::Root(body_root));
+    }
+
+    struct FailingBackend;
+
+    impl Backend for FailingBackend {
+        type Construct = DigestConstruct<Sha256>;
+        type Error = ();
+    }
+
+    impl ReadBackend for FailingBackend {
+        fn get(
+            &mut self,
+            _key: &<Self::Construct as Construct>::Intermediate
+        ) -> Result<(ValueOf<Self::Construct>, ValueOf<Self::Construct>), ()> {
+            Err(())
+        }
+    }
+
+    impl WriteBackend for FailingBackend {
+        fn rootify(&mut self, _key: &<Self::Construct as Construct>::Intermediate) -> Result<(), ()> {
+            Err(())
+        }
+
+        fn unrootify(&mut self, _key: &<Self::Construct as Construct>::Intermediate) -> Result<(), ()> {
+            Err(())
+        }
+
+        fn insert(
+            &mut self,
+            _key: <Self::Construct as Construct>::Intermediate,
+            _value: (ValueOf<Self::Construct>, ValueOf<Self::Construct>)
+        ) -> Result<(), ()> {
+            Err(())
+        }
+    }
+
+    #[test]
+    fn test_summary_errors() {
+        let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+        let body: Vec<u64> = vec![1, 2, 3, 4, 5];
+        let body_root = tree_root::<Sha256, _>(&body);
+
+        let root = body.into_tree(&mut db).unwrap();
+        let (items, len) = db.get(&body_root).unwrap();
+
+        // Only the subtree root is available, but not the nodes below.
+        let mut partial_db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+        partial_db.populate(core::iter::once((body_root, (items, len.clone()))).collect());
+        assert_eq!(Summary::<Vec<u64>>::from_tree(&root, &mut partial_db).unwrap(),
+                   Summary::Root(body_root));
+
+        // Items of five values must be a subtree of two chunks.
+        let mut corrupted_db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+        corrupted_db.populate(core::iter::once((body_root, (Value::End(Default::default()), len))).collect());
+        assert!(matches!(Summary::<Vec<u64>>::from_tree(&root, &mut corrupted_db),
+                         Err(Error::CorruptedDatabase)));
+
+        // Other backend errors are not a missing subtree.
+        assert!(matches!(Summary::<Vec<u64>>::from_tree(&root, &mut FailingBackend),
+                         Err(Error::Backend(()))));
+        assert!(matches!(Summary::<Vec<u64>>::Root(body_root).into_tree(&mut FailingBackend),
+                         Err(Error::Backend(()))));
+    }
+}
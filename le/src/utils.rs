@@ -1,11 +1,28 @@
 //! Utilities
 
-use bm::{ValueOf, ReadBackend, WriteBackend, Error};
+use bm::{ValueOf, ReadBackend, WriteBackend, Error, Value, Construct, DanglingVector, Leak};
 use primitive_types::U256;
-use crate::{CompatibleConstruct, IntoTree, FromTree};
+use alloc::vec::Vec;
+use crate::{CompatibleConstruct, IntoTree, FromTree, ElementalFixedVec, ElementalFixedVecRef,
+            IntoCompactVectorTree, FromCompactVectorTree};
 
 pub use bm::utils::*;
 
+/// Serialize a vector at given depth. With the `parallel` feature, large
+/// vectors are hashed on the rayon thread pool.
+pub fn vector_tree<DB: WriteBackend>(values: &[ValueOf<DB::Construct>], db: &mut DB, max_len: Option<usize>) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+    DB::Construct: CompatibleConstruct,
+{
+    #[cfg(feature = "parallel")]
+    {
+        bm::utils::vector_tree_parallel(values, db, max_len)
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        bm::utils::vector_tree(values, db, max_len)
+    }
+}
+
 /// Mix in type.
 pub fn mix_in_type<T, DB: WriteBackend>(value: &T, db: &mut DB, ty: usize) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
     T: IntoTree,
@@ -25,9 +42,9 @@ pub fn decode_with_type<DB: ReadBackend, F, R>(root: &ValueOf<DB::Construct>, db
     let (value, ty) = <(ValueOf<DB::Construct>, U256)>::from_tree(root, db)?;
 
     if ty > U256::from(usize::max_value()) {
-        Err(Error::CorruptedDatabase)
+        Err(Error::NonCanonical)
     } else {
-        f(&value, db, ty.as_usize())
+        decode_nested(db, |db| f(&value, db, ty.as_usize()))
     }
 }
 
@@ -50,8 +67,120 @@ pub fn decode_with_length<T, DB: ReadBackend>(root: &ValueOf<DB::Construct>, db:
     let (value, len) = <(T, U256)>::from_tree(root, db)?;
 
     if len > U256::from(usize::max_value()) {
-        Err(Error::CorruptedDatabase)
+        Err(Error::NonCanonical)
     } else {
+        db.check_decode_len(len.as_usize())?;
         Ok((value, len.as_usize()))
     }
 }
+
+/// Root of a stable container field, where `None` is an inactive field.
+pub type FieldRoot<C> = Option<ValueOf<C>>;
+
+/// Serialize a stable container of given capacity from the roots of its
+/// fields, where `None` is an inactive field. The bitvector of active
+/// fields is mixed into the root.
+pub fn stable_container_tree<DB: WriteBackend>(fields: &[FieldRoot<DB::Construct>], db: &mut DB, capacity: usize) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+    DB::Construct: CompatibleConstruct,
+{
+    if fields.len() > capacity {
+        return Err(Error::InvalidParameter)
+    }
+
+    let values = fields.iter()
+        .map(|field| field.clone().unwrap_or_else(|| Value::End(Default::default())))
+        .collect::<Vec<_>>();
+    let mut active = fields.iter().map(Option::is_some).collect::<Vec<_>>();
+    active.resize(capacity, false);
+
+    let left = vector_tree(&values, db, Some(capacity))?;
+    let right = ElementalFixedVecRef(&active).into_compact_vector_tree(db, None)?;
+
+    (left, right).into_tree(db)
+}
+
+/// Decode a stable container of given capacity into the roots of its
+/// fields, where `None` is an inactive field.
+pub fn decode_stable_container<DB: ReadBackend>(root: &ValueOf<DB::Construct>, db: &mut DB, capacity: usize) -> Result<Vec<FieldRoot<DB::Construct>>, Error<DB::Error>> where
+    DB::Construct: CompatibleConstruct,
+{
+    let (values, active) = <(ValueOf<DB::Construct>, ValueOf<DB::Construct>)>::from_tree(root, db)?;
+    let active = ElementalFixedVec::<bool>::from_compact_vector_tree(&active, db, capacity, None)?.0;
+    let vector = DanglingVector::<DB::Construct>::from_leaked((values, capacity, None));
+
+    let mut fields = Vec::with_capacity(capacity);
+    for (i, active) in active.into_iter().enumerate() {
+        let value = vector.get(db, i)?;
+        if active {
+            fields.push(Some(value));
+        } else if db.strict_decoding() && value != Value::End(Default::default()) {
+            return Err(Error::NonCanonical)
+        } else {
+            fields.push(None);
+        }
+    }
+
+    Ok(fields)
+}
+
+/// Decode a nested value, tracking decoding depth of the backend.
+pub fn decode_nested<DB: ReadBackend, F, R>(db: &mut DB, f: F) -> Result<R, Error<DB::Error>> where
+    F: FnOnce(&mut DB) -> Result<R, Error<DB::Error>>,
+{
+    db.enter_decode()?;
+    let ret = f(db);
+    db.leave_decode();
+    ret
+}
+
+/// Check that all values of a vector tree from `len` on are empty, so that
+/// the tree canonically encodes a vector of `len` values.
+pub fn check_empty_tail<DB: ReadBackend>(root: &ValueOf<DB::Construct>, db: &mut DB, len: usize, max_len: Option<usize>) -> Result<(), Error<DB::Error>> where
+    DB::Construct: CompatibleConstruct,
+{
+    let depth = required_depth(max_len.unwrap_or(len));
+
+    // Empty values come from the shared table of the construct, and are
+    // only computed here if it has none.
+    let empty = match <DB::Construct as Construct>::empty_value(depth) {
+        Some(_) => None,
+        None => {
+            let mut empty: Vec<ValueOf<DB::Construct>> = Vec::with_capacity(depth + 1);
+            empty.push(Value::End(Default::default()));
+            for i in 0..depth {
+                let value = <DB::Construct as Construct>::intermediate_of(&empty[i], &empty[i]);
+                empty.push(Value::Intermediate(value));
+            }
+            Some(empty)
+        },
+    };
+
+    check_empty_tail_at(root, db, depth, 0, len, empty.as_deref())
+}
+
+fn check_empty_tail_at<DB: ReadBackend>(node: &ValueOf<DB::Construct>, db: &mut DB, depth: usize, start: usize, len: usize, empty: Option<&[ValueOf<DB::Construct>]>) -> Result<(), Error<DB::Error>> where
+    DB::Construct: CompatibleConstruct,
+{
+    if start >= len {
+        let is_empty = match empty {
+            Some(empty) => node == &empty[depth],
+            None => <DB::Construct as Construct>::empty_value(depth).as_ref() == Some(node),
+        };
+
+        return if is_empty { Ok(()) } else { Err(Error::NonCanonical) }
+    }
+
+    let size = 1usize.checked_shl(depth as u32).unwrap_or(usize::MAX);
+    if start.saturating_add(size) <= len {
+        return Ok(())
+    }
+
+    match node {
+        Value::Intermediate(key) => {
+            let (left, right) = db.get(key)?;
+            check_empty_tail_at(&left, db, depth - 1, start, len, empty)?;
+            check_empty_tail_at(&right, db, depth - 1, start + size / 2, len, empty)
+        },
+        Value::End(_) => Err(Error::CorruptedDatabase),
+    }
+}
@@ -1,16 +1,18 @@
 use typenum::Unsigned;
 use bm::{Error, ValueOf, ReadBackend, WriteBackend};
 use core::marker::PhantomData;
-use core::ops::{Deref, DerefMut};
+use core::convert::TryFrom;
+use core::ops::Deref;
 use alloc::vec::Vec;
 use crate::{ElementalVariableVecRef, ElementalVariableVec,
             IntoTree, IntoCompactListTree, IntoCompositeListTree,
             FromTree, FromCompactListTree, FromCompositeListTree,
             Compact, CompactRef, CompatibleConstruct};
 
-/// Vec value with maximum length.
+/// Vec value with maximum length. Values longer than the maximum length
+/// are rejected on construction, and on merkleization.
 #[derive(Debug, Clone, Eq, PartialEq)]
-pub struct MaxVec<T, ML>(pub Vec<T>, PhantomData<ML>);
+pub struct MaxVec<T, ML>(Vec<T>, PhantomData<ML>);
 
 impl<T, ML> Deref for MaxVec<T, ML> {
     type Target = Vec<T>;
@@ -20,8 +22,8 @@ impl<T, ML> Deref for MaxVec<T, ML> {
     }
 }
 
-impl<T, ML> DerefMut for MaxVec<T, ML> {
-    fn deref_mut(&mut self) -> &mut Vec<T> {
+impl<T, ML> AsMut<[T]> for MaxVec<T, ML> {
+    fn as_mut(&mut self) -> &mut [T] {
         &mut self.0
     }
 }
@@ -38,9 +40,54 @@ impl<T, ML> Default for MaxVec<T, ML> {
     }
 }
 
-impl<T, ML> From<Vec<T>> for MaxVec<T, ML> {
-    fn from(vec: Vec<T>) -> Self {
-        Self(vec, PhantomData)
+impl<T, ML: Unsigned> MaxVec<T, ML> {
+    /// Create an empty vec.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum length of the vec.
+    pub fn max_len() -> usize {
+        ML::to_usize()
+    }
+
+    /// Push a value, returning it back if the vec is already full.
+    pub fn try_push(&mut self, value: T) -> Result<(), T> {
+        if self.0.len() >= ML::to_usize() {
+            return Err(value)
+        }
+
+        self.0.push(value);
+        Ok(())
+    }
+
+    /// Extend the vec with values, returning them back without changing the
+    /// vec if the maximum length would be exceeded.
+    pub fn try_extend<I: IntoIterator<Item=T>>(&mut self, values: I) -> Result<(), Vec<T>> {
+        let values = values.into_iter().collect::<Vec<_>>();
+        if self.0.len() + values.len() > ML::to_usize() {
+            return Err(values)
+        }
+
+        self.0.extend(values);
+        Ok(())
+    }
+
+    /// Remove the last value and return it, or `None` if the vec is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        self.0.pop()
+    }
+}
+
+impl<T, ML: Unsigned> TryFrom<Vec<T>> for MaxVec<T, ML> {
+    type Error = Vec<T>;
+
+    fn try_from(vec: Vec<T>) -> Result<Self, Vec<T>> {
+        if vec.len() > ML::to_usize() {
+            return Err(vec)
+        }
+
+        Ok(Self(vec, PhantomData))
     }
 }
 
@@ -177,3 +224,43 @@ impl<T> FromTree for Vec<T> where
         ElementalVariableVec::from_composite_list_tree(root, db, None).map(|ret| ret.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DigestConstruct;
+
+    use bm::InMemoryBackend;
+    use sha2::Sha256;
+    use typenum::{U4, U64};
+
+    #[test]
+    fn test_checked() {
+        let mut value = MaxVec::<u64, U4>::try_from(vec![1, 2, 3]).unwrap();
+        assert_eq!(value.try_push(4), Ok(()));
+        assert_eq!(value.try_push(5), Err(5));
+        assert_eq!(value.pop(), Some(4));
+        assert_eq!(value.try_extend(vec![4, 5]), Err(vec![4, 5]));
+        assert_eq!(&value[..], &[1, 2, 3]);
+        assert_eq!(value.try_extend(vec![4]), Ok(()));
+        assert_eq!(&value[..], &[1, 2, 3, 4]);
+        assert_eq!(MaxVec::<u64, U4>::try_from(vec![1, 2, 3, 4, 5]), Err(vec![1, 2, 3, 4, 5]));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+
+        let mut value = MaxVec::<u64, U4>::try_from(vec![1, 2, 3, 4]).unwrap();
+        value.0.push(5);
+        assert!(matches!(value.into_tree(&mut db), Err(Error::InvalidParameter)));
+        assert!(matches!(CompactRef(&value).into_tree(&mut db), Err(Error::InvalidParameter)));
+
+        let long = CompactRef(&MaxVec::<u8, U64>::try_from(vec![0; 5]).unwrap())
+            .into_tree(&mut db).unwrap();
+        assert!(matches!(Compact::<MaxVec<u8, U4>>::from_tree(&long, &mut db),
+                         Err(Error::NonCanonical)));
+        assert!(matches!(MaxVec::<u8, U4>::from_tree(&long, &mut db),
+                         Err(Error::NonCanonical)));
+    }
+}
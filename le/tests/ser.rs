@@ -0,0 +1,70 @@
+#![cfg(all(feature = "with-serde", feature = "derive"))]
+
+use std::convert::TryFrom;
+use sha2::Sha256;
+use primitive_types::{H256, U256};
+use generic_array::GenericArray;
+use typenum::{U8, U10, U300};
+use serde::Serialize;
+
+use bm::InMemoryBackend;
+use bm_le::{IntoTree, MaxVec, DigestConstruct};
+use bm_le::ser::{to_tree, SerializeError};
+
+#[derive(IntoTree, Serialize)]
+struct Fields {
+    a: bool,
+    #[bm(compact)]
+    #[serde(serialize_with = "bm_le::ser::bitlist")]
+    b: MaxVec<bool, U300>,
+    #[bm(compact)]
+    #[serde(serialize_with = "bm_le::ser::bitvector")]
+    c: GenericArray<bool, U10>,
+    #[serde(serialize_with = "bm_le::ser::h256")]
+    d: H256,
+    #[serde(serialize_with = "bm_le::ser::u256")]
+    e: U256,
+    #[bm(compact)]
+    #[serde(serialize_with = "bm_le::ser::packed_list")]
+    f: MaxVec<u16, U8>,
+}
+
+#[derive(Serialize)]
+struct PackedBools {
+    #[serde(serialize_with = "bm_le::ser::packed_list")]
+    a: MaxVec<bool, U8>,
+}
+
+#[derive(Serialize)]
+struct HexHash {
+    a: H256,
+}
+
+#[test]
+fn derive_compatible() {
+    let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+
+    for len in &[0, 7, 8, 9, 256, 300] {
+        let value = Fields {
+            a: true,
+            b: MaxVec::try_from((0..*len).map(|i| i % 3 == 0).collect::<Vec<_>>()).unwrap(),
+            c: GenericArray::from([true, false, true, true, false, false, false, true, false, true]),
+            d: H256::from_low_u64_be(0x0102),
+            e: U256::from(0x0304),
+            f: MaxVec::try_from(vec![5, 6, 7]).unwrap(),
+        };
+
+        assert_eq!(to_tree(&value, &mut db).unwrap(), value.into_tree(&mut db).unwrap());
+    }
+}
+
+#[test]
+fn unsupported() {
+    let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+
+    let packed = PackedBools { a: MaxVec::try_from(vec![true, false]).unwrap() };
+    assert!(matches!(to_tree(&packed, &mut db), Err(SerializeError::Unsupported("bool"))));
+
+    let hex = HexHash { a: H256::from_low_u64_be(0x0102) };
+    assert!(matches!(to_tree(&hex, &mut db), Err(SerializeError::Unsupported("str"))));
+}
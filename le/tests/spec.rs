@@ -1,7 +1,8 @@
 use sha2::{Digest, Sha256};
-use primitive_types::H256;
+use primitive_types::{H256, H512};
 use std::fmt::Debug;
 use std::str::FromStr;
+use std::convert::TryFrom;
 use typenum::*;
 
 use bm::InMemoryBackend;
@@ -81,25 +82,25 @@ fn spec() {
     }
 
     // bitlist TTFTFTFF
-    t(Compact(MaxVec::<bool, U8>::from(vec![true, true, false, true, false, true, false, false])),
+    t(Compact(MaxVec::<bool, U8>::try_from(vec![true, true, false, true, false, true, false, false]).unwrap()),
                h(&chunk(&[0x2b])[..], &chunk(&[0x08])[..]));
     // bitlist FTFT
-    t(Compact(MaxVec::<bool, U4>::from(vec![false, true, false, true])),
+    t(Compact(MaxVec::<bool, U4>::try_from(vec![false, true, false, true]).unwrap()),
                h(&chunk(&[0x0a])[..], &chunk(&[0x04])[..]));
     // bitlist FTF
-    t(Compact(MaxVec::<bool, U3>::from(vec![false, true, false])),
+    t(Compact(MaxVec::<bool, U3>::try_from(vec![false, true, false]).unwrap()),
                h(&chunk(&[0x02])[..], &chunk(&[0x03])[..]));
     // bitlist TFTFFFTTFT
-    t(Compact(MaxVec::<bool, U16>::from(vec![true, false, true, false, false, false, true, true, false, true])),
+    t(Compact(MaxVec::<bool, U16>::try_from(vec![true, false, true, false, false, false, true, true, false, true]).unwrap()),
                h(&chunk(&[0xc5, 0x02])[..], &chunk(&[0x0a])[..]));
     // bitlist TFTFFFTTFTFFFFTT
-    t(Compact(MaxVec::<bool, U16>::from(vec![
+    t(Compact(MaxVec::<bool, U16>::try_from(vec![
         true, false, true, false, false, false, true, true, false, true,
-        false, false, false, false, true, true])),
+        false, false, false, false, true, true]).unwrap()),
       h(&chunk(&[0xc5, 0xc2])[..], &chunk(&[0x10])[..]));
-    t(Compact(MaxVec::<bool, U4096>::from(vec![
+    t(Compact(MaxVec::<bool, U4096>::try_from(vec![
         true, false, true, true, true, false, false, false
-    ])), H256::from_str("f4de82badf841b3e8064de143959343ec7d4405e72d95bfc741748bb15721ff4").unwrap());
+    ]).unwrap()), H256::from_str("f4de82badf841b3e8064de143959343ec7d4405e72d95bfc741748bb15721ff4").unwrap());
 
     t(GenericArray::<H256, U0>::from_exact_iter(vec![]).unwrap(), H256::from_str("0000000000000000000000000000000000000000000000000000000000000000").unwrap());
 }
@@ -251,3 +252,59 @@ fn spec() {
 //          )
 //      ))
 // ]
+
+#[test]
+fn non_canonical() {
+    use bm_le::{Value, End, Error, LenientBackend};
+    use bm_le::utils::{vector_tree, mix_in_type};
+
+    let mut db = InMemoryBackend::<DigestConstruct<Sha256>>::default();
+
+    // uint8 with nonzero high bytes
+    let root = Value::End(End(chunk(&[0x01, 0x01])));
+    assert!(matches!(u8::from_tree(&root, &mut db), Err(Error::NonCanonical)));
+    assert_eq!(u8::from_tree(&root, &mut LenientBackend(&mut db)).unwrap(), 0x01);
+
+    // boolean 02
+    let root = Value::End(End(chunk(&[0x02])));
+    assert!(matches!(bool::from_tree(&root, &mut db), Err(Error::NonCanonical)));
+    assert!(bool::from_tree(&root, &mut LenientBackend(&mut db)).unwrap());
+
+    // bitvector FTF with a padding bit set
+    let root = Value::End(End(chunk(&[0x0a])));
+    assert!(matches!(Compact::<GenericArray<bool, U3>>::from_tree(&root, &mut db),
+                     Err(Error::NonCanonical)));
+    assert_eq!(Compact::<GenericArray<bool, U3>>::from_tree(&root, &mut LenientBackend(&mut db)).unwrap(),
+               Compact(GenericArray::<bool, U3>::from([false, true, false])));
+
+    // packed uint16 vector with nonzero padding bytes
+    let root = Value::End(End(chunk(&[0x01, 0x00, 0x02, 0x00, 0xff])));
+    assert!(matches!(Compact::<GenericArray<u16, U2>>::from_tree(&root, &mut db),
+                     Err(Error::NonCanonical)));
+    assert_eq!(Compact::<GenericArray<u16, U2>>::from_tree(&root, &mut LenientBackend(&mut db)).unwrap(),
+               Compact(GenericArray::<u16, U2>::from([0x01, 0x02])));
+
+    // container of three with a nonzero fourth chunk
+    let root = vector_tree(&[Value::End(End(chunk(&[0x01]))), Value::End(End(chunk(&[0x02]))),
+                             Value::End(End(chunk(&[0x03]))), Value::End(End(chunk(&[0x04])))],
+                           &mut db, None).unwrap();
+    assert!(matches!(<(u8, u8, u8)>::from_tree(&root, &mut db), Err(Error::NonCanonical)));
+    assert_eq!(<(u8, u8, u8)>::from_tree(&root, &mut LenientBackend(&mut db)).unwrap(), (1, 2, 3));
+
+    // option with an out-of-range selector, rejected in both modes
+    let root = mix_in_type(&0x01u8, &mut db, 2).unwrap();
+    assert!(matches!(Option::<u8>::from_tree(&root, &mut db), Err(Error::NonCanonical)));
+    assert!(matches!(Option::<u8>::from_tree(&root, &mut LenientBackend(&mut db)),
+                     Err(Error::NonCanonical)));
+}
+
+#[test]
+fn hash512() {
+    let mut bytes = [0u8; 64];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+
+    // bytes64 is a vector of two chunks
+    t(H512::from_slice(&bytes), h(&bytes[..32], &bytes[32..]));
+}
@@ -0,0 +1,204 @@
+#[cfg(feature = "std")]
+use std::collections::HashMap as Map;
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as Map;
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use crate::{Value, ValueOf, Construct, WriteBackend};
+
+/// Default maximum depth of an empty table.
+pub const DEFAULT_MAX_EMPTY_DEPTH: usize = 256;
+
+/// Table of inherited empty values, indexed by depth to bottom. Each empty
+/// value is hashed once when the table is created. Constructs share one
+/// table through `Construct::empty_value` and `Construct::empty_depth`, and
+/// backends use it to recognise empty values virtually, without storing
+/// them.
+pub struct EmptyTable<C: Construct> {
+    values: Vec<ValueOf<C>>,
+    depths: Map<C::Intermediate, usize>,
+}
+
+impl<C: Construct> EmptyTable<C> where
+    C::Intermediate: Eq + Hash + Ord,
+{
+    /// Create a new empty table with given maximum depth.
+    pub fn new(max_depth: usize) -> Self {
+        let mut values = Vec::with_capacity(max_depth + 1);
+        let mut depths = Map::default();
+        values.push(Value::End(Default::default()));
+
+        for depth in 1..(max_depth + 1) {
+            let last = &values[depth - 1];
+            let key = C::intermediate_of(last, last);
+            depths.insert(key.clone(), depth);
+            values.push(Value::Intermediate(key));
+        }
+
+        Self { values, depths }
+    }
+
+    /// Maximum depth of the table.
+    pub fn max_depth(&self) -> usize {
+        self.values.len() - 1
+    }
+
+    /// Get the empty value at given depth to bottom. Returns `None` if the
+    /// depth is over the maximum depth.
+    pub fn at(&self, depth_to_bottom: usize) -> Option<ValueOf<C>> {
+        self.values.get(depth_to_bottom).cloned()
+    }
+
+    /// Get the depth to bottom of the key if it is an empty value in the
+    /// table.
+    pub fn depth_of(&self, key: &C::Intermediate) -> Option<usize> {
+        self.depths.get(key).cloned()
+    }
+
+    /// Whether the key is an empty value in the table.
+    pub fn contains(&self, key: &C::Intermediate) -> bool {
+        self.depths.contains_key(key)
+    }
+
+    /// Get children of the key if it is an empty value in the table.
+    pub fn get(&self, key: &C::Intermediate) -> Option<(ValueOf<C>, ValueOf<C>)> {
+        self.depths.get(key).map(|depth| {
+            let child = self.values[depth - 1].clone();
+            (child.clone(), child)
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<C: Construct + 'static> EmptyTable<C> where
+    C::Intermediate: Eq + Hash + Ord,
+{
+    /// Run `f` with the table of the construct up to
+    /// `DEFAULT_MAX_EMPTY_DEPTH`. The table is computed once per construct
+    /// and thread.
+    pub fn with_shared<R, F: FnOnce(&Self) -> R>(f: F) -> R {
+        use std::any::{Any, TypeId};
+        use std::cell::RefCell;
+        use std::collections::HashMap;
+
+        thread_local! {
+            static TABLES: RefCell<HashMap<TypeId, std::rc::Rc<dyn Any>>> = RefCell::new(HashMap::new());
+        }
+
+        let table = TABLES.with(|tables| {
+            tables.borrow_mut()
+                .entry(TypeId::of::<C>())
+                .or_insert_with(|| std::rc::Rc::new(Self::new(DEFAULT_MAX_EMPTY_DEPTH)))
+                .clone()
+        });
+
+        f(table.downcast_ref::<Self>().expect("Tables are keyed by their construct; qed"))
+    }
+}
+
+impl<C: Construct> Clone for EmptyTable<C> {
+    fn clone(&self) -> Self {
+        Self {
+            values: self.values.clone(),
+            depths: self.depths.clone(),
+        }
+    }
+}
+
+/// Get the inherited empty value at given depth to bottom. Values are taken
+/// from the shared table of the construct if it has one, and otherwise
+/// hashed. Each level over the backend's virtual empty depth is inserted.
+pub fn inherited_empty_at<DB: WriteBackend>(
+    db: &mut DB,
+    depth_to_bottom: usize
+) -> Result<ValueOf<DB::Construct>, DB::Error> {
+    if let Some(value) = DB::Construct::empty_value(depth_to_bottom) {
+        if depth_to_bottom <= db.virtual_empty_depth() {
+            return Ok(value)
+        }
+    }
+
+    let mut current = Value::End(Default::default());
+    for depth in 1..(depth_to_bottom + 1) {
+        let key = match DB::Construct::empty_value(depth) {
+            Some(Value::Intermediate(key)) => {
+                if depth <= db.virtual_empty_depth() {
+                    current = Value::Intermediate(key);
+                    continue
+                }
+                key
+            },
+            _ => DB::Construct::intermediate_of(&current, &current),
+        };
+        db.insert(key.clone(), (current.clone(), current))?;
+        current = Value::Intermediate(key);
+    }
+    Ok(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InMemoryBackend, NoopBackend, ReadBackend, OwnedVector, Tree};
+    use sha2::Sha256;
+
+    type Construct = crate::InheritedDigestConstruct<Sha256, Vec<u8>>;
+
+    fn hashed_empty_at(depth_to_bottom: usize) -> ValueOf<Construct> {
+        let mut current = Value::End(Default::default());
+        for _ in 0..depth_to_bottom {
+            current = Value::Intermediate(
+                <Construct as crate::Construct>::intermediate_of(&current, &current)
+            );
+        }
+        current
+    }
+
+    #[test]
+    fn test_table_values() {
+        let table = EmptyTable::<Construct>::new(8);
+        for depth in (0..9).rev() {
+            assert_eq!(table.at(depth), Some(hashed_empty_at(depth)));
+        }
+        assert_eq!(table.at(9), None);
+
+        let key = hashed_empty_at(3).intermediate().unwrap();
+        assert_eq!(table.get(&key), Some((hashed_empty_at(2), hashed_empty_at(2))));
+        assert_eq!(table.get(&hashed_empty_at(9).intermediate().unwrap()), None);
+    }
+
+    #[test]
+    fn test_shared_table() {
+        let first = EmptyTable::<Construct>::with_shared(|table| table as *const _ as usize);
+        let second = EmptyTable::<Construct>::with_shared(|table| table as *const _ as usize);
+        assert_eq!(first, second);
+
+        assert_eq!(<Construct as crate::Construct>::empty_value(9), Some(hashed_empty_at(9)));
+        let key = hashed_empty_at(9).intermediate().unwrap();
+        assert_eq!(<Construct as crate::Construct>::empty_depth(&key), Some(9));
+
+        let mut db = NoopBackend::<Construct>::default();
+        assert_eq!(db.get(&key), Ok((hashed_empty_at(8), hashed_empty_at(8))));
+        let mut db = NoopBackend::<Construct>::with_max_empty_depth(8);
+        assert!(db.get(&key).is_err());
+    }
+
+    #[test]
+    fn test_virtual_empty_nodes() {
+        let mut db = InMemoryBackend::<Construct>::default();
+        let vector = OwnedVector::create(&mut db, 1 << 20, None).unwrap();
+        assert_eq!(vector.root(), hashed_empty_at(20));
+        assert_eq!(db.as_ref().len(), 0);
+        vector.drop(&mut db).unwrap();
+
+        let mut db = NoopBackend::<Construct>::default();
+        let key = <Construct as crate::Construct>::empty_at(&mut db, 4).unwrap().intermediate().unwrap();
+        assert_eq!(db.get(&key), Ok((hashed_empty_at(3), hashed_empty_at(3))));
+
+        let mut db = InMemoryBackend::<Construct>::with_max_empty_depth(0);
+        let vector = OwnedVector::create(&mut db, 1 << 4, None).unwrap();
+        assert_eq!(vector.root(), hashed_empty_at(4));
+        assert_eq!(db.as_ref().len(), 4);
+    }
+}
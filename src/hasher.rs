@@ -0,0 +1,173 @@
+use core::marker::PhantomData;
+use core::hash::Hash;
+use alloc::vec::Vec;
+
+use crate::{Value, ValueOf, Construct, WriteBackend, inherited_empty_at};
+#[cfg(feature = "std")]
+use crate::EmptyTable;
+
+/// Two-to-one compression function backing a merkle tree. Unlike digest
+/// constructs, inputs and outputs need not be byte strings, which allows
+/// algebraic hashes over field elements.
+pub trait MerkleHasher {
+    /// Output of the compression function.
+    type Output: Clone + Eq + Hash + Ord;
+    /// End value of the tree.
+    type End: Clone + Default;
+
+    /// Map an end value into the output domain.
+    fn leaf(end: &Self::End) -> Self::Output;
+    /// Compress two outputs into one.
+    fn compress(left: &Self::Output, right: &Self::Output) -> Self::Output;
+    /// Compress many pairs of outputs. Hashers can override this to
+    /// compress the pairs in parallel lanes.
+    fn compress_batch(pairs: &[(Self::Output, Self::Output)]) -> Vec<Self::Output> {
+        pairs.iter().map(|(left, right)| Self::compress(left, right)).collect()
+    }
+}
+
+/// Construct generic over a merkle hasher. Empty values are inherited.
+pub struct HasherConstruct<H: MerkleHasher>(PhantomData<H>);
+
+impl<H: MerkleHasher + 'static> HasherConstruct<H> {
+    fn output_of(value: &ValueOf<Self>) -> H::Output {
+        match value {
+            Value::Intermediate(intermediate) => intermediate.clone(),
+            Value::End(end) => H::leaf(end),
+        }
+    }
+}
+
+impl<H: MerkleHasher + 'static> Construct for HasherConstruct<H> {
+    type Intermediate = H::Output;
+    type End = H::End;
+
+    fn intermediate_of(left: &ValueOf<Self>, right: &ValueOf<Self>) -> Self::Intermediate {
+        H::compress(&Self::output_of(left), &Self::output_of(right))
+    }
+
+    fn intermediate_of_batch(pairs: &[(ValueOf<Self>, ValueOf<Self>)]) -> Vec<Self::Intermediate> {
+        let outputs = pairs.iter()
+            .map(|(left, right)| (Self::output_of(left), Self::output_of(right)))
+            .collect::<Vec<_>>();
+        H::compress_batch(&outputs)
+    }
+
+    fn empty_at<DB: WriteBackend<Construct=Self>>(
+        db: &mut DB,
+        depth_to_bottom: usize
+    ) -> Result<ValueOf<Self>, DB::Error> {
+        inherited_empty_at(db, depth_to_bottom)
+    }
+
+    #[cfg(feature = "std")]
+    fn empty_value(depth_to_bottom: usize) -> Option<ValueOf<Self>> {
+        EmptyTable::<Self>::with_shared(|table| table.at(depth_to_bottom))
+    }
+
+    #[cfg(feature = "std")]
+    fn empty_depth(key: &Self::Intermediate) -> Option<usize> {
+        EmptyTable::<Self>::with_shared(|table| table.depth_of(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InMemoryBackend, NoopBackend, OwnedVector, OwnedList, Tree, Sequence};
+    use crate::utils::vector_tree;
+
+    const MODULUS: u64 = (1 << 31) - 1;
+
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+    struct Fp(u64);
+
+    impl Fp {
+        fn add(self, other: Fp) -> Fp {
+            Fp((self.0 + other.0) % MODULUS)
+        }
+
+        fn mul(self, other: Fp) -> Fp {
+            Fp((self.0 * other.0) % MODULUS)
+        }
+
+        fn pow5(self) -> Fp {
+            let square = self.mul(self);
+            square.mul(square).mul(self)
+        }
+    }
+
+    impl From<usize> for Fp {
+        fn from(value: usize) -> Self {
+            Fp(value as u64 % MODULUS)
+        }
+    }
+
+    impl From<Fp> for usize {
+        fn from(value: Fp) -> usize {
+            value.0 as usize
+        }
+    }
+
+    /// Toy sponge-like compression over the Mersenne prime field `2^31 - 1`.
+    struct ToyHasher;
+
+    impl MerkleHasher for ToyHasher {
+        type Output = Fp;
+        type End = Fp;
+
+        fn leaf(end: &Fp) -> Fp {
+            *end
+        }
+
+        fn compress(left: &Fp, right: &Fp) -> Fp {
+            let state = left.add(Fp(7)).pow5().add(right.mul(Fp(3)));
+            state.add(Fp(11)).pow5()
+        }
+    }
+
+    type Toy = HasherConstruct<ToyHasher>;
+
+    fn compress(left: u64, right: u64) -> Fp {
+        ToyHasher::compress(&Fp(left), &Fp(right))
+    }
+
+    #[test]
+    fn test_vector_root() {
+        let mut db = InMemoryBackend::<Toy>::default();
+        let mut vector = OwnedVector::<Toy>::create(&mut db, 0, None).unwrap();
+        for i in 1..4 {
+            vector.push(&mut db, Value::End(Fp(i))).unwrap();
+        }
+
+        let expected = ToyHasher::compress(&compress(1, 2), &compress(3, 0));
+        assert_eq!(vector.root(), Value::Intermediate(expected));
+        assert_eq!(vector.get(&mut db, 2).unwrap(), Value::End(Fp(3)));
+    }
+
+    #[test]
+    fn test_list_root() {
+        let mut db = InMemoryBackend::<Toy>::default();
+        let mut list = OwnedList::<Toy>::create(&mut db, None).unwrap();
+        let mut values = Vec::new();
+        for i in 0..13 {
+            list.push(&mut db, Value::End(Fp(i * i))).unwrap();
+            values.push(Value::End(Fp(i * i)));
+        }
+
+        let items_root = vector_tree(&values, &mut db, None).unwrap();
+        assert_eq!(list.root(), Value::Intermediate(
+            Toy::intermediate_of(&items_root, &Value::End(Fp(13)))
+        ));
+        assert_eq!(list.pop(&mut db).unwrap(), Some(Value::End(Fp(144))));
+        assert_eq!(list.len(), 12);
+    }
+
+    #[test]
+    fn test_inherited_empty() {
+        let mut db = NoopBackend::<Toy>::default();
+        let empty = compress(0, 0);
+        assert_eq!(Toy::empty_at(&mut db, 2).unwrap(),
+                   Value::Intermediate(ToyHasher::compress(&empty, &empty)));
+    }
+}
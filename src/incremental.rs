@@ -0,0 +1,307 @@
+use core::fmt;
+use core::hash::Hash;
+use core::convert::Infallible;
+use alloc::vec::Vec;
+
+use crate::traits::{Construct, Value, ValueOf, Error};
+use crate::memory::NoopBackend;
+
+/// Frontier of an incremental merkle tree, which is all the state needed to
+/// continue appending to it.
+pub struct Frontier<C: Construct> {
+    /// Depth of the tree.
+    pub depth: usize,
+    /// Number of appended leaves.
+    pub len: usize,
+    /// Left-most unpaired node at each level, from the bottom. The last
+    /// item is the items root once the tree is full.
+    pub branch: Vec<ValueOf<C>>,
+}
+
+impl<C: Construct> Clone for Frontier<C> {
+    fn clone(&self) -> Self {
+        Self {
+            depth: self.depth,
+            len: self.len,
+            branch: self.branch.clone(),
+        }
+    }
+}
+
+impl<C: Construct> PartialEq for Frontier<C> where
+    C::Intermediate: PartialEq,
+    C::End: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.depth == other.depth && self.len == other.len && self.branch == other.branch
+    }
+}
+
+impl<C: Construct> Eq for Frontier<C> where
+    C::Intermediate: Eq,
+    C::End: Eq { }
+
+impl<C: Construct> fmt::Debug for Frontier<C> where
+    C::Intermediate: fmt::Debug,
+    C::End: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Frontier")
+            .field("depth", &self.depth)
+            .field("len", &self.len)
+            .field("branch", &self.branch)
+            .finish()
+    }
+}
+
+/// Incremental merkle tree of fixed depth, in the style of the deposit
+/// contract. Only the frontier is kept, and no backend is needed. The root
+/// has the same layout as a `List` with maximum length `2^depth`.
+pub struct IncrementalTree<C: Construct> {
+    frontier: Frontier<C>,
+    zeros: Vec<ValueOf<C>>,
+}
+
+impl<C: Construct> IncrementalTree<C> where
+    C::Intermediate: Eq + Hash + Ord,
+    C::End: From<usize> + Into<usize>,
+{
+    /// Create a new empty tree of given depth.
+    pub fn new(depth: usize) -> Result<Self, Error<Infallible>> {
+        if depth >= core::mem::size_of::<usize>() * 8 {
+            return Err(Error::InvalidParameter)
+        }
+
+        let zeros = Self::zeros(depth);
+        Ok(Self {
+            frontier: Frontier {
+                depth,
+                len: 0,
+                branch: zeros.clone(),
+            },
+            zeros,
+        })
+    }
+
+    /// Restore the tree from a frontier snapshot.
+    pub fn restore(frontier: Frontier<C>) -> Result<Self, Error<Infallible>> {
+        if frontier.branch.len() != frontier.depth + 1 ||
+            frontier.depth >= core::mem::size_of::<usize>() * 8 ||
+            frontier.len > 1 << frontier.depth
+        {
+            return Err(Error::InvalidParameter)
+        }
+
+        Ok(Self {
+            zeros: Self::zeros(frontier.depth),
+            frontier,
+        })
+    }
+
+    /// Snapshot the frontier of the tree.
+    pub fn snapshot(&self) -> Frontier<C> {
+        self.frontier.clone()
+    }
+
+    fn zeros(depth: usize) -> Vec<ValueOf<C>> {
+        let mut db = NoopBackend::<C>::default();
+        (0..(depth + 1))
+            .map(|depth_to_bottom| C::empty_at(&mut db, depth_to_bottom)
+                 .expect("Noop backend never fails on insertion; qed"))
+            .collect()
+    }
+
+    /// Depth of the tree.
+    pub fn depth(&self) -> usize {
+        self.frontier.depth
+    }
+
+    /// Number of appended leaves.
+    pub fn len(&self) -> usize {
+        self.frontier.len
+    }
+
+    /// Whether the tree is empty.
+    pub fn is_empty(&self) -> bool {
+        self.frontier.len == 0
+    }
+
+    /// Append a new leaf.
+    pub fn push(&mut self, value: ValueOf<C>) -> Result<(), Error<Infallible>> {
+        if self.frontier.len >= 1 << self.frontier.depth {
+            return Err(Error::AccessOverflowed)
+        }
+
+        self.frontier.len += 1;
+        let mut node = value;
+        let mut size = self.frontier.len;
+        for height in 0..(self.frontier.depth + 1) {
+            if size & 1 == 1 {
+                self.frontier.branch[height] = node;
+                break
+            }
+            node = Value::Intermediate(C::intermediate_of(&self.frontier.branch[height], &node));
+            size >>= 1;
+        }
+
+        Ok(())
+    }
+
+    /// Root of the items, without length mixed in.
+    pub fn items_root(&self) -> ValueOf<C> {
+        let len = self.frontier.len;
+        if len == 1 << self.frontier.depth {
+            return self.frontier.branch[self.frontier.depth].clone()
+        }
+
+        let mut node = self.zeros[0].clone();
+        for height in 0..self.frontier.depth {
+            node = if (len >> height) & 1 == 1 {
+                Value::Intermediate(C::intermediate_of(&self.frontier.branch[height], &node))
+            } else if len & ((1 << (height + 1)) - 1) == 0 {
+                self.zeros[height + 1].clone()
+            } else {
+                Value::Intermediate(C::intermediate_of(&node, &self.zeros[height]))
+            };
+        }
+        node
+    }
+
+    /// Root of the tree, with length mixed in.
+    pub fn root(&self) -> ValueOf<C> {
+        let len = Value::End(self.frontier.len.into());
+        Value::Intermediate(C::intermediate_of(&self.items_root(), &len))
+    }
+
+    /// Merkle branch of the latest leaf, from the bottom, with the length
+    /// node as the last item. Returns `None` if the tree is empty.
+    pub fn branch(&self) -> Option<Vec<ValueOf<C>>> {
+        let index = self.frontier.len.checked_sub(1)?;
+        let mut branch = (0..self.frontier.depth).map(|height| {
+            if (index >> height) & 1 == 1 {
+                self.frontier.branch[height].clone()
+            } else {
+                self.zeros[height].clone()
+            }
+        }).collect::<Vec<_>>();
+        branch.push(Value::End(self.frontier.len.into()));
+        Some(branch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::vector_tree;
+    use crate::memory::InMemoryBackend;
+    use sha2::Sha256;
+
+    type Inherited = crate::InheritedDigestConstruct<Sha256, ListValue>;
+    type Unit = crate::UnitDigestConstruct<Sha256, ListValue>;
+
+    #[derive(Clone, PartialEq, Eq, Debug, Default)]
+    struct ListValue(Vec<u8>);
+
+    impl AsRef<[u8]> for ListValue {
+        fn as_ref(&self) -> &[u8] {
+            self.0.as_ref()
+        }
+    }
+
+    impl From<usize> for ListValue {
+        fn from(value: usize) -> Self {
+            ListValue((&(value as u64).to_le_bytes()[..]).into())
+        }
+    }
+
+    impl Into<usize> for ListValue {
+        fn into(self) -> usize {
+            let mut raw = [0u8; 8];
+            raw.copy_from_slice(&self.0[0..8]);
+            u64::from_le_bytes(raw) as usize
+        }
+    }
+
+    fn check_list_roots<C: Construct<End=ListValue>>() where
+        C::Intermediate: Eq + Hash + Ord + fmt::Debug,
+    {
+        let depth = 5;
+        let mut db = InMemoryBackend::<C>::default();
+        let mut tree = IncrementalTree::<C>::new(depth).unwrap();
+        let mut values = Vec::new();
+
+        for i in 0..((1 << depth) + 1) {
+            let items_root = vector_tree(&values, &mut db, Some(1 << depth)).unwrap();
+            assert_eq!(tree.items_root(), items_root);
+            assert_eq!(tree.root(), Value::Intermediate(
+                C::intermediate_of(&items_root, &Value::End(values.len().into()))
+            ));
+
+            if i < (1 << depth) {
+                values.push(Value::End((i + 1).into()));
+                tree.push(Value::End((i + 1).into())).unwrap();
+            }
+        }
+
+        assert_eq!(tree.push(Value::End(0.into())), Err(Error::AccessOverflowed));
+    }
+
+    #[test]
+    fn test_list_roots() {
+        check_list_roots::<Inherited>();
+        check_list_roots::<Unit>();
+    }
+
+    #[test]
+    fn test_branch() {
+        let mut tree = IncrementalTree::<Inherited>::new(6).unwrap();
+        assert_eq!(tree.branch(), None);
+
+        for i in 0..40 {
+            let leaf = Value::End((i + 1).into());
+            tree.push(leaf.clone()).unwrap();
+
+            let branch = tree.branch().unwrap();
+            assert_eq!(branch.len(), 7);
+            let mut node = leaf;
+            for (height, sibling) in branch.iter().enumerate() {
+                node = if height < 6 && (i >> height) & 1 == 1 {
+                    Value::Intermediate(Inherited::intermediate_of(sibling, &node))
+                } else {
+                    Value::Intermediate(Inherited::intermediate_of(&node, sibling))
+                };
+            }
+            assert_eq!(node, tree.root());
+        }
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut tree = IncrementalTree::<Inherited>::new(8).unwrap();
+        for i in 0..13 {
+            tree.push(Value::End(i.into())).unwrap();
+        }
+
+        let snapshot = tree.snapshot();
+        let mut restored = IncrementalTree::restore(snapshot.clone()).unwrap();
+        assert_eq!(restored.root(), tree.root());
+
+        for i in 13..20 {
+            tree.push(Value::End(i.into())).unwrap();
+            restored.push(Value::End(i.into())).unwrap();
+        }
+        assert_eq!(restored.root(), tree.root());
+
+        let mut invalid = snapshot;
+        invalid.branch.pop();
+        assert!(IncrementalTree::restore(invalid).is_err());
+    }
+
+    #[test]
+    fn test_invalid_depth() {
+        let bits = core::mem::size_of::<usize>() * 8;
+        assert!(IncrementalTree::<Inherited>::new(bits - 1).is_ok());
+        assert!(IncrementalTree::<Inherited>::new(bits).is_err());
+        assert!(IncrementalTree::<Inherited>::new(bits + 1).is_err());
+    }
+}
@@ -35,7 +35,7 @@ impl IndexRoute {
 }
 
 /// Raw merkle index.
-#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+#[derive(Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Hash, Debug)]
 pub struct Index(usize);
 
 impl Index {
@@ -63,6 +63,11 @@ impl Index {
         }
     }
 
+    /// Get the depth of current merkle index, where root is considered depth 0.
+    pub const fn depth(&self) -> usize {
+        core::mem::size_of::<usize>() * 8 - 1 - self.0.leading_zeros() as usize
+    }
+
     /// Whether this index has given descendant.
     pub fn has_descendant(&self, other: &Index) -> bool {
         match other.parent() {
@@ -87,10 +92,31 @@ impl Index {
     }
 
     /// From zero-based index.
-    pub fn from_zero(value: usize) -> Self {
+    pub const fn from_zero(value: usize) -> Self {
         Self(value + 1)
     }
 
+    /// Index of `other` in the subtree rooted at current index, where
+    /// `other` is relative to the subtree root.
+    ///
+    /// The combined depth must be less than the bits of `usize`, or high
+    /// bits of the result are lost. Use `checked_concat` if the indices
+    /// may be that deep.
+    pub const fn concat(&self, other: &Index) -> Self {
+        let depth = other.depth();
+        Self((self.0 << depth) | (other.0 ^ (1 << depth)))
+    }
+
+    /// Index of `other` in the subtree rooted at current index, or `None`
+    /// if it does not fit in `usize`.
+    pub fn checked_concat(&self, other: &Index) -> Option<Self> {
+        if self.depth() + other.depth() >= core::mem::size_of::<usize>() * 8 {
+            None
+        } else {
+            Some(self.concat(other))
+        }
+    }
+
     /// Get selections from current index.
     pub fn route(&self) -> IndexRoute {
         let mut value = self.0;
@@ -124,6 +150,27 @@ impl Index {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_depth() {
+        assert_eq!(Index::root().depth(), 0);
+        assert_eq!(Index::root().left().depth(), 1);
+        assert_eq!(Index::root().right().right().left().depth(), 3);
+    }
+
+    #[test]
+    fn test_concat() {
+        assert_eq!(Index::root().concat(&Index::root().right()), Index::root().right());
+        assert_eq!(Index::root().left().concat(&Index::root()), Index::root().left());
+        assert_eq!(Index::root().left().right().concat(&Index::root().right().left()),
+                   Index::root().left().right().right().left());
+
+        let bits = core::mem::size_of::<usize>() * 8;
+        let deep = Index::from_one(1 << (bits - 2)).unwrap();
+        assert_eq!(deep.checked_concat(&Index::root().left()), Some(deep.left()));
+        assert_eq!(deep.checked_concat(&Index::root().left().right()), None);
+        assert_eq!(deep.left().checked_concat(&Index::root().right()), None);
+    }
+
     #[test]
     fn test_descendant() {
         assert!(Index::root().left().has_descendant(&Index::root().left().right().left().right().right()));
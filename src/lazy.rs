@@ -0,0 +1,235 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+
+use crate::index::Index;
+use crate::raw::Raw;
+use crate::traits::{Construct, WriteBackend, ValueOf, RootStatus, Owned, Dangling, Error, Tree};
+
+/// `LazyRaw` with owned root.
+pub type OwnedLazyRaw<C> = LazyRaw<Owned, C>;
+
+/// `LazyRaw` with dangling root.
+pub type DanglingLazyRaw<C> = LazyRaw<Dangling, C>;
+
+/// Raw merkle tree with lazy hashing. Changes are recorded against their
+/// indexes, and dirty paths are only hashed on flush, so that only the
+/// final intermediate nodes are written to the backend.
+pub struct LazyRaw<R: RootStatus, C: Construct> {
+    raw: Raw<R, C>,
+    pending: BTreeMap<Index, ValueOf<C>>,
+    dirty: BTreeSet<Index>,
+}
+
+impl<R: RootStatus, C: Construct> Default for LazyRaw<R, C> {
+    fn default() -> Self {
+        Self::from_raw(Raw::default())
+    }
+}
+
+impl<R: RootStatus, C: Construct> From<Raw<R, C>> for LazyRaw<R, C> {
+    fn from(raw: Raw<R, C>) -> Self {
+        Self::from_raw(raw)
+    }
+}
+
+impl<R: RootStatus, C: Construct> LazyRaw<R, C> {
+    /// Create a lazy tree from an existing raw tree.
+    pub fn from_raw(raw: Raw<R, C>) -> Self {
+        Self {
+            raw,
+            pending: BTreeMap::new(),
+            dirty: BTreeSet::new(),
+        }
+    }
+
+    /// Whether there are changes not yet flushed.
+    pub fn is_dirty(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Whether the node at index is pending, or is under a pending node.
+    fn is_covered(&self, index: Index) -> bool {
+        let mut current = Some(index);
+        while let Some(index) = current {
+            if self.pending.contains_key(&index) {
+                return true
+            }
+            current = index.parent();
+        }
+        false
+    }
+
+    /// Get value from the tree via generalized merkle index. Nodes on dirty
+    /// paths are hashed first.
+    pub fn get<DB: WriteBackend<Construct=C>>(
+        &mut self,
+        db: &mut DB,
+        index: Index
+    ) -> Result<Option<ValueOf<C>>, Error<DB::Error>> {
+        if let Some(value) = self.pending.get(&index) {
+            return Ok(Some(value.clone()))
+        }
+
+        if self.dirty.contains(&index) || self.is_covered(index) {
+            self.flush(db)?;
+        }
+        self.raw.get(db, index)
+    }
+
+    /// Record a change of value via generalized merkle index. Nothing is
+    /// hashed until the tree is flushed.
+    pub fn set<DB: WriteBackend<Construct=C>>(
+        &mut self,
+        db: &mut DB,
+        index: Index,
+        set: ValueOf<C>
+    ) -> Result<(), Error<DB::Error>> {
+        if !self.pending.contains_key(&index) &&
+            (self.dirty.contains(&index) || self.is_covered(index))
+        {
+            self.flush(db)?;
+        }
+
+        let mut current = index.parent();
+        while let Some(parent) = current {
+            if !self.dirty.insert(parent) {
+                break
+            }
+            current = parent.parent();
+        }
+        self.pending.insert(index, set);
+
+        Ok(())
+    }
+
+    /// Hash all dirty paths and write the final nodes to the backend.
+    pub fn flush<DB: WriteBackend<Construct=C>>(
+        &mut self,
+        db: &mut DB
+    ) -> Result<(), Error<DB::Error>> {
+        if self.pending.is_empty() {
+            return Ok(())
+        }
+
+        let sets = core::mem::take(&mut self.pending).into_iter().collect();
+        self.dirty.clear();
+        self.raw.set_many(db, sets)
+    }
+
+    /// Discard all changes not yet flushed.
+    pub fn discard(&mut self) {
+        self.pending.clear();
+        self.dirty.clear();
+    }
+
+    /// Flush and get the root of the tree.
+    pub fn root<DB: WriteBackend<Construct=C>>(
+        &mut self,
+        db: &mut DB
+    ) -> Result<ValueOf<C>, Error<DB::Error>> {
+        self.flush(db)?;
+        Ok(self.raw.root())
+    }
+
+    /// Flush and convert into the underlying raw tree.
+    pub fn into_raw<DB: WriteBackend<Construct=C>>(
+        mut self,
+        db: &mut DB
+    ) -> Result<Raw<R, C>, Error<DB::Error>> {
+        self.flush(db)?;
+        Ok(self.raw)
+    }
+
+    /// Drop the tree, discarding changes not yet flushed.
+    pub fn drop<DB: WriteBackend<Construct=C>>(
+        self,
+        db: &mut DB
+    ) -> Result<(), Error<DB::Error>> {
+        self.raw.drop(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::{Value, Backend, ReadBackend};
+    use crate::raw::OwnedRaw;
+    use crate::memory::InMemoryBackend;
+    use sha2::Sha256;
+
+    type Construct = crate::InheritedDigestConstruct<Sha256, Vec<u8>>;
+    type InMemory = InMemoryBackend<Construct>;
+
+    #[derive(Default)]
+    struct CountingBackend(InMemory, usize);
+
+    impl Backend for CountingBackend {
+        type Construct = Construct;
+        type Error = <InMemory as Backend>::Error;
+    }
+
+    impl ReadBackend for CountingBackend {
+        fn get(&mut self, key: &<Construct as crate::Construct>::Intermediate) -> Result<(ValueOf<Construct>, ValueOf<Construct>), Self::Error> {
+            self.0.get(key)
+        }
+    }
+
+    impl WriteBackend for CountingBackend {
+        fn rootify(&mut self, key: &<Construct as crate::Construct>::Intermediate) -> Result<(), Self::Error> {
+            self.0.rootify(key)
+        }
+
+        fn unrootify(&mut self, key: &<Construct as crate::Construct>::Intermediate) -> Result<(), Self::Error> {
+            self.0.unrootify(key)
+        }
+
+        fn insert(
+            &mut self,
+            key: <Construct as crate::Construct>::Intermediate,
+            value: (ValueOf<Construct>, ValueOf<Construct>)
+        ) -> Result<(), Self::Error> {
+            self.1 += 1;
+            self.0.insert(key, value)
+        }
+    }
+
+    #[test]
+    fn test_lazy_matches_eager() {
+        let mut eager_db = CountingBackend::default();
+        let mut lazy_db = CountingBackend::default();
+        let mut eager = OwnedRaw::<Construct>::default();
+        let mut lazy = OwnedLazyRaw::<Construct>::default();
+
+        for round in 0..100u32 {
+            let index = Index::from_one(32 + (round as usize * 7) % 32).unwrap();
+            let value = Value::End(round.to_le_bytes().to_vec());
+            eager.set(&mut eager_db, index, value.clone()).unwrap();
+            lazy.set(&mut lazy_db, index, value).unwrap();
+        }
+        assert!(lazy.is_dirty());
+        assert_eq!(lazy_db.1, 0);
+
+        assert_eq!(lazy.root(&mut lazy_db).unwrap(), eager.root());
+        assert!(!lazy.is_dirty());
+        assert_eq!(lazy_db.1, 31);
+        assert_eq!(eager_db.1, 100 * 5);
+    }
+
+    #[test]
+    fn test_overlapping_sets() {
+        let mut db = InMemory::default();
+        let mut eager = OwnedRaw::<Construct>::default();
+        let mut lazy = OwnedLazyRaw::<Construct>::default();
+
+        for (index, value) in [(8, 1u8), (9, 2), (4, 3), (10, 4), (2, 5), (5, 6)].iter() {
+            let index = Index::from_one(*index).unwrap();
+            eager.set(&mut db, index, Value::End(vec![*value])).unwrap();
+            lazy.set(&mut db, index, Value::End(vec![*value])).unwrap();
+            assert_eq!(lazy.get(&mut db, index).unwrap(), Some(Value::End(vec![*value])));
+            assert_eq!(lazy.get(&mut db, Index::root()).unwrap(), Some(eager.root()));
+        }
+
+        lazy.set(&mut db, Index::from_one(11).unwrap(), Value::End(vec![7])).unwrap();
+        lazy.discard();
+        assert_eq!(lazy.root(&mut db).unwrap(), eager.root());
+    }
+}
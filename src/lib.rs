@@ -7,19 +7,29 @@ extern crate alloc;
 
 mod traits;
 mod memory;
+mod hasher;
+mod empty;
 mod raw;
+mod lazy;
 mod index;
 mod vector;
 mod list;
 mod packed;
 mod length;
 mod proving;
+mod map;
+mod sparse;
+mod mmr;
+mod incremental;
 
 pub mod utils;
 
 pub use crate::traits::{Backend, ReadBackend, WriteBackend, Construct, Value, ValueOf, Dangling, Owned, RootStatus, Error, Sequence, Tree, Leak};
-pub use crate::memory::{EmptyStatus, UnitEmpty, InheritedEmpty, UnitDigestConstruct, InheritedDigestConstruct, InMemoryBackend, InMemoryBackendError, NoopBackend, NoopBackendError};
+pub use crate::memory::{EmptyStatus, UnitEmpty, InheritedEmpty, UnitDigestConstruct, InheritedDigestConstruct, DomainDigestConstruct, InMemoryBackend, InMemoryBackendError, NoopBackend, NoopBackendError};
+pub use crate::hasher::{MerkleHasher, HasherConstruct};
+pub use crate::empty::{EmptyTable, DEFAULT_MAX_EMPTY_DEPTH, inherited_empty_at};
 pub use crate::raw::{Raw, OwnedRaw, DanglingRaw};
+pub use crate::lazy::{LazyRaw, OwnedLazyRaw, DanglingLazyRaw};
 pub use crate::index::{Index, IndexSelection, IndexRoute};
 pub use crate::vector::{Vector, OwnedVector, DanglingVector};
 pub use crate::list::{List, OwnedList, DanglingList};
@@ -27,3 +37,8 @@ pub use crate::packed::{PackedVector, OwnedPackedVector, DanglingPackedVector,
                         PackedList, OwnedPackedList, DanglingPackedList};
 pub use crate::length::LengthMixed;
 pub use crate::proving::{ProvingBackend, Proofs, CompactValue};
+pub use crate::map::{MerkleMap, OwnedMerkleMap, DanglingMerkleMap};
+pub use crate::sparse::{SparseMerkleTree, OwnedSparseMerkleTree, DanglingSparseMerkleTree,
+                        SparseProof, CompressedSparseProof, SparseKey, SPARSE_DEPTH};
+pub use crate::mmr::{Mmr, OwnedMmr, DanglingMmr, MmrProof, MmrConsistencyProof};
+pub use crate::incremental::{IncrementalTree, Frontier};
@@ -0,0 +1,355 @@
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+use core::hash::Hash;
+use alloc::vec::Vec;
+
+use crate::traits::{ReadBackend, WriteBackend, Construct, Value, ValueOf, RootStatus, Dangling, Owned, Leak, Error, Tree, Sequence};
+use crate::list::List;
+use crate::raw::Raw;
+use crate::index::Index;
+use crate::proving::{ProvingBackend, Proofs};
+
+const LEN_INDEX: Index = Index::root().right();
+
+/// `MerkleMap` with owned root.
+pub type OwnedMerkleMap<C, K, V> = MerkleMap<Owned, C, K, V>;
+
+/// `MerkleMap` with dangling root.
+pub type DanglingMerkleMap<C, K, V> = MerkleMap<Dangling, C, K, V>;
+
+/// Binary merkle map, with entries kept sorted by key. Each entry is
+/// stored as an intermediate of key and value in a list.
+pub struct MerkleMap<R: RootStatus, C: Construct, K, V> {
+    list: List<R, C>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<R: RootStatus, C: Construct, K, V> MerkleMap<R, C, K, V> where
+    C::End: From<usize> + Into<usize>,
+    K: Ord + Clone + From<C::End> + Into<C::End>,
+    V: From<C::End> + Into<C::End>,
+{
+    fn entry<DB: ReadBackend<Construct=C>>(&self, db: &mut DB, index: usize) -> Result<(K, V), Error<DB::Error>> {
+        let entry = self.list.get(db, index)?.intermediate().ok_or(Error::CorruptedDatabase)?;
+        let (key, value) = db.get(&entry)?;
+
+        Ok((K::from(key.end().ok_or(Error::CorruptedDatabase)?),
+            V::from(value.end().ok_or(Error::CorruptedDatabase)?)))
+    }
+
+    fn entry_value<DB: WriteBackend<Construct=C>>(db: &mut DB, key: K, value: V) -> Result<ValueOf<C>, Error<DB::Error>> {
+        let pair = (Value::End(key.into()), Value::End(value.into()));
+        let entry = C::intermediate_of(&pair.0, &pair.1);
+        db.insert(entry.clone(), pair)?;
+        Ok(Value::Intermediate(entry))
+    }
+
+    /// Binary search the key. Returns `Ok` with the entry index if the key
+    /// exists, otherwise `Err` with the index where it would be inserted.
+    ///
+    /// When the key does not exist, the entries immediately before and
+    /// after the insertion index are always visited.
+    pub fn search<DB: ReadBackend<Construct=C>>(&self, db: &mut DB, key: &K) -> Result<Result<usize, usize>, Error<DB::Error>> {
+        let mut low = 0;
+        let mut high = self.len();
+
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let (mid_key, _) = self.entry(db, mid)?;
+
+            if &mid_key == key {
+                return Ok(Ok(mid))
+            } else if &mid_key < key {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        Ok(Err(low))
+    }
+
+    /// Get value of the key.
+    pub fn get<DB: ReadBackend<Construct=C>>(&self, db: &mut DB, key: &K) -> Result<Option<V>, Error<DB::Error>> {
+        match self.search(db, key)? {
+            Ok(index) => Ok(Some(self.entry(db, index)?.1)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Insert a key value pair, returning the old value if it exists.
+    pub fn insert<DB: WriteBackend<Construct=C>>(&mut self, db: &mut DB, key: K, value: V) -> Result<Option<V>, Error<DB::Error>> {
+        match self.search(db, &key)? {
+            Ok(index) => {
+                let (_, old) = self.entry(db, index)?;
+                let entry = Self::entry_value(db, key, value)?;
+                self.list.set(db, index, entry)?;
+                Ok(Some(old))
+            },
+            Err(index) => {
+                let entry = Self::entry_value(db, key, value)?;
+                let len = self.len();
+
+                if index == len {
+                    self.list.push(db, entry)?;
+                } else {
+                    let last = self.list.get(db, len - 1)?;
+                    self.list.push(db, last)?;
+                    for i in (index..(len - 1)).rev() {
+                        let moved = self.list.get(db, i)?;
+                        self.list.set(db, i + 1, moved)?;
+                    }
+                    self.list.set(db, index, entry)?;
+                }
+                Ok(None)
+            },
+        }
+    }
+
+    /// Remove a key, returning its value if it exists.
+    pub fn remove<DB: WriteBackend<Construct=C>>(&mut self, db: &mut DB, key: &K) -> Result<Option<V>, Error<DB::Error>> {
+        match self.search(db, key)? {
+            Ok(index) => {
+                let (_, old) = self.entry(db, index)?;
+                let len = self.len();
+
+                for i in index..(len - 1) {
+                    let moved = self.list.get(db, i + 1)?;
+                    self.list.set(db, i, moved)?;
+                }
+                self.list.pop(db)?;
+                Ok(Some(old))
+            },
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Get all entries whose keys are in the range, in key order.
+    pub fn range<DB: ReadBackend<Construct=C>, B: RangeBounds<K>>(&self, db: &mut DB, range: B) -> Result<Vec<(K, V)>, Error<DB::Error>> {
+        let start = match range.start_bound() {
+            Bound::Included(key) => self.search(db, key)?.unwrap_or_else(|index| index),
+            Bound::Excluded(key) => match self.search(db, key)? {
+                Ok(index) => index + 1,
+                Err(index) => index,
+            },
+            Bound::Unbounded => 0,
+        };
+
+        let mut ret = Vec::new();
+        for index in start..self.len() {
+            let (key, value) = self.entry(db, index)?;
+            let in_range = match range.end_bound() {
+                Bound::Included(end) => &key <= end,
+                Bound::Excluded(end) => &key < end,
+                Bound::Unbounded => true,
+            };
+            if !in_range {
+                break
+            }
+            ret.push((key, value));
+        }
+
+        Ok(ret)
+    }
+
+    /// Generate proofs of the key. If the key exists, the proofs contain
+    /// its entry, otherwise the proofs contain its neighbouring entries,
+    /// showing that the key is absent. Either way, the proofs can be
+    /// verified by reconstructing the map and calling `get`.
+    pub fn prove<DB: ReadBackend<Construct=C>>(&self, db: &mut DB, key: &K) -> Result<Proofs<C>, Error<DB::Error>> where
+        C::Intermediate: Eq + Hash + Ord,
+    {
+        let mut proving = ProvingBackend::new(db);
+        Raw::<Dangling, C>::from_leaked(self.root()).get(&mut proving, LEN_INDEX)?;
+        let _ = self.search(&mut proving, key)?;
+        Ok(proving.into_proofs())
+    }
+
+    /// Deconstruct the map into one single hash value, and leak only the hash value.
+    pub fn deconstruct<DB: ReadBackend<Construct=C>>(self, db: &mut DB) -> Result<ValueOf<C>, Error<DB::Error>> {
+        self.list.deconstruct(db)
+    }
+
+    /// Reconstruct the map from a single hash value.
+    pub fn reconstruct<DB: WriteBackend<Construct=C>>(root: ValueOf<C>, db: &mut DB, max_len: Option<usize>) -> Result<Self, Error<DB::Error>> {
+        Ok(Self {
+            list: List::reconstruct(root, db, max_len)?,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<R: RootStatus, C: Construct, K, V> Tree for MerkleMap<R, C, K, V> where
+    C::End: From<usize> + Into<usize>,
+{
+    type RootStatus = R;
+    type Construct = C;
+
+    fn root(&self) -> ValueOf<C> {
+        self.list.root()
+    }
+
+    fn drop<DB: WriteBackend<Construct=C>>(self, db: &mut DB) -> Result<(), Error<DB::Error>> {
+        self.list.drop(db)
+    }
+
+    fn into_raw(self) -> Raw<R, C> {
+        self.list.into_raw()
+    }
+}
+
+impl<R: RootStatus, C: Construct, K, V> Sequence for MerkleMap<R, C, K, V> where
+    C::End: From<usize> + Into<usize>,
+{
+    fn len(&self) -> usize {
+        self.list.len()
+    }
+}
+
+impl<R: RootStatus, C: Construct, K, V> Leak for MerkleMap<R, C, K, V> where
+    C::End: From<usize> + Into<usize>,
+{
+    type Metadata = <List<R, C> as Leak>::Metadata;
+
+    fn metadata(&self) -> Self::Metadata {
+        self.list.metadata()
+    }
+
+    fn from_leaked(metadata: Self::Metadata) -> Self {
+        Self {
+            list: List::from_leaked(metadata),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<C: Construct, K, V> MerkleMap<Owned, C, K, V> where
+    C::End: From<usize> + Into<usize>,
+{
+    /// Create a new map.
+    pub fn create<DB: WriteBackend<Construct=C>>(
+        db: &mut DB,
+        max_len: Option<usize>
+    ) -> Result<Self, Error<DB::Error>> {
+        Ok(Self {
+            list: List::create(db, max_len)?,
+            _marker: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::InMemoryBackend;
+    use sha2::Sha256;
+
+    type InMemory = InMemoryBackend<crate::InheritedDigestConstruct<Sha256, MapValue>>;
+
+    #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+    struct MapValue([u8; 8]);
+
+    impl AsRef<[u8]> for MapValue {
+        fn as_ref(&self) -> &[u8] {
+            self.0.as_ref()
+        }
+    }
+
+    impl From<usize> for MapValue {
+        fn from(value: usize) -> Self {
+            MapValue((value as u64).to_be_bytes())
+        }
+    }
+
+    impl Into<usize> for MapValue {
+        fn into(self) -> usize {
+            u64::from_be_bytes(self.0) as usize
+        }
+    }
+
+    fn k(value: usize) -> MapValue {
+        value.into()
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut db = InMemory::default();
+        let mut map = OwnedMerkleMap::<_, MapValue, MapValue>::create(&mut db, None).unwrap();
+
+        for i in &[5, 1, 9, 3, 7, 0, 8, 2, 6, 4] {
+            assert_eq!(map.insert(&mut db, k(*i), k(*i * 10)).unwrap(), None);
+        }
+        assert_eq!(map.len(), 10);
+        for i in 0..10 {
+            assert_eq!(map.get(&mut db, &k(i)).unwrap(), Some(k(i * 10)));
+        }
+        assert_eq!(map.get(&mut db, &k(10)).unwrap(), None);
+
+        assert_eq!(map.insert(&mut db, k(3), k(33)).unwrap(), Some(k(30)));
+        assert_eq!(map.get(&mut db, &k(3)).unwrap(), Some(k(33)));
+        assert_eq!(map.len(), 10);
+
+        assert_eq!(map.remove(&mut db, &k(3)).unwrap(), Some(k(33)));
+        assert_eq!(map.remove(&mut db, &k(3)).unwrap(), None);
+        assert_eq!(map.get(&mut db, &k(3)).unwrap(), None);
+        assert_eq!(map.len(), 9);
+    }
+
+    #[test]
+    fn test_order_independent_root() {
+        let mut db1 = InMemory::default();
+        let mut db2 = InMemory::default();
+        let mut map1 = OwnedMerkleMap::<_, MapValue, MapValue>::create(&mut db1, None).unwrap();
+        let mut map2 = OwnedMerkleMap::<_, MapValue, MapValue>::create(&mut db2, None).unwrap();
+
+        for i in 0..20 {
+            map1.insert(&mut db1, k(i), k(i + 1)).unwrap();
+            map2.insert(&mut db2, k(19 - i), k(20 - i)).unwrap();
+        }
+        assert_eq!(map1.root(), map2.root());
+
+        map1.insert(&mut db1, k(100), k(1)).unwrap();
+        map1.remove(&mut db1, &k(100)).unwrap();
+        assert_eq!(map1.root(), map2.root());
+    }
+
+    #[test]
+    fn test_range() {
+        let mut db = InMemory::default();
+        let mut map = OwnedMerkleMap::<_, MapValue, MapValue>::create(&mut db, None).unwrap();
+
+        for i in 0..10 {
+            map.insert(&mut db, k(i * 2), k(i)).unwrap();
+        }
+
+        let keys = |entries: Vec<(MapValue, MapValue)>| entries.into_iter().map(|(key, _)| key.into()).collect::<Vec<usize>>();
+        assert_eq!(keys(map.range(&mut db, k(3)..k(9)).unwrap()), vec![4, 6, 8]);
+        assert_eq!(keys(map.range(&mut db, k(4)..=k(8)).unwrap()), vec![4, 6, 8]);
+        assert_eq!(keys(map.range(&mut db, ..k(3)).unwrap()), vec![0, 2]);
+        assert_eq!(keys(map.range(&mut db, k(15)..).unwrap()), vec![16, 18]);
+        assert_eq!(keys(map.range(&mut db, (Bound::Excluded(k(16)), Bound::Unbounded)).unwrap()), vec![18]);
+        assert!(map.range(&mut db, k(19)..).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_proofs() {
+        let mut db = InMemory::default();
+        let mut map = OwnedMerkleMap::<_, MapValue, MapValue>::create(&mut db, None).unwrap();
+
+        for i in 0..50 {
+            map.insert(&mut db, k(i * 2), k(i)).unwrap();
+        }
+        let root = map.root();
+
+        for key in &[0, 1, 42, 43, 98, 99] {
+            let proofs = map.prove(&mut db, &k(*key)).unwrap();
+
+            let mut proved = InMemory::default();
+            proved.populate(proofs.into());
+            let proved_map = OwnedMerkleMap::<_, MapValue, MapValue>::reconstruct(root.clone(), &mut proved, None).unwrap();
+            assert_eq!(proved_map.len(), 50);
+            let expected = if key % 2 == 0 { Some(k(key / 2)) } else { None };
+            assert_eq!(proved_map.get(&mut proved, &k(*key)).unwrap(), expected);
+        }
+    }
+}
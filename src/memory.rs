@@ -2,12 +2,16 @@
 use std::collections::HashMap as Map;
 #[cfg(not(feature = "std"))]
 use alloc::collections::BTreeMap as Map;
+use alloc::vec::Vec;
 use generic_array::GenericArray;
 use digest::Digest;
 use core::marker::PhantomData;
 use core::hash::Hash;
 
-use crate::{Value, ValueOf, Construct, Backend, ReadBackend, WriteBackend};
+use crate::{Value, ValueOf, Construct, Backend, ReadBackend, WriteBackend, DEFAULT_MAX_EMPTY_DEPTH,
+            inherited_empty_at};
+#[cfg(feature = "std")]
+use crate::EmptyTable;
 
 /// Empty status.
 pub trait EmptyStatus {
@@ -31,6 +35,18 @@ impl EmptyStatus for UnitEmpty {
     fn is_unit() -> bool { true }
 }
 
+/// Hash pairs of children with one digest, which is reset after each pair.
+fn digest_batch<D: Digest, V, F: FnMut(&mut D, &V, &V)>(
+    pairs: &[(V, V)],
+    mut input: F
+) -> Vec<GenericArray<u8, D::OutputSize>> {
+    let mut digest = D::new();
+    pairs.iter().map(|(left, right)| {
+        input(&mut digest, left, right);
+        digest.result_reset()
+    }).collect()
+}
+
 /// Unit Digest construct.
 pub struct UnitDigestConstruct<D: Digest, T: AsRef<[u8]> + Clone + Default>(PhantomData<(D, T)>);
 
@@ -45,18 +61,29 @@ impl<D: Digest, T: AsRef<[u8]> + Clone + Default> Construct for UnitDigestConstr
         digest.result()
     }
 
+    fn intermediate_of_batch(pairs: &[(ValueOf<Self>, ValueOf<Self>)]) -> Vec<Self::Intermediate> {
+        digest_batch::<D, _, _>(pairs, |digest, left, right| {
+            digest.input(left.as_ref());
+            digest.input(right.as_ref());
+        })
+    }
+
     fn empty_at<DB: WriteBackend<Construct=Self>>(
         _db: &mut DB,
         _depth_to_bottom: usize
     ) -> Result<ValueOf<Self>, DB::Error> {
         Ok(Value::End(Default::default()))
     }
+
+    fn empty_value(_depth_to_bottom: usize) -> Option<ValueOf<Self>> {
+        Some(Value::End(Default::default()))
+    }
 }
 
 /// Inherited Digest construct.
 pub struct InheritedDigestConstruct<D: Digest, T: AsRef<[u8]> + Clone + Default>(PhantomData<(D, T)>);
 
-impl<D: Digest, T: AsRef<[u8]> + Clone + Default> Construct for InheritedDigestConstruct<D, T> {
+impl<D: Digest + 'static, T: AsRef<[u8]> + Clone + Default + 'static> Construct for InheritedDigestConstruct<D, T> {
     type Intermediate = GenericArray<u8, D::OutputSize>;
     type End = T;
 
@@ -67,18 +94,90 @@ impl<D: Digest, T: AsRef<[u8]> + Clone + Default> Construct for InheritedDigestC
         digest.result()
     }
 
+    fn intermediate_of_batch(pairs: &[(ValueOf<Self>, ValueOf<Self>)]) -> Vec<Self::Intermediate> {
+        digest_batch::<D, _, _>(pairs, |digest, left, right| {
+            digest.input(left.as_ref());
+            digest.input(right.as_ref());
+        })
+    }
+
     fn empty_at<DB: WriteBackend<Construct=Self>>(
         db: &mut DB,
         depth_to_bottom: usize
     ) -> Result<ValueOf<Self>, DB::Error> {
-        let mut current = Value::End(Default::default());
-        for _ in 0..depth_to_bottom {
-            let value = (current.clone(), current);
-            let key = Self::intermediate_of(&value.0, &value.1);
-            db.insert(key.clone(), value)?;
-            current = Value::Intermediate(key);
+        inherited_empty_at(db, depth_to_bottom)
+    }
+
+    #[cfg(feature = "std")]
+    fn empty_value(depth_to_bottom: usize) -> Option<ValueOf<Self>> {
+        EmptyTable::<Self>::with_shared(|table| table.at(depth_to_bottom))
+    }
+
+    #[cfg(feature = "std")]
+    fn empty_depth(key: &Self::Intermediate) -> Option<usize> {
+        EmptyTable::<Self>::with_shared(|table| table.depth_of(key))
+    }
+}
+
+/// Domain separation prefix of leaf hashing.
+const LEAF_PREFIX: u8 = 0x00;
+/// Domain separation prefix of node hashing.
+const NODE_PREFIX: u8 = 0x01;
+
+/// Domain separated digest construct, in the style of RFC 6962. End values
+/// are hashed into leaves as `H(0x00 || end)`, and nodes are hashed as
+/// `H(0x01 || left || right)`. Empty values are inherited.
+pub struct DomainDigestConstruct<D: Digest, T: AsRef<[u8]> + Clone + Default>(PhantomData<(D, T)>);
+
+impl<D: Digest + 'static, T: AsRef<[u8]> + Clone + Default + 'static> DomainDigestConstruct<D, T> {
+    fn input_child(digest: &mut D, child: &ValueOf<Self>) {
+        match child {
+            Value::Intermediate(intermediate) => digest.input(&intermediate[..]),
+            Value::End(end) => {
+                let mut leaf = D::new();
+                leaf.input([LEAF_PREFIX]);
+                leaf.input(end.as_ref());
+                digest.input(&leaf.result()[..]);
+            },
         }
-        Ok(current)
+    }
+}
+
+impl<D: Digest + 'static, T: AsRef<[u8]> + Clone + Default + 'static> Construct for DomainDigestConstruct<D, T> {
+    type Intermediate = GenericArray<u8, D::OutputSize>;
+    type End = T;
+
+    fn intermediate_of(left: &ValueOf<Self>, right: &ValueOf<Self>) -> Self::Intermediate {
+        let mut digest = D::new();
+        digest.input([NODE_PREFIX]);
+        Self::input_child(&mut digest, left);
+        Self::input_child(&mut digest, right);
+        digest.result()
+    }
+
+    fn intermediate_of_batch(pairs: &[(ValueOf<Self>, ValueOf<Self>)]) -> Vec<Self::Intermediate> {
+        digest_batch::<D, _, _>(pairs, |digest, left, right| {
+            digest.input([NODE_PREFIX]);
+            Self::input_child(digest, left);
+            Self::input_child(digest, right);
+        })
+    }
+
+    fn empty_at<DB: WriteBackend<Construct=Self>>(
+        db: &mut DB,
+        depth_to_bottom: usize
+    ) -> Result<ValueOf<Self>, DB::Error> {
+        inherited_empty_at(db, depth_to_bottom)
+    }
+
+    #[cfg(feature = "std")]
+    fn empty_value(depth_to_bottom: usize) -> Option<ValueOf<Self>> {
+        EmptyTable::<Self>::with_shared(|table| table.at(depth_to_bottom))
+    }
+
+    #[cfg(feature = "std")]
+    fn empty_depth(key: &Self::Intermediate) -> Option<usize> {
+        EmptyTable::<Self>::with_shared(|table| table.depth_of(key))
     }
 }
 
@@ -89,22 +188,30 @@ pub enum NoopBackendError {
     NotSupported,
 }
 
-/// Noop merkle database.
+/// Noop merkle database. Only inherited empty values of the construct can
+/// be fetched.
 pub struct NoopBackend<C: Construct>(
+    usize,
     PhantomData<C>,
 );
 
-impl<C: Construct> Default for NoopBackend<C> where
-    C::Intermediate: Eq + Hash + Ord
-{
+impl<C: Construct> Default for NoopBackend<C> {
     fn default() -> Self {
-        Self(Default::default())
+        Self(DEFAULT_MAX_EMPTY_DEPTH, PhantomData)
+    }
+}
+
+impl<C: Construct> NoopBackend<C> {
+    /// Create a new noop database, recognising inherited empty values up to
+    /// given maximum depth.
+    pub fn with_max_empty_depth(max_depth: usize) -> Self {
+        Self(max_depth, PhantomData)
     }
 }
 
 impl<C: Construct> Clone for NoopBackend<C> {
     fn clone(&self) -> Self {
-        Self(self.0.clone())
+        Self(self.0, PhantomData)
     }
 }
 
@@ -116,9 +223,13 @@ impl<C: Construct> Backend for NoopBackend<C> {
 impl<C: Construct> ReadBackend for NoopBackend<C> {
     fn get(
         &mut self,
-        _key: &C::Intermediate,
+        key: &C::Intermediate,
     ) -> Result<(ValueOf<C>, ValueOf<C>), Self::Error> {
-        Err(NoopBackendError::NotSupported)
+        empty_children::<C>(key, self.0).ok_or(NoopBackendError::NotSupported)
+    }
+
+    fn contains(&mut self, key: &C::Intermediate) -> Result<bool, Self::Error> {
+        Ok(empty_children::<C>(key, self.0).is_some())
     }
 }
 
@@ -138,6 +249,21 @@ impl<C: Construct> WriteBackend for NoopBackend<C> {
     ) -> Result<(), Self::Error> {
         Ok(())
     }
+
+    fn virtual_empty_depth(&self) -> usize {
+        self.0
+    }
+}
+
+/// Children of the key if it is an inherited empty value of the construct,
+/// up to given maximum depth.
+fn empty_children<C: Construct>(
+    key: &C::Intermediate,
+    max_depth: usize
+) -> Option<(ValueOf<C>, ValueOf<C>)> {
+    let depth = C::empty_depth(key).filter(|depth| *depth <= max_depth)?;
+    let child = C::empty_value(depth - 1)?;
+    Some((child.clone(), child))
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -151,29 +277,45 @@ pub enum InMemoryBackendError {
     SetIntermediateNotExist
 }
 
-/// In-memory merkle database.
+/// In-memory merkle database. Inherited empty values of the construct are
+/// not stored.
 pub struct InMemoryBackend<C: Construct>(
     Map<C::Intermediate, ((ValueOf<C>, ValueOf<C>), Option<usize>)>,
+    usize,
 );
 
 impl<C: Construct> Default for InMemoryBackend<C> where
     C::Intermediate: Eq + Hash + Ord
 {
     fn default() -> Self {
-        Self(Default::default())
+        Self(Default::default(), DEFAULT_MAX_EMPTY_DEPTH)
     }
 }
 
 impl<C: Construct> Clone for InMemoryBackend<C> {
     fn clone(&self) -> Self {
-        Self(self.0.clone())
+        Self(self.0.clone(), self.1)
     }
 }
 
 impl<C: Construct> InMemoryBackend<C> where
     C::Intermediate: Eq + Hash + Ord,
 {
+    /// Create a new in-memory database, recognising inherited empty values
+    /// up to given maximum depth.
+    pub fn with_max_empty_depth(max_depth: usize) -> Self {
+        Self(Default::default(), max_depth)
+    }
+
+    fn is_empty_value(&self, key: &C::Intermediate) -> bool {
+        C::empty_depth(key).map(|depth| depth <= self.1).unwrap_or(false)
+    }
+
     fn remove(&mut self, old_key: &C::Intermediate) -> Result<(), InMemoryBackendError> {
+        if self.is_empty_value(old_key) {
+            return Ok(())
+        }
+
         let (old_value, to_remove) = {
             let value = self.0.get_mut(old_key).ok_or(InMemoryBackendError::SetIntermediateNotExist)?;
             value.1.as_mut().map(|v| *v -= 1);
@@ -220,7 +362,13 @@ impl<C: Construct> ReadBackend for InMemoryBackend<C> where
     C::Intermediate: Eq + Hash + Ord,
 {
     fn get(&mut self, key: &C::Intermediate) -> Result<(ValueOf<C>, ValueOf<C>), Self::Error> {
-        self.0.get(key).map(|v| v.0.clone()).ok_or(InMemoryBackendError::FetchingKeyNotExist)
+        self.0.get(key).map(|v| v.0.clone())
+            .or_else(|| empty_children::<C>(key, self.1))
+            .ok_or(InMemoryBackendError::FetchingKeyNotExist)
+    }
+
+    fn contains(&mut self, key: &C::Intermediate) -> Result<bool, Self::Error> {
+        Ok(self.0.contains_key(key) || empty_children::<C>(key, self.1).is_some())
     }
 }
 
@@ -228,6 +376,10 @@ impl<C: Construct> WriteBackend for InMemoryBackend<C> where
     C::Intermediate: Eq + Hash + Ord,
 {
     fn rootify(&mut self, key: &C::Intermediate) -> Result<(), Self::Error> {
+        if self.is_empty_value(key) {
+            return Ok(())
+        }
+
         self.0.get_mut(key).ok_or(InMemoryBackendError::RootifyKeyNotExist)?.1
             .as_mut().map(|v| *v += 1);
         Ok(())
@@ -243,13 +395,14 @@ impl<C: Construct> WriteBackend for InMemoryBackend<C> where
         key: C::Intermediate,
         value: (ValueOf<C>, ValueOf<C>)
     ) -> Result<(), Self::Error> {
-        if self.0.contains_key(&key) {
+        if self.0.contains_key(&key) || self.is_empty_value(&key) {
             return Ok(())
         }
 
         let (left, right) = value;
 
         match &left {
+            Value::Intermediate(ref subkey) if self.is_empty_value(subkey) => (),
             Value::Intermediate(ref subkey) => {
                 self.0.get_mut(subkey).ok_or(InMemoryBackendError::SetIntermediateNotExist)?.1
                     .as_mut().map(|v| *v += 1);
@@ -257,6 +410,7 @@ impl<C: Construct> WriteBackend for InMemoryBackend<C> where
             Value::End(_) => (),
         }
         match &right {
+            Value::Intermediate(ref subkey) if self.is_empty_value(subkey) => (),
             Value::Intermediate(ref subkey) => {
                 self.0.get_mut(subkey).ok_or(InMemoryBackendError::SetIntermediateNotExist)?.1
                     .as_mut().map(|v| *v += 1);
@@ -267,4 +421,8 @@ impl<C: Construct> WriteBackend for InMemoryBackend<C> where
         self.0.insert(key, ((left, right), Some(0)));
         Ok(())
     }
+
+    fn virtual_empty_depth(&self) -> usize {
+        self.1
+    }
 }
@@ -0,0 +1,662 @@
+use core::fmt;
+use alloc::vec::Vec;
+
+use crate::traits::{Construct, ReadBackend, WriteBackend, Value, ValueOf,
+                    RootStatus, Owned, Dangling, Leak, Error, Tree, Sequence};
+use crate::raw::Raw;
+use crate::index::Index;
+
+/// `Mmr` with owned root.
+pub type OwnedMmr<C> = Mmr<Owned, C>;
+
+/// `Mmr` with dangling root.
+pub type DanglingMmr<C> = Mmr<Dangling, C>;
+
+/// Peaks of a mountain range of given size, as start leaf and height, from
+/// the leftmost peak.
+fn peaks_of(size: usize) -> Vec<(usize, usize)> {
+    let mut peaks = Vec::new();
+    let mut start = 0;
+    for height in (0..(core::mem::size_of::<usize>() * 8)).rev() {
+        if size & (1 << height) != 0 {
+            peaks.push((start, height));
+            start += 1 << height;
+        }
+    }
+    peaks
+}
+
+/// Merkle index of peak `at` in a mountain range of `count` peaks. Peaks
+/// are bagged at the left child of the root, with the size at the right.
+fn peak_index(at: usize, count: usize) -> Index {
+    let mut index = Index::root().left();
+    for _ in 0..at {
+        index = index.right();
+    }
+    if at + 1 < count {
+        index = index.left();
+    }
+    index
+}
+
+/// Merkle index of the node at `offset` of `depth` below `index`.
+fn descendant_index(mut index: Index, depth: usize, offset: usize) -> Index {
+    for d in (0..depth).rev() {
+        if offset & (1 << d) == 0 {
+            index = index.left();
+        } else {
+            index = index.right();
+        }
+    }
+    index
+}
+
+/// Bag peaks from right to left, into `H(p0, H(p1, ... H(pk-2, pk-1)))`.
+fn bag<C: Construct>(peaks: &[ValueOf<C>]) -> ValueOf<C> {
+    let mut peaks = peaks.iter().rev();
+    let mut root = match peaks.next() {
+        Some(peak) => peak.clone(),
+        None => return Value::End(Default::default()),
+    };
+    for peak in peaks {
+        root = Value::Intermediate(C::intermediate_of(peak, &root));
+    }
+    root
+}
+
+/// Mix the size of the mountain range into the bagged root, as
+/// `H(bag, size)`. Returns the root key and its children.
+fn mix_size<C: Construct>(
+    bag: ValueOf<C>,
+    size: usize
+) -> (C::Intermediate, (ValueOf<C>, ValueOf<C>)) where
+    C::End: From<usize>,
+{
+    let size = Value::End(size.into());
+    (C::intermediate_of(&bag, &size), (bag, size))
+}
+
+/// Root of a mountain range of given size, from its peaks.
+fn root_of<C: Construct>(peaks: &[ValueOf<C>], size: usize) -> ValueOf<C> where
+    C::End: From<usize>,
+{
+    Value::Intermediate(mix_size::<C>(bag::<C>(peaks), size).0)
+}
+
+/// Climb from a node at `offset` of its level, through siblings, returning
+/// the value of the ancestor.
+fn climb<C: Construct>(
+    mut current: ValueOf<C>,
+    offset: usize,
+    siblings: &[ValueOf<C>]
+) -> ValueOf<C> {
+    for (level, sibling) in siblings.iter().enumerate() {
+        current = if (offset >> level) & 1 == 0 {
+            Value::Intermediate(C::intermediate_of(&current, sibling))
+        } else {
+            Value::Intermediate(C::intermediate_of(sibling, &current))
+        };
+    }
+    current
+}
+
+/// Merkle mountain range, an append-only accumulator consisting of perfect
+/// binary trees. The peaks are bagged, and mixed with the size into the
+/// root.
+pub struct Mmr<R: RootStatus, C: Construct> {
+    raw: Raw<R, C>,
+    len: usize,
+}
+
+impl<R: RootStatus, C: Construct> Mmr<R, C> where
+    C::End: From<usize>,
+{
+    /// Get the node of a perfect subtree starting at leaf `start` with
+    /// `height`. The subtree must be within one of the current peaks.
+    fn node<DB: ReadBackend<Construct=C>>(
+        &self,
+        db: &mut DB,
+        start: usize,
+        height: usize
+    ) -> Result<ValueOf<C>, Error<DB::Error>> {
+        let peaks = peaks_of(self.len);
+        let (at, (peak_start, peak_height)) = peaks.iter().cloned().enumerate()
+            .find(|(_, (peak_start, peak_height))| {
+                start >= *peak_start && start < peak_start + (1 << peak_height)
+            })
+            .ok_or(Error::AccessOverflowed)?;
+        if height > peak_height {
+            return Err(Error::InvalidParameter)
+        }
+
+        let index = descendant_index(
+            peak_index(at, peaks.len()),
+            peak_height - height,
+            (start - peak_start) >> height,
+        );
+        self.raw.get(db, index)?.ok_or(Error::CorruptedDatabase)
+    }
+
+    /// Get peaks of the mountain range at a historical size.
+    fn peaks_at<DB: ReadBackend<Construct=C>>(
+        &self,
+        db: &mut DB,
+        size: usize
+    ) -> Result<Vec<ValueOf<C>>, Error<DB::Error>> {
+        if size > self.len {
+            return Err(Error::AccessOverflowed)
+        }
+
+        peaks_of(size).into_iter()
+            .map(|(start, height)| self.node(db, start, height))
+            .collect()
+    }
+
+    /// Get leaf at index.
+    pub fn get<DB: ReadBackend<Construct=C>>(
+        &self,
+        db: &mut DB,
+        index: usize
+    ) -> Result<ValueOf<C>, Error<DB::Error>> {
+        self.node(db, index, 0)
+    }
+
+    /// Get the root of the mountain range at a historical size.
+    pub fn root_at<DB: ReadBackend<Construct=C>>(
+        &self,
+        db: &mut DB,
+        size: usize
+    ) -> Result<ValueOf<C>, Error<DB::Error>> {
+        Ok(root_of::<C>(&self.peaks_at(db, size)?, size))
+    }
+
+    /// Append a new leaf.
+    pub fn push<DB: WriteBackend<Construct=C>>(
+        &mut self,
+        db: &mut DB,
+        value: ValueOf<C>
+    ) -> Result<(), Error<DB::Error>> {
+        let mut peaks = self.peaks_at(db, self.len)?;
+        let mut heights = peaks_of(self.len).into_iter()
+            .map(|(_, height)| height).collect::<Vec<_>>();
+
+        let mut current = value;
+        let mut height = 0;
+        while heights.last() == Some(&height) {
+            heights.pop();
+            let left = peaks.pop().ok_or(Error::CorruptedDatabase)?;
+            let key = C::intermediate_of(&left, &current);
+            db.insert(key.clone(), (left, current))?;
+            current = Value::Intermediate(key);
+            height += 1;
+        }
+        peaks.push(current);
+
+        let mut root = peaks.pop().ok_or(Error::CorruptedDatabase)?;
+        while let Some(peak) = peaks.pop() {
+            let key = C::intermediate_of(&peak, &root);
+            db.insert(key.clone(), (peak, root))?;
+            root = Value::Intermediate(key);
+        }
+
+        let (key, value) = mix_size::<C>(root, self.len + 1);
+        db.insert(key.clone(), value)?;
+        self.raw.set(db, Index::root(), Value::Intermediate(key))?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Generate inclusion proof of leaf at `index`, against the root at
+    /// historical `size`.
+    pub fn prove<DB: ReadBackend<Construct=C>>(
+        &self,
+        db: &mut DB,
+        index: usize,
+        size: usize
+    ) -> Result<MmrProof<C>, Error<DB::Error>> {
+        if index >= size || size > self.len {
+            return Err(Error::AccessOverflowed)
+        }
+
+        let mut siblings = Vec::new();
+        let mut peaks = Vec::new();
+        for (peak_start, peak_height) in peaks_of(size) {
+            if index >= peak_start && index < peak_start + (1 << peak_height) {
+                let offset = index - peak_start;
+                for level in 0..peak_height {
+                    let sibling_start = peak_start + (((offset >> level) ^ 1) << level);
+                    siblings.push(self.node(db, sibling_start, level)?);
+                }
+            } else {
+                peaks.push(self.node(db, peak_start, peak_height)?);
+            }
+        }
+
+        Ok(MmrProof { size, index, siblings, peaks })
+    }
+
+    /// Generate consistency proof between the root at historical
+    /// `old_size` and the current root.
+    pub fn prove_consistency<DB: ReadBackend<Construct=C>>(
+        &self,
+        db: &mut DB,
+        old_size: usize
+    ) -> Result<MmrConsistencyProof<C>, Error<DB::Error>> {
+        if old_size > self.len {
+            return Err(Error::AccessOverflowed)
+        }
+
+        let new_peaks_layout = peaks_of(self.len);
+        let mut old_peaks = Vec::new();
+        let mut paths = Vec::new();
+        for (start, height) in peaks_of(old_size) {
+            old_peaks.push(self.node(db, start, height)?);
+
+            let (peak_start, peak_height) = new_peaks_layout.iter().cloned()
+                .find(|(peak_start, peak_height)| {
+                    start >= *peak_start && start < peak_start + (1 << peak_height)
+                })
+                .ok_or(Error::CorruptedDatabase)?;
+            let offset = start - peak_start;
+            let mut path = Vec::new();
+            for level in height..peak_height {
+                let sibling_start = peak_start + (((offset >> level) ^ 1) << level);
+                path.push(self.node(db, sibling_start, level)?);
+            }
+            paths.push(path);
+        }
+
+        let mut new_peaks = Vec::new();
+        for (start, height) in new_peaks_layout {
+            if start >= old_size {
+                new_peaks.push(self.node(db, start, height)?);
+            }
+        }
+
+        Ok(MmrConsistencyProof {
+            old_size,
+            new_size: self.len,
+            old_peaks,
+            paths,
+            new_peaks,
+        })
+    }
+}
+
+impl<R: RootStatus, C: Construct> Tree for Mmr<R, C> {
+    type RootStatus = R;
+    type Construct = C;
+
+    fn root(&self) -> ValueOf<C> {
+        self.raw.root()
+    }
+
+    fn drop<DB: WriteBackend<Construct=C>>(
+        self,
+        db: &mut DB
+    ) -> Result<(), Error<DB::Error>> {
+        self.raw.drop(db)
+    }
+
+    fn into_raw(self) -> Raw<R, C> {
+        self.raw
+    }
+}
+
+impl<R: RootStatus, C: Construct> Sequence for Mmr<R, C> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<R: RootStatus, C: Construct> Leak for Mmr<R, C> {
+    type Metadata = (ValueOf<C>, usize);
+
+    fn metadata(&self) -> Self::Metadata {
+        (self.raw.metadata(), self.len)
+    }
+
+    fn from_leaked((raw_root, len): Self::Metadata) -> Self {
+        Self {
+            raw: Raw::from_leaked(raw_root),
+            len,
+        }
+    }
+}
+
+impl<C: Construct> Mmr<Owned, C> where
+    C::End: From<usize>,
+{
+    /// Create a new empty mountain range.
+    pub fn create<DB: WriteBackend<Construct=C>>(
+        db: &mut DB
+    ) -> Result<Self, Error<DB::Error>> {
+        let (key, value) = mix_size::<C>(Value::End(Default::default()), 0);
+        db.insert(key.clone(), value)?;
+        let mut raw = Raw::default();
+        raw.set(db, Index::root(), Value::Intermediate(key))?;
+
+        Ok(Self { raw, len: 0 })
+    }
+}
+
+/// Inclusion proof of a leaf in a merkle mountain range.
+pub struct MmrProof<C: Construct> {
+    /// Size of the mountain range the proof is against.
+    pub size: usize,
+    /// Index of the leaf.
+    pub index: usize,
+    /// Siblings from the leaf up to its peak.
+    pub siblings: Vec<ValueOf<C>>,
+    /// All other peaks, from the leftmost peak.
+    pub peaks: Vec<ValueOf<C>>,
+}
+
+impl<C: Construct> MmrProof<C> where
+    C::Intermediate: PartialEq,
+    C::End: PartialEq + From<usize>,
+{
+    /// Verify the proof of a leaf against the root.
+    pub fn verify(&self, root: &ValueOf<C>, leaf: ValueOf<C>) -> bool {
+        if self.index >= self.size {
+            return false
+        }
+
+        let layout = peaks_of(self.size);
+        if self.peaks.len() + 1 != layout.len() {
+            return false
+        }
+
+        let mut peaks = self.peaks.clone();
+        for (at, (peak_start, peak_height)) in layout.into_iter().enumerate() {
+            if self.index >= peak_start && self.index < peak_start + (1 << peak_height) {
+                if self.siblings.len() != peak_height {
+                    return false
+                }
+                let peak = climb::<C>(leaf, self.index - peak_start, &self.siblings);
+                peaks.insert(at, peak);
+                break
+            }
+        }
+
+        &root_of::<C>(&peaks, self.size) == root
+    }
+}
+
+impl<C: Construct> Clone for MmrProof<C> {
+    fn clone(&self) -> Self {
+        Self {
+            size: self.size,
+            index: self.index,
+            siblings: self.siblings.clone(),
+            peaks: self.peaks.clone(),
+        }
+    }
+}
+
+impl<C: Construct> fmt::Debug for MmrProof<C> where
+    C::Intermediate: fmt::Debug,
+    C::End: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MmrProof")
+            .field("size", &self.size)
+            .field("index", &self.index)
+            .field("siblings", &self.siblings)
+            .field("peaks", &self.peaks)
+            .finish()
+    }
+}
+
+/// Consistency proof between two sizes of a merkle mountain range.
+pub struct MmrConsistencyProof<C: Construct> {
+    /// Old size.
+    pub old_size: usize,
+    /// New size.
+    pub new_size: usize,
+    /// Peaks at the old size, from the leftmost peak.
+    pub old_peaks: Vec<ValueOf<C>>,
+    /// For each old peak, siblings up to the new peak containing it.
+    pub paths: Vec<Vec<ValueOf<C>>>,
+    /// New peaks that do not contain any old leaf, from the leftmost peak.
+    pub new_peaks: Vec<ValueOf<C>>,
+}
+
+impl<C: Construct> MmrConsistencyProof<C> where
+    C::Intermediate: PartialEq,
+    C::End: PartialEq + From<usize>,
+{
+    /// Verify the proof against the old root and the new root.
+    pub fn verify(&self, old_root: &ValueOf<C>, new_root: &ValueOf<C>) -> bool {
+        if self.old_size > self.new_size {
+            return false
+        }
+
+        let old_layout = peaks_of(self.old_size);
+        let new_layout = peaks_of(self.new_size);
+        if self.old_peaks.len() != old_layout.len() || self.paths.len() != old_layout.len() {
+            return false
+        }
+        if &root_of::<C>(&self.old_peaks, self.old_size) != old_root {
+            return false
+        }
+
+        let mut peaks: Vec<Option<ValueOf<C>>> = new_layout.iter().map(|_| None).collect();
+        for ((start, height), (old_peak, path)) in old_layout.into_iter()
+            .zip(self.old_peaks.iter().zip(self.paths.iter()))
+        {
+            let at = match new_layout.iter().position(|(peak_start, peak_height)| {
+                start >= *peak_start && start < peak_start + (1 << peak_height)
+            }) {
+                Some(at) => at,
+                None => return false,
+            };
+            let (peak_start, peak_height) = new_layout[at];
+            if height + path.len() != peak_height {
+                return false
+            }
+
+            let peak = climb::<C>(old_peak.clone(), (start - peak_start) >> height, path);
+            match &peaks[at] {
+                Some(existing) if existing != &peak => return false,
+                _ => peaks[at] = Some(peak),
+            }
+        }
+
+        let mut new_peaks = self.new_peaks.iter();
+        for (peak, (start, _)) in peaks.iter_mut().zip(new_layout.iter()) {
+            if *start >= self.old_size {
+                match new_peaks.next() {
+                    Some(new_peak) => *peak = Some(new_peak.clone()),
+                    None => return false,
+                }
+            }
+        }
+        if new_peaks.next().is_some() {
+            return false
+        }
+
+        let peaks = match peaks.into_iter().collect::<Option<Vec<_>>>() {
+            Some(peaks) => peaks,
+            None => return false,
+        };
+        &root_of::<C>(&peaks, self.new_size) == new_root
+    }
+}
+
+impl<C: Construct> Clone for MmrConsistencyProof<C> {
+    fn clone(&self) -> Self {
+        Self {
+            old_size: self.old_size,
+            new_size: self.new_size,
+            old_peaks: self.old_peaks.clone(),
+            paths: self.paths.clone(),
+            new_peaks: self.new_peaks.clone(),
+        }
+    }
+}
+
+impl<C: Construct> fmt::Debug for MmrConsistencyProof<C> where
+    C::Intermediate: fmt::Debug,
+    C::End: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MmrConsistencyProof")
+            .field("old_size", &self.old_size)
+            .field("new_size", &self.new_size)
+            .field("old_peaks", &self.old_peaks)
+            .field("paths", &self.paths)
+            .field("new_peaks", &self.new_peaks)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Sha256;
+
+    type Construct = crate::InheritedDigestConstruct<Sha256, MmrValue>;
+    type InMemory = crate::memory::InMemoryBackend<Construct>;
+
+    #[derive(Clone, PartialEq, Eq, Debug, Default)]
+    struct MmrValue(Vec<u8>);
+
+    impl AsRef<[u8]> for MmrValue {
+        fn as_ref(&self) -> &[u8] {
+            self.0.as_ref()
+        }
+    }
+
+    impl From<usize> for MmrValue {
+        fn from(value: usize) -> Self {
+            MmrValue((&(value as u64).to_le_bytes()[..]).into())
+        }
+    }
+
+    fn leaf(i: usize) -> ValueOf<Construct> {
+        Value::End(MmrValue((i as u64).to_le_bytes().to_vec()))
+    }
+
+    fn intermediate(left: &ValueOf<Construct>, right: &ValueOf<Construct>) -> ValueOf<Construct> {
+        Value::Intermediate(<Construct as crate::Construct>::intermediate_of(left, right))
+    }
+
+    fn naive_root(size: usize) -> ValueOf<Construct> {
+        let peaks = peaks_of(size).into_iter().map(|(start, height)| {
+            let mut level = (start..(start + (1 << height))).map(leaf).collect::<Vec<_>>();
+            while level.len() > 1 {
+                level = level.chunks(2).map(|pair| intermediate(&pair[0], &pair[1])).collect();
+            }
+            level.remove(0)
+        }).collect::<Vec<_>>();
+        intermediate(&bag::<Construct>(&peaks), &Value::End(size.into()))
+    }
+
+    fn build(db: &mut InMemory, size: usize) -> (OwnedMmr<Construct>, Vec<ValueOf<Construct>>) {
+        let mut mmr = OwnedMmr::create(db).unwrap();
+        let mut roots = vec![mmr.root()];
+        for i in 0..size {
+            mmr.push(db, leaf(i)).unwrap();
+            roots.push(mmr.root());
+        }
+        (mmr, roots)
+    }
+
+    #[test]
+    fn test_push_and_roots() {
+        let mut db = InMemory::default();
+        let (mmr, roots) = build(&mut db, 37);
+
+        for (size, root) in roots.iter().enumerate() {
+            assert_eq!(root, &naive_root(size));
+            assert_eq!(&mmr.root_at(&mut db, size).unwrap(), root);
+        }
+        for i in 0..37 {
+            assert_eq!(mmr.get(&mut db, i).unwrap(), leaf(i));
+        }
+        assert_eq!(mmr.get(&mut db, 37), Err(Error::AccessOverflowed));
+
+        mmr.drop(&mut db).unwrap();
+        assert_eq!(db.as_ref().len(), 0);
+    }
+
+    #[test]
+    fn test_inclusion_proofs() {
+        let mut db = InMemory::default();
+        let (mmr, roots) = build(&mut db, 20);
+
+        for size in 1..=20 {
+            for index in 0..size {
+                let proof = mmr.prove(&mut db, index, size).unwrap();
+                assert!(proof.verify(&roots[size], leaf(index)));
+                assert!(!proof.verify(&roots[size], leaf(index + 1)));
+                if size < 20 {
+                    assert!(!proof.verify(&roots[size + 1], leaf(index)));
+                }
+            }
+        }
+        assert_eq!(mmr.prove(&mut db, 3, 21).unwrap_err(), Error::AccessOverflowed);
+        assert_eq!(mmr.prove(&mut db, 3, 3).unwrap_err(), Error::AccessOverflowed);
+    }
+
+    #[test]
+    fn test_consistency_proofs() {
+        let mut db = InMemory::default();
+        let (mmr, roots) = build(&mut db, 20);
+
+        for old_size in 0..=20 {
+            let proof = mmr.prove_consistency(&mut db, old_size).unwrap();
+            assert!(proof.verify(&roots[old_size], &roots[20]));
+            assert!(!proof.verify(&roots[old_size], &roots[19]));
+            if old_size > 0 {
+                assert!(!proof.verify(&roots[old_size - 1], &roots[20]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_forged_size() {
+        let mut db = InMemory::default();
+        let (mmr, roots) = build(&mut db, 4);
+        let l01 = intermediate(&leaf(0), &leaf(1));
+        let l23 = intermediate(&leaf(2), &leaf(3));
+
+        // A range of size two with leaves `H(l0, l1)` and `H(l2, l3)` bags
+        // into the same peak as the range of size four.
+        let forged = MmrProof::<Construct> {
+            size: 2,
+            index: 1,
+            siblings: vec![l01.clone()],
+            peaks: Vec::new(),
+        };
+        assert!(!forged.verify(&roots[4], l23.clone()));
+        let mut proof = mmr.prove(&mut db, 1, 4).unwrap();
+        assert!(proof.verify(&roots[4], leaf(1)));
+        proof.size = 5;
+        assert!(!proof.verify(&roots[4], leaf(1)));
+
+        let forged = MmrConsistencyProof::<Construct> {
+            old_size: 1,
+            new_size: 1,
+            old_peaks: vec![intermediate(&l01, &l23)],
+            paths: vec![Vec::new()],
+            new_peaks: Vec::new(),
+        };
+        assert!(!forged.verify(&roots[4], &roots[4]));
+        let mut proof = mmr.prove_consistency(&mut db, 2).unwrap();
+        assert!(proof.verify(&roots[2], &roots[4]));
+        proof.old_size = 3;
+        assert!(!proof.verify(&roots[2], &roots[4]));
+    }
+
+    #[test]
+    fn test_empty_root() {
+        let mut db = InMemory::default();
+        let mmr = OwnedMmr::<Construct>::create(&mut db).unwrap();
+        assert_eq!(mmr.root(), intermediate(&Value::End(Default::default()), &Value::End(0.into())));
+        assert_eq!(mmr.root_at(&mut db, 0).unwrap(), mmr.root());
+        mmr.drop(&mut db).unwrap();
+        assert_eq!(db.as_ref().len(), 0);
+    }
+}
@@ -103,23 +103,27 @@ impl<R: RootStatus, C: Construct, T, H: ArrayLength<u8>, V: ArrayLength<u8>> Pac
 
         let index = self.len - 1;
         let ret = self.get(db, index)?;
+        let (covering_base, covering_ranges) = coverings::<H, V>(index);
+        let remaining_host_len = host_len::<H, V>(index);
 
-        if self.len == 1 {
-            while self.tuple.len() > 0 {
-                self.tuple.pop(db)?;
+        // Only the leading covering can share its host with a previous
+        // value. Clear its bytes, and let the trailing hosts be popped.
+        for (i, range) in covering_ranges.into_iter().enumerate() {
+            let host_index = covering_base + i;
+            if host_index >= remaining_host_len {
+                break
             }
-        } else {
-            let last_index = index - 1;
 
-            let (covering_base, covering_ranges) = coverings::<H, V>(last_index);
-            while self.tuple.len() > covering_base + covering_ranges.len() {
-                self.tuple.pop(db)?;
+            let mut host_value: GenericArray<u8, H> = self.tuple.get(db, host_index)?
+                .end().ok_or(Error::CorruptedDatabase)?.into();
+            for byte in &mut host_value[range] {
+                *byte = 0;
             }
+            self.tuple.set(db, host_index, Value::End(host_value.into()))?;
+        }
 
-            let last_value = self.get(db, last_index)?;
+        while self.tuple.len() > remaining_host_len {
             self.tuple.pop(db)?;
-            self.tuple.push(db, Value::End(Default::default()))?;
-            self.set(db, last_index, last_value)?;
         }
 
         self.len -= 1;
@@ -307,7 +311,8 @@ mod tests {
     use super::*;
     use sha2::Sha256;
     use crate::traits::Owned;
-    use typenum::{U8, U32};
+    use typenum::{Unsigned, U1, U8, U12, U16, U32};
+    use quickcheck::{QuickCheck, StdThreadGen};
 
     type InMemory = crate::memory::InMemoryBackend<crate::InheritedDigestConstruct<Sha256, ListValue>>;
 
@@ -412,4 +417,145 @@ mod tests {
                                                   0, 0, 0, 0, 0, 0, 0, 0]);
         }
     }
+
+    fn reference_pop<DB: WriteBackend<Construct=C>, C: Construct, T, H: ArrayLength<u8>, V: ArrayLength<u8>>(
+        vector: &mut PackedVector<Owned, C, T, H, V>,
+        db: &mut DB
+    ) -> Result<Option<T>, Error<DB::Error>> where
+        C::End: From<GenericArray<u8, H>> + Into<GenericArray<u8, H>>,
+        T: From<GenericArray<u8, V>> + Into<GenericArray<u8, V>>,
+    {
+        if vector.len == 0 {
+            return Ok(None)
+        }
+
+        let index = vector.len - 1;
+        let ret = vector.get(db, index)?;
+
+        if vector.len == 1 {
+            while vector.tuple.len() > 0 {
+                vector.tuple.pop(db)?;
+            }
+        } else {
+            let last_index = index - 1;
+
+            let (covering_base, covering_ranges) = coverings::<H, V>(last_index);
+            while vector.tuple.len() > covering_base + covering_ranges.len() {
+                vector.tuple.pop(db)?;
+            }
+
+            let last_value = vector.get(db, last_index)?;
+            vector.tuple.pop(db)?;
+            vector.tuple.push(db, Value::End(Default::default()))?;
+            vector.set(db, last_index, last_value)?;
+        }
+
+        vector.len -= 1;
+        Ok(Some(ret))
+    }
+
+    fn check_pop<V: ArrayLength<u8>>(ops: Vec<(u8, u8)>) -> bool {
+        // The previous pop only kept the last value of a shared host, so
+        // it is a valid reference only when values are host-aligned. In
+        // all cases, popping must restore the root before the push.
+        let aligned = V::to_usize() % U8::to_usize() == 0;
+
+        let mut db = InMemory::default();
+        let mut reference_db = InMemory::default();
+        let mut vector = PackedVector::<Owned, _, GenericArray<u8, V>, U8, V>::create(&mut db, 0, None).unwrap();
+        let mut reference = PackedVector::<Owned, _, GenericArray<u8, V>, U8, V>::create(&mut reference_db, 0, None).unwrap();
+        let mut values = Vec::new();
+        let mut roots = Vec::new();
+
+        for (op, byte) in ops {
+            if op % 3 == 0 {
+                let popped = vector.pop(&mut db).unwrap();
+                if popped != values.pop() {
+                    return false
+                }
+                if let Some(root) = roots.pop() {
+                    if vector.root() != root {
+                        return false
+                    }
+                }
+                if aligned && popped != reference_pop(&mut reference, &mut reference_db).unwrap() {
+                    return false
+                }
+            } else {
+                let mut value = GenericArray::<u8, V>::default();
+                for (i, b) in value.iter_mut().enumerate() {
+                    *b = byte.wrapping_add(i as u8) | 1;
+                }
+                roots.push(vector.root());
+                vector.push(&mut db, value.clone()).unwrap();
+                if aligned {
+                    reference.push(&mut reference_db, value.clone()).unwrap();
+                }
+                values.push(value);
+            }
+
+            if vector.len() != values.len() {
+                return false
+            }
+            if aligned && vector.root() != reference.root() {
+                return false
+            }
+        }
+
+        for (i, value) in values.iter().enumerate() {
+            if &vector.get(&mut db, i).unwrap() != value {
+                return false
+            }
+        }
+
+        true
+    }
+
+    #[test]
+    fn test_pop_shared_host() {
+        let mut db = InMemory::default();
+        let mut vector = PackedVector::<Owned, _, GenericArray<u8, U1>, U8, U1>::create(&mut db, 0, None).unwrap();
+        let mut roots = Vec::new();
+
+        for i in 0..10u8 {
+            roots.push(vector.root());
+            vector.push(&mut db, GenericArray::from([i + 1])).unwrap();
+        }
+        for i in (0..10u8).rev() {
+            assert_eq!(vector.pop(&mut db).unwrap(), Some(GenericArray::from([i + 1])));
+            assert_eq!(vector.root(), roots.pop().unwrap());
+            for j in 0..i {
+                assert_eq!(vector.get(&mut db, j as usize).unwrap(), GenericArray::from([j + 1]));
+            }
+        }
+    }
+
+    fn quickcheck_pop(f: fn(Vec<(u8, u8)>) -> bool) {
+        QuickCheck::new().tests(50).gen(StdThreadGen::new(40)).quickcheck(f);
+    }
+
+    #[test]
+    fn prop_pop_u1() {
+        quickcheck_pop(check_pop::<U1>);
+    }
+
+    #[test]
+    fn prop_pop_u8() {
+        quickcheck_pop(check_pop::<U8>);
+    }
+
+    #[test]
+    fn prop_pop_u12() {
+        quickcheck_pop(check_pop::<U12>);
+    }
+
+    #[test]
+    fn prop_pop_u32() {
+        quickcheck_pop(check_pop::<U32>);
+    }
+
+    #[test]
+    fn prop_pop_u16() {
+        quickcheck_pop(check_pop::<U16>);
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{Backend, ReadBackend, WriteBackend, Construct, Value, ValueOf};
+use crate::{Backend, ReadBackend, WriteBackend, Construct, Value, ValueOf, Error};
 use core::hash::Hash;
 use core::ops::Deref;
 use core::fmt;
@@ -51,6 +51,29 @@ impl<'a, DB: ReadBackend> ReadBackend for ProvingBackend<'a, DB> where
         }
         Ok(value)
     }
+
+    fn contains(
+        &mut self,
+        key: &<DB::Construct as Construct>::Intermediate
+    ) -> Result<bool, Self::Error> {
+        self.db.contains(key)
+    }
+
+    fn strict_decoding(&self) -> bool {
+        self.db.strict_decoding()
+    }
+
+    fn check_decode_len(&mut self, len: usize) -> Result<(), Error<Self::Error>> {
+        self.db.check_decode_len(len)
+    }
+
+    fn enter_decode(&mut self) -> Result<(), Error<Self::Error>> {
+        self.db.enter_decode()
+    }
+
+    fn leave_decode(&mut self) {
+        self.db.leave_decode()
+    }
 }
 
 impl<'a, DB: WriteBackend> WriteBackend for ProvingBackend<'a, DB> where
@@ -72,6 +95,10 @@ impl<'a, DB: WriteBackend> WriteBackend for ProvingBackend<'a, DB> where
         self.inserts.insert(key.clone());
         self.db.insert(key, value)
     }
+
+    fn virtual_empty_depth(&self) -> usize {
+        self.db.virtual_empty_depth()
+    }
 }
 
 /// Type of proofs.
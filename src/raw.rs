@@ -1,5 +1,6 @@
 use core::marker::PhantomData;
 use alloc::vec::Vec;
+use alloc::collections::BTreeMap;
 
 use crate::index::{Index, IndexSelection, IndexRoute};
 use crate::traits::{Construct, ReadBackend, WriteBackend,
@@ -206,6 +207,108 @@ impl<R: RootStatus, C: Construct> Raw<R, C> {
         self.root = update;
         Ok(())
     }
+
+    /// Get children of the node at index in the current tree. Nodes not
+    /// reachable from the root are considered to have empty children.
+    fn children<DB: ReadBackend<Construct=C>>(
+        &self,
+        db: &mut DB,
+        index: Index
+    ) -> Result<(ValueOf<C>, ValueOf<C>), Error<DB::Error>> {
+        match self.get(db, index)? {
+            Some(Value::Intermediate(key)) => Ok(db.get(&key)?),
+            Some(Value::End(_)) | None => Ok((Value::End(Default::default()), Value::End(Default::default()))),
+        }
+    }
+
+    /// Set many values of the merkle tree via generalized merkle indexes.
+    /// Parents are hashed level by level, in batches. No index can be the
+    /// descendant of another.
+    pub fn set_many<DB: WriteBackend<Construct=C>>(
+        &mut self,
+        db: &mut DB,
+        sets: Vec<(Index, ValueOf<C>)>
+    ) -> Result<(), Error<DB::Error>> {
+        let mut pending = BTreeMap::new();
+        for (index, set) in sets {
+            if let Value::Intermediate(key) = &set {
+                let value = db.get(key)?;
+                db.insert(key.clone(), value)?;
+            }
+            if pending.insert(index, set).is_some() {
+                return Err(Error::InvalidParameter)
+            }
+        }
+
+        for index in pending.keys() {
+            let mut current = *index;
+            while let Some(parent) = current.parent() {
+                if pending.contains_key(&parent) {
+                    return Err(Error::InvalidParameter)
+                }
+                current = parent;
+            }
+        }
+
+        loop {
+            let depth = match pending.keys().next_back() {
+                Some(index) if index.depth() > 0 => index.depth(),
+                _ => break,
+            };
+
+            let mut parents = Vec::new();
+            while let Some(index) = pending.keys().next_back().cloned() {
+                if index.depth() != depth {
+                    break
+                }
+
+                let value = pending.remove(&index).ok_or(Error::CorruptedDatabase)?;
+                let parent = index.parent().ok_or(Error::CorruptedDatabase)?;
+                let is_left = parent.left() == index;
+
+                match parents.last_mut() {
+                    Some((last, left, _)) if *last == parent && is_left => { *left = Some(value); },
+                    _ if is_left => parents.push((parent, Some(value), None)),
+                    _ => parents.push((parent, None, Some(value))),
+                }
+            }
+
+            let mut pairs = Vec::with_capacity(parents.len());
+            for (parent, left, right) in &parents {
+                let pair = match (left, right) {
+                    (Some(left), Some(right)) => (left.clone(), right.clone()),
+                    (left, right) => {
+                        let (current_left, current_right) = self.children(db, *parent)?;
+                        (left.clone().unwrap_or(current_left), right.clone().unwrap_or(current_right))
+                    },
+                };
+                pairs.push(pair);
+            }
+
+            let keys = C::intermediate_of_batch(&pairs);
+            for (((parent, _, _), key), pair) in parents.into_iter().zip(keys).zip(pairs) {
+                db.insert(key.clone(), pair)?;
+                pending.insert(parent, Value::Intermediate(key));
+            }
+        }
+
+        let update = match pending.remove(&Index::root()) {
+            Some(update) => update,
+            None => return Ok(()),
+        };
+
+        if R::is_owned() {
+            if let Value::Intermediate(ref key) = update {
+                db.rootify(key)?;
+            }
+            if let Value::Intermediate(ref key) = self.root {
+                db.unrootify(key)?;
+            }
+        }
+
+        self.root = update;
+        Ok(())
+    }
 }
 
 impl<R: RootStatus, C: Construct> Leak for Raw<R, C> {
@@ -227,6 +330,7 @@ impl<R: RootStatus, C: Construct> Leak for Raw<R, C> {
 mod tests {
     use super::*;
     use crate::traits::Owned;
+    use core::sync::atomic::{AtomicUsize, Ordering};
     use sha2::Sha256;
 
     type Construct = crate::InheritedDigestConstruct<Sha256, Vec<u8>>;
@@ -331,9 +435,44 @@ mod tests {
         assert!(db1.as_ref().is_empty());
     }
 
+    #[test]
+    fn test_set_many() {
+        let mut db1 = InMemory::default();
+        let mut db2 = InMemory::default();
+        let mut list1 = Raw::<Owned, Construct>::default();
+        let mut list2 = Raw::<Owned, Construct>::default();
+
+        let sets = (40..64).chain(8..10)
+            .map(|i| (Index::from_one(i).unwrap(), Value::End(vec![i as u8])))
+            .collect::<Vec<_>>();
+        for (index, value) in sets.clone() {
+            list1.set(&mut db1, index, value).unwrap();
+        }
+        list2.set_many(&mut db2, sets).unwrap();
+        assert_eq!(list1.root(), list2.root());
+        assert_eq!(db1.as_ref(), db2.as_ref());
+
+        let sets = vec![
+            (Index::from_one(41).unwrap(), Value::End(vec![0])),
+            (Index::from_one(63).unwrap(), Value::End(vec![1])),
+            (Index::from_one(18).unwrap(), Value::End(vec![2])),
+        ];
+        for (index, value) in sets.clone() {
+            list1.set(&mut db1, index, value).unwrap();
+        }
+        list2.set_many(&mut db2, sets).unwrap();
+        assert_eq!(list1.root(), list2.root());
+        assert_eq!(db1.as_ref(), db2.as_ref());
+
+        assert_eq!(list2.set_many(&mut db2, vec![
+            (Index::from_one(5).unwrap(), Value::End(vec![0])),
+            (Index::from_one(45).unwrap(), Value::End(vec![1])),
+        ]), Err(Error::InvalidParameter));
+    }
+
     #[test]
     fn test_intermediate() {
-        let mut db = InMemory::default();
+        let mut db = InMemory::with_max_empty_depth(0);
         let mut list = Raw::<Owned, Construct>::default();
         list.set(&mut db, Index::from_one(2).unwrap(), Value::End(vec![])).unwrap();
         assert_eq!(list.get(&mut db, Index::from_one(3).unwrap()).unwrap().unwrap(), Value::End(vec![]));
@@ -356,4 +495,55 @@ mod tests {
         assert_eq!(list1.get(&mut db1, Index::from_one(1).unwrap()).unwrap().unwrap(), Value::End(vec![0]));
         assert!(db1.as_ref().is_empty());
     }
+
+    static BATCHES: AtomicUsize = AtomicUsize::new(0);
+
+    /// Construct counting calls of its batch method.
+    struct CountingConstruct;
+
+    impl crate::Construct for CountingConstruct {
+        type Intermediate = <Construct as crate::Construct>::Intermediate;
+        type End = Vec<u8>;
+
+        fn intermediate_of(left: &ValueOf<Self>, right: &ValueOf<Self>) -> Self::Intermediate {
+            Construct::intermediate_of(left, right)
+        }
+
+        fn intermediate_of_batch(pairs: &[(ValueOf<Self>, ValueOf<Self>)]) -> Vec<Self::Intermediate> {
+            BATCHES.fetch_add(1, Ordering::SeqCst);
+            Construct::intermediate_of_batch(pairs)
+        }
+
+        fn empty_at<DB: WriteBackend<Construct=Self>>(
+            db: &mut DB,
+            depth_to_bottom: usize
+        ) -> Result<ValueOf<Self>, DB::Error> {
+            crate::inherited_empty_at(db, depth_to_bottom)
+        }
+    }
+
+    #[test]
+    fn test_batch_routing() {
+        let values = (0..5u8).map(|i| Value::End(vec![i])).collect::<Vec<_>>();
+
+        let mut db = crate::memory::InMemoryBackend::<CountingConstruct>::default();
+        BATCHES.store(0, Ordering::SeqCst);
+        let root = crate::utils::vector_tree(&values, &mut db, Some(8)).unwrap();
+        assert_eq!(BATCHES.load(Ordering::SeqCst), 3);
+        assert_eq!(root, crate::utils::vector_tree(&values, &mut InMemory::default(), Some(8)).unwrap());
+
+        let mut list = Raw::<Owned, CountingConstruct>::default();
+        let mut expected = Raw::<Owned, Construct>::default();
+        let mut expected_db = InMemory::default();
+        let sets = vec![
+            (Index::from_one(4).unwrap(), Value::End(vec![1])),
+            (Index::from_one(6).unwrap(), Value::End(vec![2])),
+            (Index::from_one(7).unwrap(), Value::End(vec![3])),
+        ];
+        BATCHES.store(0, Ordering::SeqCst);
+        list.set_many(&mut db, sets.clone()).unwrap();
+        assert_eq!(BATCHES.load(Ordering::SeqCst), 2);
+        expected.set_many(&mut expected_db, sets).unwrap();
+        assert_eq!(list.root(), expected.root());
+    }
 }
@@ -0,0 +1,559 @@
+use core::marker::PhantomData;
+use core::hash::Hash;
+use core::fmt;
+use alloc::vec::Vec;
+
+use crate::traits::{Construct, ReadBackend, WriteBackend, Value, ValueOf,
+                    RootStatus, Owned, Dangling, Leak, Error, Tree};
+use crate::raw::Raw;
+use crate::memory::InMemoryBackend;
+
+/// Depth of a sparse merkle tree.
+pub const SPARSE_DEPTH: usize = 256;
+
+/// Key of a sparse merkle tree, usually a hash.
+pub type SparseKey = [u8; 32];
+
+/// `SparseMerkleTree` with owned root.
+pub type OwnedSparseMerkleTree<C> = SparseMerkleTree<Owned, C>;
+
+/// `SparseMerkleTree` with dangling root.
+pub type DanglingSparseMerkleTree<C> = SparseMerkleTree<Dangling, C>;
+
+/// Pair of children of an intermediate node.
+type Pair<C> = (ValueOf<C>, ValueOf<C>);
+
+/// Intermediate node to insert, with its children.
+type Insert<C> = Option<(<C as Construct>::Intermediate, Pair<C>)>;
+
+/// Siblings from the root down to the leaf, and the leaf value.
+type PathOf<C> = (Vec<ValueOf<C>>, ValueOf<C>);
+
+fn selection_of(key: &SparseKey, depth: usize) -> bool {
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+/// Empty roots of subtrees known without a backend, indexed by depth to
+/// bottom.
+fn known_zeros<C: Construct>() -> Option<Vec<ValueOf<C>>> {
+    (0..(SPARSE_DEPTH + 1)).map(C::empty_value).collect()
+}
+
+/// Empty roots of subtrees, indexed by depth to bottom.
+fn zeros_of<DB: WriteBackend>(
+    db: &mut DB
+) -> Result<Vec<ValueOf<DB::Construct>>, Error<DB::Error>> {
+    if let Some(zeros) = known_zeros::<DB::Construct>() {
+        return Ok(zeros)
+    }
+
+    let mut zeros = Vec::with_capacity(SPARSE_DEPTH + 1);
+    let mut current = <DB::Construct as Construct>::empty_at(db, SPARSE_DEPTH)?;
+    zeros.push(current.clone());
+    for _ in 0..SPARSE_DEPTH {
+        current = match current {
+            Value::Intermediate(key) => db.get(&key)?.0,
+            Value::End(end) => Value::End(end),
+        };
+        zeros.push(current.clone());
+    }
+    zeros.reverse();
+    Ok(zeros)
+}
+
+/// Empty roots of subtrees, without a backend.
+fn default_zeros<C: Construct>() -> Vec<ValueOf<C>> where
+    C::Intermediate: Eq + Hash + Ord,
+{
+    known_zeros::<C>().unwrap_or_else(|| {
+        zeros_of(&mut InMemoryBackend::<C>::default())
+            .expect("In-memory backend always contains inserted empty values; qed")
+    })
+}
+
+/// Get the parent of two children, collapsing to the empty value if both
+/// children are empty.
+fn parent_of<C: Construct>(
+    zeros: &[ValueOf<C>],
+    depth_to_bottom: usize,
+    left: ValueOf<C>,
+    right: ValueOf<C>
+) -> (ValueOf<C>, Insert<C>) where
+    C::Intermediate: PartialEq,
+    C::End: PartialEq,
+{
+    let zero = &zeros[depth_to_bottom - 1];
+    if &left == zero && &right == zero {
+        (zeros[depth_to_bottom].clone(), None)
+    } else {
+        let key = C::intermediate_of(&left, &right);
+        (Value::Intermediate(key.clone()), Some((key, (left, right))))
+    }
+}
+
+/// Sparse merkle tree with `2^256` leaves, addressed by 256-bit keys. Key
+/// bits are read from the most significant bit, with zero selecting the left
+/// child. Empty subtrees collapse into the construct's empty values, which
+/// are computed once per tree.
+pub struct SparseMerkleTree<R: RootStatus, C: Construct> {
+    root: ValueOf<C>,
+    zeros: Vec<ValueOf<C>>,
+    _marker: PhantomData<R>,
+}
+
+impl<R: RootStatus, C: Construct> SparseMerkleTree<R, C> where
+    C::Intermediate: PartialEq,
+    C::End: PartialEq,
+{
+    /// Walk the path of the key. Returns siblings from the root down to the
+    /// leaf, and the leaf value.
+    fn path<DB: ReadBackend<Construct=C>>(
+        &self,
+        db: &mut DB,
+        key: &SparseKey
+    ) -> Result<PathOf<C>, Error<DB::Error>> {
+        let mut siblings = Vec::with_capacity(SPARSE_DEPTH);
+        let mut current = self.root.clone();
+
+        for depth in 0..SPARSE_DEPTH {
+            let (left, right) = match current {
+                Value::Intermediate(intermediate) => db.get(&intermediate)?,
+                // An end value above leaf level can only be an empty subtree
+                // of a unit empty construct.
+                Value::End(end) => (Value::End(end.clone()), Value::End(end)),
+            };
+
+            if selection_of(key, depth) {
+                siblings.push(left);
+                current = right;
+            } else {
+                siblings.push(right);
+                current = left;
+            }
+        }
+
+        Ok((siblings, current))
+    }
+
+    /// Get value of the key. Returns `None` if the leaf is empty.
+    pub fn get<DB: ReadBackend<Construct=C>>(
+        &self,
+        db: &mut DB,
+        key: &SparseKey
+    ) -> Result<Option<C::End>, Error<DB::Error>> {
+        let mut current = self.root.clone();
+
+        for depth in 0..SPARSE_DEPTH {
+            let intermediate = match current {
+                Value::Intermediate(intermediate) => intermediate,
+                Value::End(_) => return Ok(None),
+            };
+
+            let pair = db.get(&intermediate)?;
+            current = if selection_of(key, depth) { pair.1 } else { pair.0 };
+        }
+
+        match current {
+            Value::End(end) => {
+                if end == Default::default() {
+                    Ok(None)
+                } else {
+                    Ok(Some(end))
+                }
+            },
+            Value::Intermediate(_) => Err(Error::CorruptedDatabase),
+        }
+    }
+
+    /// Set the leaf of the key, returning the old value.
+    fn set<DB: WriteBackend<Construct=C>>(
+        &mut self,
+        db: &mut DB,
+        key: &SparseKey,
+        value: C::End
+    ) -> Result<Option<C::End>, Error<DB::Error>> {
+        if self.zeros.is_empty() {
+            self.zeros = zeros_of(db)?;
+        }
+        let (mut siblings, old) = self.path(db, key)?;
+        let old = old.end().ok_or(Error::CorruptedDatabase)?;
+
+        let mut update = Value::End(value);
+        for depth in (0..SPARSE_DEPTH).rev() {
+            let sibling = siblings.pop().ok_or(Error::CorruptedDatabase)?;
+            let (left, right) = if selection_of(key, depth) {
+                (sibling, update)
+            } else {
+                (update, sibling)
+            };
+
+            let (parent, insert) = parent_of::<C>(&self.zeros, SPARSE_DEPTH - depth, left, right);
+            if let Some((intermediate, pair)) = insert {
+                db.insert(intermediate, pair)?;
+            }
+            update = parent;
+        }
+
+        if R::is_owned() {
+            if let Value::Intermediate(ref key) = update {
+                db.rootify(key)?;
+            }
+            if let Value::Intermediate(ref key) = self.root {
+                db.unrootify(key)?;
+            }
+        }
+        self.root = update;
+
+        if old == Default::default() {
+            Ok(None)
+        } else {
+            Ok(Some(old))
+        }
+    }
+
+    /// Insert a value at the key, returning the old value if it exists.
+    pub fn insert<DB: WriteBackend<Construct=C>>(
+        &mut self,
+        db: &mut DB,
+        key: &SparseKey,
+        value: C::End
+    ) -> Result<Option<C::End>, Error<DB::Error>> {
+        self.set(db, key, value)
+    }
+
+    /// Remove the value at the key, returning it if it exists.
+    pub fn remove<DB: WriteBackend<Construct=C>>(
+        &mut self,
+        db: &mut DB,
+        key: &SparseKey
+    ) -> Result<Option<C::End>, Error<DB::Error>> {
+        self.set(db, key, Default::default())
+    }
+
+    /// Generate proof of the key. The same proof shows inclusion of the
+    /// current value, or exclusion if the leaf is empty.
+    pub fn prove<DB: ReadBackend<Construct=C>>(
+        &self,
+        db: &mut DB,
+        key: &SparseKey
+    ) -> Result<SparseProof<C>, Error<DB::Error>> {
+        let (siblings, _) = self.path(db, key)?;
+        Ok(SparseProof { siblings })
+    }
+}
+
+impl<R: RootStatus, C: Construct> Tree for SparseMerkleTree<R, C> {
+    type RootStatus = R;
+    type Construct = C;
+
+    fn root(&self) -> ValueOf<C> {
+        self.root.clone()
+    }
+
+    fn drop<DB: WriteBackend<Construct=C>>(
+        self,
+        db: &mut DB
+    ) -> Result<(), Error<DB::Error>> {
+        if R::is_owned() {
+            if let Value::Intermediate(key) = C::empty_at(db, SPARSE_DEPTH)? {
+                db.unrootify(&key)?;
+            }
+        }
+        self.into_raw().drop(db)
+    }
+
+    fn into_raw(self) -> Raw<R, C> {
+        Raw::from_leaked(self.root)
+    }
+}
+
+impl<R: RootStatus, C: Construct> Leak for SparseMerkleTree<R, C> {
+    type Metadata = ValueOf<C>;
+
+    fn metadata(&self) -> Self::Metadata {
+        self.root.clone()
+    }
+
+    fn from_leaked(root: Self::Metadata) -> Self {
+        Self {
+            root,
+            zeros: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<C: Construct> SparseMerkleTree<Owned, C> {
+    /// Create a new empty sparse merkle tree. The empty root is
+    /// additionally rootified, so that empty subtrees are kept in the
+    /// backend for the lifetime of the tree.
+    pub fn create<DB: WriteBackend<Construct=C>>(
+        db: &mut DB
+    ) -> Result<Self, Error<DB::Error>> {
+        let root = C::empty_at(db, SPARSE_DEPTH)?;
+        if let Value::Intermediate(ref key) = root {
+            db.rootify(key)?;
+            db.rootify(key)?;
+        }
+        let zeros = zeros_of(db)?;
+
+        Ok(Self {
+            root,
+            zeros,
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// Proof of a key in a sparse merkle tree, consisting of all siblings from
+/// the root down to the leaf.
+pub struct SparseProof<C: Construct> {
+    /// Siblings, from the root down to the leaf.
+    pub siblings: Vec<ValueOf<C>>,
+}
+
+impl<C: Construct> Clone for SparseProof<C> {
+    fn clone(&self) -> Self {
+        Self { siblings: self.siblings.clone() }
+    }
+}
+
+impl<C: Construct> PartialEq for SparseProof<C> where
+    C::Intermediate: PartialEq,
+    C::End: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.siblings == other.siblings
+    }
+}
+
+impl<C: Construct> Eq for SparseProof<C> where
+    C::Intermediate: Eq,
+    C::End: Eq { }
+
+impl<C: Construct> fmt::Debug for SparseProof<C> where
+    C::Intermediate: fmt::Debug,
+    C::End: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SparseProof")
+            .field("siblings", &self.siblings)
+            .finish()
+    }
+}
+
+impl<C: Construct> SparseProof<C> where
+    C::Intermediate: Eq + Hash + Ord,
+    C::End: PartialEq,
+{
+    /// Calculate the root given key and value. `None` means the leaf is
+    /// empty. Returns `None` if the proof is malformed.
+    pub fn root(&self, key: &SparseKey, value: Option<C::End>) -> Option<ValueOf<C>> {
+        if self.siblings.len() != SPARSE_DEPTH {
+            return None
+        }
+
+        let zeros = default_zeros::<C>();
+        let mut update = Value::End(value.unwrap_or_default());
+        for depth in (0..SPARSE_DEPTH).rev() {
+            let sibling = self.siblings[depth].clone();
+            let (left, right) = if selection_of(key, depth) {
+                (sibling, update)
+            } else {
+                (update, sibling)
+            };
+
+            update = parent_of::<C>(&zeros, SPARSE_DEPTH - depth, left, right).0;
+        }
+
+        Some(update)
+    }
+
+    /// Verify the proof against a root. Pass `Some` to verify inclusion of
+    /// the value, or `None` to verify exclusion of the key.
+    pub fn verify(&self, root: &ValueOf<C>, key: &SparseKey, value: Option<C::End>) -> bool {
+        self.root(key, value).as_ref() == Some(root)
+    }
+
+    /// Compress the proof by skipping siblings that are empty subtrees.
+    pub fn compress(&self) -> CompressedSparseProof<C> {
+        let zeros = default_zeros::<C>();
+        let mut bitmap = [0u8; SPARSE_DEPTH / 8];
+        let mut siblings = Vec::new();
+
+        for (depth, sibling) in self.siblings.iter().enumerate() {
+            if sibling != &zeros[SPARSE_DEPTH - depth - 1] {
+                bitmap[depth / 8] |= 0x80 >> (depth % 8);
+                siblings.push(sibling.clone());
+            }
+        }
+
+        CompressedSparseProof { bitmap, siblings }
+    }
+}
+
+/// Compressed proof of a key in a sparse merkle tree.
+pub struct CompressedSparseProof<C: Construct> {
+    /// Bitmap of siblings from the root down to the leaf. A set bit means
+    /// the sibling is included, otherwise it is an empty subtree.
+    pub bitmap: [u8; SPARSE_DEPTH / 8],
+    /// Included siblings.
+    pub siblings: Vec<ValueOf<C>>,
+}
+
+impl<C: Construct> Clone for CompressedSparseProof<C> {
+    fn clone(&self) -> Self {
+        Self { bitmap: self.bitmap, siblings: self.siblings.clone() }
+    }
+}
+
+impl<C: Construct> PartialEq for CompressedSparseProof<C> where
+    C::Intermediate: PartialEq,
+    C::End: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.bitmap == other.bitmap && self.siblings == other.siblings
+    }
+}
+
+impl<C: Construct> Eq for CompressedSparseProof<C> where
+    C::Intermediate: Eq,
+    C::End: Eq { }
+
+impl<C: Construct> fmt::Debug for CompressedSparseProof<C> where
+    C::Intermediate: fmt::Debug,
+    C::End: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedSparseProof")
+            .field("bitmap", &self.bitmap)
+            .field("siblings", &self.siblings)
+            .finish()
+    }
+}
+
+impl<C: Construct> CompressedSparseProof<C> where
+    C::Intermediate: Eq + Hash + Ord,
+    C::End: PartialEq,
+{
+    /// Decompress the proof. Returns `None` if the number of included
+    /// siblings does not match the bitmap.
+    pub fn decompress(&self) -> Option<SparseProof<C>> {
+        let zeros = default_zeros::<C>();
+        let mut included = self.siblings.iter();
+        let mut siblings = Vec::with_capacity(SPARSE_DEPTH);
+
+        for depth in 0..SPARSE_DEPTH {
+            if selection_of(&self.bitmap, depth) {
+                siblings.push(included.next()?.clone());
+            } else {
+                siblings.push(zeros[SPARSE_DEPTH - depth - 1].clone());
+            }
+        }
+
+        if included.next().is_some() {
+            return None
+        }
+
+        Some(SparseProof { siblings })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Sha256, Digest};
+
+    type InheritedInMemory = InMemoryBackend<crate::InheritedDigestConstruct<Sha256, Vec<u8>>>;
+    type UnitInMemory = InMemoryBackend<crate::UnitDigestConstruct<Sha256, Vec<u8>>>;
+
+    fn key(value: u32) -> SparseKey {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&Sha256::digest(&value.to_le_bytes())[..]);
+        key
+    }
+
+    fn value(value: u32) -> Vec<u8> {
+        value.to_le_bytes().to_vec()
+    }
+
+    fn check_insert_get_remove<C: Construct<End=Vec<u8>>>(db: &mut InMemoryBackend<C>) where
+        C::Intermediate: Eq + Hash + Ord + core::fmt::Debug,
+    {
+        let mut tree = OwnedSparseMerkleTree::create(db).unwrap();
+        let empty = tree.root();
+
+        for i in 0..20 {
+            assert_eq!(tree.insert(db, &key(i), value(i)).unwrap(), None);
+        }
+        for i in 0..20 {
+            assert_eq!(tree.get(db, &key(i)).unwrap(), Some(value(i)));
+        }
+        assert_eq!(tree.get(db, &key(20)).unwrap(), None);
+
+        assert_eq!(tree.insert(db, &key(3), value(33)).unwrap(), Some(value(3)));
+        assert_eq!(tree.get(db, &key(3)).unwrap(), Some(value(33)));
+
+        for i in 0..20 {
+            assert!(tree.remove(db, &key(i)).unwrap().is_some());
+        }
+        assert_eq!(tree.remove(db, &key(0)).unwrap(), None);
+        assert_eq!(tree.root(), empty);
+        assert_eq!(tree.get(db, &key(5)).unwrap(), None);
+
+        tree.drop(db).unwrap();
+        assert_eq!(db.as_ref().len(), 0);
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        check_insert_get_remove(&mut InheritedInMemory::default());
+        check_insert_get_remove(&mut UnitInMemory::default());
+    }
+
+    #[test]
+    fn test_order_independent_root() {
+        let mut db = InheritedInMemory::default();
+        let mut tree1 = OwnedSparseMerkleTree::create(&mut db).unwrap();
+        let mut tree2 = OwnedSparseMerkleTree::create(&mut db).unwrap();
+
+        for i in 0..10 {
+            tree1.insert(&mut db, &key(i), value(i)).unwrap();
+            tree2.insert(&mut db, &key(9 - i), value(9 - i)).unwrap();
+        }
+        assert_eq!(tree1.root(), tree2.root());
+    }
+
+    fn check_proofs<C: Construct<End=Vec<u8>>>(db: &mut InMemoryBackend<C>) where
+        C::Intermediate: Eq + Hash + Ord + core::fmt::Debug,
+    {
+        let mut tree = OwnedSparseMerkleTree::create(db).unwrap();
+        for i in 0..10 {
+            tree.insert(db, &key(i), value(i)).unwrap();
+        }
+        let root = tree.root();
+
+        let proof = tree.prove(db, &key(4)).unwrap();
+        assert!(proof.verify(&root, &key(4), Some(value(4))));
+        assert!(!proof.verify(&root, &key(4), Some(value(5))));
+        assert!(!proof.verify(&root, &key(4), None));
+
+        let proof = tree.prove(db, &key(42)).unwrap();
+        assert!(proof.verify(&root, &key(42), None));
+        assert!(!proof.verify(&root, &key(42), Some(value(42))));
+
+        let compressed = proof.compress();
+        assert!(compressed.siblings.len() < 16);
+        assert_eq!(compressed.decompress(), Some(proof.clone()));
+
+        let mut malformed = compressed.clone();
+        malformed.siblings.pop();
+        assert_eq!(malformed.decompress(), None);
+    }
+
+    #[test]
+    fn test_proofs() {
+        check_proofs(&mut InheritedInMemory::default());
+        check_proofs(&mut UnitInMemory::default());
+    }
+}
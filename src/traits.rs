@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 /// Value in a merkle tree.
 #[derive(Clone, Eq, PartialEq, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -45,12 +47,27 @@ pub trait Construct: Sized {
 
     /// Get the intermediate value of given left and right child.
     fn intermediate_of(left: &ValueOf<Self>, right: &ValueOf<Self>) -> Self::Intermediate;
+    /// Get intermediate values of many pairs of children. Implementations
+    /// can override this to hash the pairs in parallel lanes.
+    fn intermediate_of_batch(pairs: &[(ValueOf<Self>, ValueOf<Self>)]) -> Vec<Self::Intermediate> {
+        pairs.iter().map(|(left, right)| Self::intermediate_of(left, right)).collect()
+    }
     /// Get or create the empty value given a backend. `empty_at(0)`
     /// should always equal to `Value::End(Default::default())`.
     fn empty_at<DB: WriteBackend<Construct=Self>>(
         db: &mut DB,
         depth_to_bottom: usize
     ) -> Result<ValueOf<Self>, DB::Error>;
+    /// Empty value at given depth to bottom, if it is known without a
+    /// backend. Inherited constructs take it from their shared empty table.
+    fn empty_value(_depth_to_bottom: usize) -> Option<ValueOf<Self>> {
+        None
+    }
+    /// Depth to bottom of the key if it is an inherited empty value in the
+    /// shared empty table of the construct.
+    fn empty_depth(_key: &Self::Intermediate) -> Option<usize> {
+        None
+    }
 }
 
 /// Represents a basic merkle tree with a known root.
@@ -111,6 +128,10 @@ pub enum Error<DBError> {
     AccessOverflowed,
     /// Parameters are invalid.
     InvalidParameter,
+    /// The tree does not canonically encode a value.
+    NonCanonical,
+    /// Decoding exceeded a resource limit of the backend.
+    LimitExceeded,
     /// Backend database error.
     Backend(DBError),
 }
@@ -136,6 +157,31 @@ pub trait ReadBackend: Backend {
         &mut self,
         key: &<Self::Construct as Construct>::Intermediate,
     ) -> Result<(ValueOf<Self::Construct>, ValueOf<Self::Construct>), Self::Error>;
+    /// Whether the backend has an internal item of the key. Backends that
+    /// can tell a missing item apart from other errors should override
+    /// this; by default the item is fetched and any error is returned.
+    fn contains(
+        &mut self,
+        key: &<Self::Construct as Construct>::Intermediate,
+    ) -> Result<bool, Self::Error> {
+        self.get(key).map(|_| true)
+    }
+    /// Whether values decoded from this backend must be canonically
+    /// encoded. Strict by default.
+    fn strict_decoding(&self) -> bool {
+        true
+    }
+    /// Check a sequence length read from the tree, before any of its
+    /// elements is fetched. Unlimited by default.
+    fn check_decode_len(&mut self, _len: usize) -> Result<(), Error<Self::Error>> {
+        Ok(())
+    }
+    /// Enter decoding of a nested value. Unlimited by default.
+    fn enter_decode(&mut self) -> Result<(), Error<Self::Error>> {
+        Ok(())
+    }
+    /// Leave decoding of a nested value.
+    fn leave_decode(&mut self) { }
 }
 
 /// Write backend.
@@ -156,6 +202,11 @@ pub trait WriteBackend: ReadBackend {
         key: <Self::Construct as Construct>::Intermediate,
         value: (ValueOf<Self::Construct>, ValueOf<Self::Construct>)
     ) -> Result<(), Self::Error>;
+    /// Maximum depth to bottom of inherited empty values that the backend
+    /// recognises virtually, so that they need not be inserted.
+    fn virtual_empty_depth(&self) -> usize {
+        0
+    }
 }
 
 /// Leakable value, whose default behavior of drop is to leak.
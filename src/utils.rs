@@ -1,7 +1,7 @@
 //! Utilities
 
 use crate::{Construct, WriteBackend, ValueOf, Error, Value};
-use alloc::collections::VecDeque;
+use alloc::vec::Vec;
 use generic_array::ArrayLength;
 
 /// Required depth of given length.
@@ -18,31 +18,141 @@ pub fn required_depth(len: usize) -> usize {
 /// Serialize a vector at given depth.
 pub fn vector_tree<DB: WriteBackend>(values: &[ValueOf<DB::Construct>], db: &mut DB, max_len: Option<usize>) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> {
     let total_depth = required_depth(max_len.unwrap_or(values.len()));
+    vector_tree_at(values, db, total_depth, 0)
+}
 
-    let mut current = values.iter().cloned().collect::<VecDeque<_>>();
-    let mut next = VecDeque::new();
+/// Serialize a vector of `total_depth`, whose values are subtrees of
+/// `base_depth` to bottom.
+fn vector_tree_at<DB: WriteBackend>(values: &[ValueOf<DB::Construct>], db: &mut DB, total_depth: usize, base_depth: usize) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> {
+    let mut current = values.to_vec();
     for depth in (1..(total_depth + 1)).rev() {
-        let depth_to_bottom = total_depth - depth;
-        while !current.is_empty() {
-            let left = current.pop_front().unwrap_or(<DB::Construct as Construct>::empty_at(db, depth_to_bottom)?);
-            let right = current.pop_front().unwrap_or(<DB::Construct as Construct>::empty_at(db, depth_to_bottom)?);
+        let depth_to_bottom = base_depth + total_depth - depth;
+        let empty = if current.len() % 2 == 1 {
+            Some(<DB::Construct as Construct>::empty_at(db, depth_to_bottom)?)
+        } else {
+            None
+        };
 
-            let key = <DB::Construct as Construct>::intermediate_of(&left, &right);
+        let pairs = current.chunks(2).map(|chunk| {
+            let left = chunk[0].clone();
+            let right = chunk.get(1).cloned().or_else(|| empty.clone())
+                .expect("Empty value is set when there is an odd number of values; qed");
+            (left, right)
+        }).collect::<Vec<_>>();
+        let keys = <DB::Construct as Construct>::intermediate_of_batch(&pairs);
 
-            db.insert(key.clone(), (left, right))?;
-            next.push_back(Value::Intermediate(key));
+        current = Vec::with_capacity(pairs.len());
+        for (key, pair) in keys.into_iter().zip(pairs) {
+            db.insert(key.clone(), pair)?;
+            current.push(Value::Intermediate(key));
         }
-        current = next;
-        next = VecDeque::new();
     }
 
     if current.is_empty() {
-        Ok(<DB::Construct as Construct>::empty_at(db, total_depth)?)
+        Ok(<DB::Construct as Construct>::empty_at(db, base_depth + total_depth)?)
     } else {
         Ok(current[0].clone())
     }
 }
 
+/// Number of values in each subtree hashed by one task of
+/// `vector_tree_parallel`.
+#[cfg(feature = "parallel")]
+pub const PARALLEL_SUBTREE_LEN: usize = 1 << 10;
+
+/// Backend collecting inserts of one subtree in `vector_tree_parallel`.
+#[cfg(feature = "parallel")]
+struct CollectBackend<C: Construct>(
+    Vec<(C::Intermediate, (ValueOf<C>, ValueOf<C>))>,
+    usize,
+);
+
+#[cfg(feature = "parallel")]
+impl<C: Construct> crate::Backend for CollectBackend<C> {
+    type Construct = C;
+    type Error = ();
+}
+
+#[cfg(feature = "parallel")]
+impl<C: Construct> crate::ReadBackend for CollectBackend<C> {
+    fn get(&mut self, _key: &C::Intermediate) -> Result<(ValueOf<C>, ValueOf<C>), ()> {
+        Err(())
+    }
+
+    fn contains(&mut self, _key: &C::Intermediate) -> Result<bool, ()> {
+        Ok(false)
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<C: Construct> WriteBackend for CollectBackend<C> {
+    fn rootify(&mut self, _key: &C::Intermediate) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn unrootify(&mut self, _key: &C::Intermediate) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn insert(&mut self, key: C::Intermediate, value: (ValueOf<C>, ValueOf<C>)) -> Result<(), ()> {
+        self.0.push((key, value));
+        Ok(())
+    }
+
+    fn virtual_empty_depth(&self) -> usize {
+        self.1
+    }
+}
+
+/// Serialize a vector at given depth, hashing subtrees of
+/// `PARALLEL_SUBTREE_LEN` values on the rayon thread pool. The root is the
+/// same as `vector_tree`. Nodes are inserted subtree by subtree in order,
+/// followed by the nodes above them.
+///
+/// Empty values must be computable without reading the backend.
+#[cfg(feature = "parallel")]
+pub fn vector_tree_parallel<DB: WriteBackend>(values: &[ValueOf<DB::Construct>], db: &mut DB, max_len: Option<usize>) -> Result<ValueOf<DB::Construct>, Error<DB::Error>> where
+    <DB::Construct as Construct>::Intermediate: Eq + core::hash::Hash + Ord + Send + Sync,
+    <DB::Construct as Construct>::End: Send + Sync,
+{
+    use rayon::prelude::*;
+
+    let total_depth = required_depth(max_len.unwrap_or(values.len()));
+    let subtree_depth = required_depth(PARALLEL_SUBTREE_LEN);
+    if values.len() <= PARALLEL_SUBTREE_LEN || total_depth <= subtree_depth {
+        return vector_tree(values, db, max_len)
+    }
+
+    let virtual_empty_depth = db.virtual_empty_depth();
+    let subtrees = values.par_chunks(PARALLEL_SUBTREE_LEN).map(|chunk| {
+        let mut collect = CollectBackend::<DB::Construct>(Vec::new(), virtual_empty_depth);
+        let root = vector_tree_at(chunk, &mut collect, subtree_depth, 0);
+        root.map(|root| (root, collect.0))
+    }).collect::<Result<Vec<_>, _>>().map_err(|err| match err {
+        Error::CorruptedDatabase => Error::CorruptedDatabase,
+        Error::AccessOverflowed => Error::AccessOverflowed,
+        Error::InvalidParameter => Error::InvalidParameter,
+        Error::NonCanonical => Error::NonCanonical,
+        Error::LimitExceeded => Error::LimitExceeded,
+        // The collecting backend cannot read, so an empty node missing
+        // from the table is reported as a corrupted database.
+        Error::Backend(()) => Error::CorruptedDatabase,
+    })?;
+
+    // Subtrees are collected separately, so make sure empty values are
+    // available in the backend as well.
+    <DB::Construct as Construct>::empty_at(db, subtree_depth)?;
+    let mut roots = Vec::with_capacity(subtrees.len());
+    for (root, inserts) in subtrees {
+        for (key, value) in inserts {
+            db.insert(key, value)?;
+        }
+        roots.push(root);
+    }
+
+    vector_tree_at(&roots, db, total_depth - subtree_depth, subtree_depth)
+}
+
 /// Get the host len of a packed vector.
 pub fn host_len<Host: ArrayLength<u8>, Value: ArrayLength<u8>>(value_len: usize) -> usize {
     let host_array_len = Host::to_usize();
@@ -55,3 +165,31 @@ pub fn host_len<Host: ArrayLength<u8>, Value: ArrayLength<u8>>(value_len: usize)
         bytes / host_array_len + 1
     }
 }
+
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+    use super::*;
+    use crate::InMemoryBackend;
+    use sha2::Sha256;
+
+    type InMemory = InMemoryBackend<crate::InheritedDigestConstruct<Sha256, Vec<u8>>>;
+
+    #[test]
+    fn test_parallel_same_as_sequential() {
+        for len in &[0, 1, 1024, 1025, 3000, 4096, 5000] {
+            for max_len in &[None, Some(8192)] {
+                let values = (0..*len)
+                    .map(|i: usize| Value::End(i.to_le_bytes().to_vec()))
+                    .collect::<Vec<_>>();
+
+                let mut sequential_db = InMemory::default();
+                let sequential = vector_tree(&values, &mut sequential_db, *max_len).unwrap();
+                let mut parallel_db = InMemory::default();
+                let parallel = vector_tree_parallel(&values, &mut parallel_db, *max_len).unwrap();
+
+                assert_eq!(sequential, parallel);
+                assert_eq!(sequential_db.as_ref(), parallel_db.as_ref());
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use bm::{Construct, Value, ValueOf, Tree, Sequence, Index, InMemoryBackend,
+         OwnedRaw, OwnedVector, OwnedList, OwnedPackedVector, OwnedPackedList,
+         OwnedMerkleMap, OwnedSparseMerkleTree, OwnedMmr, IncrementalTree};
+use bm::utils::vector_tree;
+use generic_array::GenericArray;
+use typenum::{U2, U32};
+use sha2::{Sha256, Digest};
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+struct VecValue([u8; 32]);
+
+impl AsRef<[u8]> for VecValue {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+impl From<usize> for VecValue {
+    fn from(value: usize) -> Self {
+        let mut bytes = [0u8; 32];
+        bytes[0..8].copy_from_slice(&(value as u64).to_le_bytes()[..]);
+        VecValue(bytes)
+    }
+}
+
+impl From<VecValue> for usize {
+    fn from(value: VecValue) -> usize {
+        let mut raw = [0u8; 8];
+        raw.copy_from_slice(&value.0[0..8]);
+        u64::from_le_bytes(raw) as usize
+    }
+}
+
+impl From<GenericArray<u8, U32>> for VecValue {
+    fn from(array: GenericArray<u8, U32>) -> Self {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&array[..]);
+        VecValue(bytes)
+    }
+}
+
+impl From<VecValue> for GenericArray<u8, U32> {
+    fn from(value: VecValue) -> Self {
+        GenericArray::clone_from_slice(&value.0[..])
+    }
+}
+
+type Domain = bm::DomainDigestConstruct<Sha256, VecValue>;
+type InMemory = InMemoryBackend<Domain>;
+
+fn end(value: usize) -> ValueOf<Domain> {
+    Value::End(value.into())
+}
+
+#[test]
+fn rfc6962_node_hash() {
+    type Bytes = bm::DomainDigestConstruct<Sha256, Vec<u8>>;
+
+    let leaf = |data: &[u8]| {
+        let mut digest = Sha256::new();
+        digest.input([0x00]);
+        digest.input(data);
+        digest.result()
+    };
+    let mut digest = Sha256::new();
+    digest.input([0x01]);
+    digest.input(&leaf(b"a")[..]);
+    digest.input(&leaf(b"b")[..]);
+
+    assert_eq!(Bytes::intermediate_of(&Value::End(b"a".to_vec()), &Value::End(b"b".to_vec())),
+               digest.result());
+}
+
+#[test]
+fn leaf_node_separation() {
+    let node = Domain::intermediate_of(&end(1), &end(2));
+    let mut forged = [0u8; 32];
+    forged.copy_from_slice(&node[..]);
+
+    assert_ne!(Domain::intermediate_of(&Value::Intermediate(node), &end(3)),
+               Domain::intermediate_of(&Value::End(VecValue(forged)), &end(3)));
+}
+
+#[test]
+fn raw_vector_list() {
+    let mut db = InMemory::default();
+
+    let mut raw = OwnedRaw::<Domain>::default();
+    raw.set(&mut db, Index::from_one(5).unwrap(), end(5)).unwrap();
+    assert_eq!(raw.get(&mut db, Index::from_one(5).unwrap()).unwrap(), Some(end(5)));
+
+    let mut vector = OwnedVector::<Domain>::create(&mut db, 0, None).unwrap();
+    let mut list = OwnedList::<Domain>::create(&mut db, None).unwrap();
+    let mut values = Vec::new();
+    for i in 0..20 {
+        vector.push(&mut db, end(i)).unwrap();
+        list.push(&mut db, end(i)).unwrap();
+        values.push(end(i));
+    }
+    assert_eq!(vector.root(), vector_tree(&values, &mut db, None).unwrap());
+    assert_eq!(list.get(&mut db, 7).unwrap(), end(7));
+    while list.len() > 0 {
+        list.pop(&mut db).unwrap();
+    }
+
+    raw.drop(&mut db).unwrap();
+    vector.drop(&mut db).unwrap();
+    list.drop(&mut db).unwrap();
+}
+
+#[test]
+fn packed() {
+    let mut db = InMemory::default();
+    let mut vector = OwnedPackedVector::<Domain, GenericArray<u8, U2>, U32, U2>::create(&mut db, 0, None).unwrap();
+    let mut list = OwnedPackedList::<Domain, GenericArray<u8, U2>, U32, U2>::create(&mut db, None).unwrap();
+
+    for i in 0..40u8 {
+        vector.push(&mut db, GenericArray::clone_from_slice(&[i, i])).unwrap();
+        list.push(&mut db, GenericArray::clone_from_slice(&[i, i])).unwrap();
+    }
+    for i in 0..40u8 {
+        assert_eq!(vector.get(&mut db, i as usize).unwrap()[..], [i, i][..]);
+        assert_eq!(list.get(&mut db, i as usize).unwrap()[..], [i, i][..]);
+    }
+
+    vector.drop(&mut db).unwrap();
+    list.drop(&mut db).unwrap();
+}
+
+#[test]
+fn map_sparse_mmr_incremental() {
+    let mut db = InMemory::default();
+
+    let mut map = OwnedMerkleMap::<Domain, VecValue, VecValue>::create(&mut db, None).unwrap();
+    for i in (0..10).rev() {
+        map.insert(&mut db, (i * 2).into(), i.into()).unwrap();
+    }
+    assert_eq!(map.get(&mut db, &8.into()).unwrap(), Some(4.into()));
+    assert_eq!(map.get(&mut db, &9.into()).unwrap(), None);
+
+    let mut sparse = OwnedSparseMerkleTree::<Domain>::create(&mut db).unwrap();
+    sparse.insert(&mut db, &[1u8; 32], 1.into()).unwrap();
+    let proof = sparse.prove(&mut db, &[2u8; 32]).unwrap();
+    assert!(proof.verify(&sparse.root(), &[2u8; 32], None));
+
+    let mut mmr = OwnedMmr::<Domain>::create(&mut db).unwrap();
+    for i in 0..11 {
+        mmr.push(&mut db, end(i)).unwrap();
+    }
+    let proof = mmr.prove(&mut db, 3, 11).unwrap();
+    assert!(proof.verify(&mmr.root(), end(3)));
+
+    let mut incremental = IncrementalTree::<Domain>::new(4).unwrap();
+    let mut values = Vec::new();
+    for i in 0..11 {
+        incremental.push(end(i)).unwrap();
+        values.push(end(i));
+    }
+    assert_eq!(incremental.items_root(), vector_tree(&values, &mut db, Some(16)).unwrap());
+
+    map.drop(&mut db).unwrap();
+    sparse.drop(&mut db).unwrap();
+    mmr.drop(&mut db).unwrap();
+}